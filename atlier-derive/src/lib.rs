@@ -0,0 +1,245 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse_macro_input;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Fields;
+use syn::Ident;
+use syn::LitFloat;
+use syn::LitInt;
+use syn::LitStr;
+
+/// Derives `atlier::Attributes` for a struct with named fields
+///
+/// Field options,
+/// - `#[attributes(label = "..")]` label shown in the editor, defaults to the field name
+/// - `#[attributes(min = .., max = ..)]` shows a slider for int/float fields
+/// - `#[attributes(read_only)]` shows the field without allowing changes
+/// - `#[attributes(hidden)]` the field is still mapped to an attribute, but not shown
+///
+/// Struct options,
+/// - `#[attributes(app)]` also implements `atlier::App` with the generated editors
+#[proc_macro_derive(Attributes, attributes(attributes))]
+pub fn derive_attributes(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Field {
+    ident: Ident,
+    label: String,
+    range: Option<(f32, f32)>,
+    read_only: bool,
+    hidden: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => named,
+            _ => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "Attributes can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "Attributes can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut app = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("attributes"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("app") {
+                app = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported attributes option, expected `app`"))
+            }
+        })?;
+    }
+
+    let mut fields = vec![];
+    for field in named.named.iter() {
+        let ident = field.ident.clone().expect("named fields have an ident");
+        let mut parsed = Field {
+            label: ident.to_string(),
+            ident,
+            range: None,
+            read_only: false,
+            hidden: false,
+        };
+        let (mut min, mut max) = (None, None);
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("attributes"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("label") {
+                    parsed.label = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("min") {
+                    min = Some(parse_number(&meta)?);
+                } else if meta.path.is_ident("max") {
+                    max = Some(parse_number(&meta)?);
+                } else if meta.path.is_ident("read_only") {
+                    parsed.read_only = true;
+                } else if meta.path.is_ident("hidden") {
+                    parsed.hidden = true;
+                } else {
+                    return Err(meta.error(
+                        "unsupported attributes option, expected one of `label`, `min`, `max`, `read_only`, `hidden`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+
+        parsed.range = match (min, max) {
+            (Some(min), Some(max)) => Some((min, max)),
+            (None, None) => None,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &parsed.ident,
+                    "`min` and `max` must be set together",
+                ))
+            }
+        };
+
+        fields.push(parsed);
+    }
+
+    let to_attributes = fields.iter().map(|Field { ident, .. }| {
+        let attribute_name = ident.to_string();
+        quote! {
+            ::atlier::system::Attribute::new(
+                id,
+                #attribute_name,
+                ::atlier::system::Value::from(self.#ident.clone()),
+            )
+        }
+    });
+
+    let from_attributes = fields.iter().map(|Field { ident, .. }| {
+        let attribute_name = ident.to_string();
+        quote! {
+            #ident: ::std::convert::TryFrom::try_from(
+                ::atlier::system::find_field(attributes, #attribute_name)?,
+            )?
+        }
+    });
+
+    let options = |field: &Field| {
+        let label = &field.label;
+        let read_only = field.read_only;
+        let range = match field.range {
+            Some((min, max)) => quote! { Some((#min, #max)) },
+            None => quote! { None },
+        };
+        quote! {
+            &::atlier::system::FieldOptions {
+                label: #label,
+                range: #range,
+                read_only: #read_only,
+            }
+        }
+    };
+
+    let edit_fields = fields.iter().filter(|f| !f.hidden).map(|field| {
+        let ident = &field.ident;
+        let options = options(field);
+        quote! {
+            ::atlier::system::edit_field(ui, #options, &mut self.#ident);
+        }
+    });
+
+    let display_fields = fields.iter().filter(|f| !f.hidden).map(|field| {
+        let ident = &field.ident;
+        let options = options(field);
+        quote! {
+            ::atlier::system::display_field(ui, #options, &self.#ident);
+        }
+    });
+
+    let app_impl = if app {
+        let app_name = name.to_string();
+        quote! {
+            impl #impl_generics ::atlier::system::App for #name #ty_generics #where_clause {
+                fn name() -> &'static str {
+                    #app_name
+                }
+
+                fn edit_ui(&mut self, ui: &::atlier::imgui::Ui) {
+                    ::atlier::system::Attributes::edit_fields(self, ui);
+                }
+
+                fn display_ui(&self, ui: &::atlier::imgui::Ui) {
+                    ::atlier::system::Attributes::display_fields(self, ui);
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::atlier::system::Attributes for #name #ty_generics #where_clause {
+            fn to_attributes(&self, id: u32) -> ::std::vec::Vec<::atlier::system::Attribute> {
+                vec![#(#to_attributes),*]
+            }
+
+            fn from_attributes(
+                attributes: &[::atlier::system::Attribute],
+            ) -> ::std::result::Result<Self, ::atlier::system::ConversionError> {
+                Ok(Self {
+                    #(#from_attributes),*
+                })
+            }
+
+            fn edit_fields(&mut self, ui: &::atlier::imgui::Ui) {
+                #(#edit_fields)*
+            }
+
+            fn display_fields(&self, ui: &::atlier::imgui::Ui) {
+                #(#display_fields)*
+            }
+        }
+
+        #app_impl
+    })
+}
+
+/// parses an int or float literal as f32
+fn parse_number(meta: &syn::meta::ParseNestedMeta) -> syn::Result<f32> {
+    let value = meta.value()?;
+    let negative = value.peek(syn::Token![-]);
+    if negative {
+        value.parse::<syn::Token![-]>()?;
+    }
+
+    let number = if value.peek(LitInt) {
+        value.parse::<LitInt>()?.base10_parse::<f32>()?
+    } else {
+        value.parse::<LitFloat>()?.base10_parse::<f32>()?
+    };
+
+    Ok(if negative { -number } else { number })
+}
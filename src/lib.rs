@@ -1,2 +1,4 @@
 pub mod system;
 pub mod prelude;
+
+pub use imgui;
@@ -1,16 +1,44 @@
+mod attributes;
+mod changeset;
+mod code_editor;
+mod conflict;
+mod convert;
+mod derived;
+mod events;
+mod expr;
 mod font;
+mod grammar;
 mod gui;
+mod hex_editor;
+mod history;
+mod image_viewer;
+mod index;
+mod json_schema;
+mod metadata;
+mod patch;
+mod persist;
+mod preview;
+mod project;
+mod query;
+mod recovery;
+mod scheme;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod sync;
+mod text_editor;
+mod timeline;
+mod watch;
+mod widget_cache;
 mod window;
 
 use imgui::FontSource;
-use imgui::Key;
-use imgui::MouseButton;
 use imgui::Ui;
 use imgui_wgpu::Renderer;
 use imgui_wgpu::RendererConfig;
 use serde::Deserialize;
 use serde::Serialize;
 use specs::storage::DenseVecStorage;
+use specs::storage::FlaggedStorage;
 use specs::Builder;
 use specs::Component;
 use specs::DispatcherBuilder;
@@ -21,10 +49,8 @@ use std::any::Any;
 use std::cmp::Ordering;
 use std::collections::hash_map::DefaultHasher;
 use std::fmt::Display;
-use std::fs;
 use std::hash::Hash;
 use std::hash::Hasher;
-use std::str::from_utf8;
 use wgpu::util::StagingBelt;
 use wgpu::TextureView;
 use window::Hardware;
@@ -33,9 +59,117 @@ use winit::event::DeviceEvent;
 use winit::event::DeviceId;
 use winit::event_loop::ControlFlow;
 
+pub use atlier_derive::Attributes;
+pub use attributes::display_field;
+pub use attributes::edit_field;
+pub use attributes::find_field;
+pub use attributes::Attributes;
+pub use attributes::FieldOptions;
+pub use changeset::AtomicCommit;
+pub use changeset::Changeset;
+pub use changeset::ChangesetError;
+pub use changeset::Changesets;
+pub use code_editor::CodeEditor;
+pub use conflict::diff_lines;
+pub use conflict::Change;
+pub use conflict::ConflictDetection;
+pub use conflict::MergeBases;
+pub use convert::ConversionError;
+pub use derived::Derivations;
+pub use derived::Derived;
+pub use events::AttributeEvent;
+pub use events::AttributeEventKind;
+pub use events::AttributeEvents;
+pub use events::AttributeFilter;
+pub use events::ChangeDetection;
+pub use events::Subscription;
+pub use expr::BinaryOp;
+pub use expr::Expr;
+pub use expr::ExprError;
+pub use expr::UnaryOp;
+pub use grammar::grammars;
+pub use grammar::register_grammar;
+pub use grammar::Diagnostic;
+pub use grammar::DiagnosticsProvider;
+pub use grammar::Grammar;
+pub use grammar::GrammarSpan;
+pub use grammar::Grammars;
+pub use grammar::JsonDiagnostics;
+pub use grammar::Severity;
+pub use grammar::Token;
+pub use grammar::TokenKind;
+pub use gui::CloseResponse;
 pub use gui::ControlState;
 pub use gui::GUIUpdate;
 pub use gui::GUI;
+pub use hex_editor::HexEditor;
+pub use history::History;
+pub use history::HistoryPanel;
+pub use history::Operation;
+pub use history::Transaction;
+pub use image_viewer::ImageFormat;
+pub use image_viewer::ImageTextures;
+pub use image_viewer::ImageViewer;
+pub use index::AttributeIndex;
+pub use index::Indexing;
+pub use json_schema::JsonSchemaError;
+pub use json_schema::JSON_SCHEMA_DRAFT;
+pub use metadata::Metadata;
+pub use metadata::Pattern;
+pub use metadata::Schema;
+pub use metadata::ValidationError;
+pub use patch::snapshot;
+pub use patch::AttributeChange;
+pub use patch::Patch;
+pub use patch::PatchError;
+pub use persist::load_from_file;
+pub use persist::load_world;
+pub use persist::save_to_file;
+pub use persist::save_world;
+pub use persist::Encoding;
+pub use persist::PersistError;
+pub use persist::Persisted;
+pub use persist::PersistedMarker;
+pub use persist::PersistedMarkerAllocator;
+pub use persist::Persistence;
+pub use persist::PROJECT_FORMAT;
+pub use persist::PROJECT_FORMAT_VERSION;
+pub use persist::PROJECT_MAGIC;
+pub use project::ProjectFile;
+pub use project::UnsavedChanges;
+pub use query::AttributeQuery;
+pub use query::NameFilter;
+pub use query::QueryError;
+pub use query::SearchPanel;
+pub use recovery::recovery_path;
+pub use recovery::Autosave;
+pub use recovery::Recovery;
+pub use scheme::register_scheme;
+pub use scheme::schemes;
+pub use scheme::CommandScheme;
+pub use scheme::DirScheme;
+pub use scheme::EnvOverrides;
+pub use scheme::EnvScheme;
+pub use scheme::Environment;
+pub use scheme::FileScheme;
+pub use scheme::SchemeError;
+pub use scheme::SchemeHandler;
+pub use scheme::Schemes;
+#[cfg(feature = "scripting")]
+pub use scripting::Scripting;
+pub use sync::AttributeSync;
+pub use sync::Stamp;
+pub use sync::SyncUpdate;
+pub use text_editor::LineEnding;
+pub use text_editor::TextEditor;
+pub use text_editor::TextEncoding;
+pub use timeline::Snapshot;
+pub use timeline::Snapshots;
+pub use timeline::Timeline;
+pub use watch::ChangedOnDisk;
+pub use watch::FileState;
+pub use watch::FileWatcher;
+pub use watch::WatchedFiles;
 pub use winit::event::WindowEvent;
 
 pub use font::cascadia_code;
@@ -73,11 +207,17 @@ where
     }
 
     /// Enable depth stencil
-    fn enable_depth_stencil<'a>(&self) -> bool {
+    fn enable_depth_stencil(&self) -> bool {
         false
     }
 
+    /// Called when the window is asked to close, return `CloseResponse::Deny` to keep the window open
+    fn on_close_requested(&mut self) -> CloseResponse {
+        CloseResponse::Allow
+    }
+
     /// Called when a new frame is ready to be rendered
+    #[allow(clippy::too_many_arguments)]
     fn on_render<'a>(
         &'a mut self,
         _view: &wgpu::TextureView,
@@ -125,6 +265,14 @@ pub trait Extension {
     /// on_maintain is called after `.maintain()` is called on the world
     fn on_maintain(&'_ mut self, _app_world: &mut World) {}
 
+    /// on_close_requested is called when the window is asked to close,
+    /// return `CloseResponse::Deny` to keep the window open
+    ///
+    /// To close the window later, set `ControlState::control_flow` in the app world to `ControlFlow::Exit`
+    fn on_close_requested(&'_ mut self, _app_world: &World) -> CloseResponse {
+        CloseResponse::Allow
+    }
+
     /// on_render_init is called when the renderer pipeline is being setup
     fn on_render_init(
         &'_ mut self,
@@ -137,6 +285,7 @@ pub trait Extension {
     }
 
     /// on_render for extensions relies on the encoder/staging_belt
+    #[allow(clippy::too_many_arguments)]
     fn on_render(
         &'_ mut self,
         _view: &wgpu::TextureView,
@@ -236,6 +385,19 @@ where
         b.on_maintain(app_world);
     }
 
+    fn on_close_requested(&'_ mut self, app_world: &World) -> CloseResponse {
+        let (a, b) = self;
+
+        // both extensions are asked, so that each has a chance to prompt the user
+        match (
+            a.on_close_requested(app_world),
+            b.on_close_requested(app_world),
+        ) {
+            (CloseResponse::Allow, CloseResponse::Allow) => CloseResponse::Allow,
+            _ => CloseResponse::Deny,
+        }
+    }
+
     fn on_render_init(
         &'_ mut self,
         surface: &wgpu::Surface,
@@ -250,6 +412,7 @@ where
         b.on_render_init(surface, config, adapter, device, queue);
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn on_render(
         &'_ mut self,
         view: &wgpu::TextureView,
@@ -290,8 +453,15 @@ where
 }
 
 /// An attribute is the main "framing" resource
-#[derive(Clone, Default, Debug, Component, Serialize, Deserialize, Hash)]
-#[storage(DenseVecStorage)]
+///
+/// Attributes are ordered by (id, name, value, transient), which makes them safe to
+/// store in ordered sets and to deduplicate.
+///
+/// Attributes are stored in flagged storage, see `AttributeEvents` to subscribe to changes.
+#[derive(
+    Clone, Default, Debug, Component, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[storage(FlaggedStorage)]
 pub struct Attribute {
     pub id: u32,
     pub name: String,
@@ -300,39 +470,6 @@ pub struct Attribute {
     pub transient: Option<(String, Value)>,
 }
 
-impl Ord for Attribute {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (self.id, &self.name, &self.value, &self.transient).cmp(&(
-            other.id,
-            &other.name,
-            &other.value,
-            &self.transient,
-        ))
-    }
-}
-
-impl Eq for Attribute {}
-
-impl PartialEq for Attribute {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-            && self.name == other.name
-            && self.value == other.value
-            && self.transient == other.transient
-    }
-}
-
-impl PartialOrd for Attribute {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        (self.id, &self.name, &self.value, &self.transient).partial_cmp(&(
-            other.id,
-            &other.name,
-            &other.value,
-            &self.transient,
-        ))
-    }
-}
-
 impl Display for Attribute {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#010x}::", self.id)?;
@@ -342,9 +479,9 @@ impl Display for Attribute {
     }
 }
 
-impl Into<(String, Value)> for &mut Attribute {
-    fn into(self) -> (String, Value) {
-        (self.name().to_string(), self.value().clone())
+impl From<&mut Attribute> for (String, Value) {
+    fn from(attribute: &mut Attribute) -> Self {
+        (attribute.name().to_string(), attribute.value().clone())
     }
 }
 
@@ -373,14 +510,50 @@ impl Attribute {
         self.transient.take()
     }
 
-    pub fn commit(&mut self) {
-        if let Some((name, value)) = &self.transient {
-            self.name = name.clone();
-            self.value = value.clone();
-            self.transient = None;
+    /// Commits the pending changes of this attribute without validating them,
+    /// returns the previous name and value if there were pending changes
+    #[deprecated(
+        note = "use `try_commit` or `Schema::commit` to validate the pending value, or `commit_unchecked`"
+    )]
+    pub fn commit(&mut self) -> Option<(String, Value)> {
+        self.commit_unchecked()
+    }
+
+    /// Commits the pending changes of this attribute without validating them against any metadata,
+    /// returns the previous name and value if there were pending changes
+    ///
+    /// This overwrites the stable value even if it has a conflict, see `MergeBases`
+    pub fn commit_unchecked(&mut self) -> Option<(String, Value)> {
+        self.transient.take().map(|(name, value)| {
+            (
+                std::mem::replace(&mut self.name, name),
+                std::mem::replace(&mut self.value, value),
+            )
+        })
+    }
+
+    /// Validates the pending value of this attribute against `metadata`,
+    /// if there are no pending changes the current value is validated
+    pub fn validate(&self, metadata: &Metadata) -> Result<(), ValidationError> {
+        match &self.transient {
+            Some(_) if metadata.read_only => Err(ValidationError::ReadOnly),
+            Some((_, value)) => metadata.validate(value),
+            None => metadata.validate(&self.value),
         }
     }
 
+    /// Commits the pending changes of this attribute only if they are valid for `metadata`,
+    /// otherwise the pending changes are kept so that they can be fixed
+    ///
+    /// returns the previous name and value if there were pending changes
+    pub fn try_commit(
+        &mut self,
+        metadata: &Metadata,
+    ) -> Result<Option<(String, Value)>, ValidationError> {
+        self.validate(metadata)?;
+        Ok(self.commit_unchecked())
+    }
+
     pub fn edit_self(&mut self) {
         let init = self.into();
         self.edit(init);
@@ -398,6 +571,7 @@ impl Attribute {
         }
     }
 
+    /// resets the pending changes to the current stable name/value
     pub fn reset_editing(&mut self) {
         if let Some((name, value)) = &mut self.transient {
             *value = self.value.clone();
@@ -405,6 +579,14 @@ impl Attribute {
         }
     }
 
+    /// hash of the stable name/value of this attribute
+    pub fn stable_hash(&self) -> u64 {
+        let state = &mut DefaultHasher::default();
+        self.name.hash(state);
+        self.value.hash(state);
+        state.finish()
+    }
+
     // sets the id/owner of this attribute
     pub fn set_id(&mut self, id: u32) {
         self.id = id;
@@ -425,6 +607,22 @@ impl Attribute {
         &mut self.value
     }
 
+    /// read the current value of this attribute as `T`
+    pub fn get<'a, T>(&'a self) -> Result<T, ConversionError>
+    where
+        T: TryFrom<&'a Value, Error = ConversionError>,
+    {
+        T::try_from(&self.value)
+    }
+
+    /// write `value` to the current value of this attribute, this does not affect the transient value
+    pub fn set<T>(&mut self, value: T)
+    where
+        T: Into<Value>,
+    {
+        self.value = value.into();
+    }
+
     /// read the current id of this attribute
     /// This id is likely the entity owner of this attribute
     pub fn id(&self) -> u32 {
@@ -440,7 +638,21 @@ impl App for Attribute {
     fn display_ui(&self, _: &imgui::Ui) {}
 
     fn edit_ui(&mut self, ui: &imgui::Ui) {
+        self.edit_ui_with(ui, None);
+    }
+}
+
+impl Attribute {
+    /// shows the editor of `edit_ui`, numbers use a slider between min and max and snap to the step,
+    /// and text with choices uses a combo box, when there is metadata
+    fn edit_ui_with(&mut self, ui: &imgui::Ui, metadata: Option<&Metadata>) {
         let label = format!("{} {:#4x}", self.name, self.id);
+        let scheme = schemes().find(&self.name);
+        // the transient value has its own editor state
+        let key = match &self.transient {
+            Some(_) => format!("{} transient", label),
+            None => label.clone(),
+        };
 
         let editing = if let Some((name, e)) = &mut self.transient {
             let name_label = format!("name of {}", label);
@@ -454,124 +666,201 @@ impl App for Attribute {
             &mut self.value
         };
 
+        let range = metadata.and_then(|m| m.min.zip(m.max));
+        let step = metadata.and_then(|m| m.step).filter(|step| *step > 0.0);
+        let choices = metadata.map(|m| m.choices.as_slice()).unwrap_or_default();
+
         ui.set_next_item_width(200.0);
-        match editing {
+        match &mut *editing {
             Value::Empty => {
                 ui.text("empty");
             }
             Value::Float(float) => {
-                ui.input_float(label, float).build();
+                let changed = match range {
+                    Some((min, max)) => imgui::Slider::new(&label, min, max).build(ui, float),
+                    None => ui
+                        .input_float(&label, float)
+                        .step(step.unwrap_or_default())
+                        .build(),
+                };
+                if let Some(metadata) = metadata.filter(|_| changed) {
+                    *float = metadata.snap(*float);
+                }
             }
             Value::Int(int) => {
-                ui.input_int(label, int).build();
+                let changed = match range {
+                    Some((min, max)) => {
+                        imgui::Slider::new(&label, min as i32, max as i32).build(ui, int)
+                    }
+                    None => ui
+                        .input_int(&label, int)
+                        .step(step.map_or(1, |step| (step as i32).max(1)))
+                        .build(),
+                };
+                if let Some(metadata) = metadata.filter(|_| changed) {
+                    *int = metadata.snap(*int as f32).round() as i32;
+                }
             }
             Value::Bool(bool) => {
-                ui.checkbox(label, bool);
+                ui.checkbox(&label, bool);
             }
             Value::FloatRange(f1, f2, f3) => {
                 let clone = &mut [*f1, *f2, *f3];
-                ui.input_float3(label, clone).build();
+                ui.input_float3(&label, clone).build();
                 *f1 = clone[0];
                 *f2 = clone[1];
                 *f3 = clone[2];
             }
             Value::IntRange(i1, i2, i3) => {
                 let clone = &mut [*i1, *i2, *i3];
-                ui.input_int3(label, clone).build();
+                ui.input_int3(&label, clone).build();
                 *i1 = clone[0];
                 *i2 = clone[1];
                 *i3 = clone[2];
             }
+            Value::TextBuffer(text)
+                if scheme.is_none()
+                    && (text.contains('\n') || grammars().for_path(&self.name).is_some()) =>
+            {
+                ui.text(&label);
+                code_editor::text_buffer_ui(&self.name, &key, text, ui);
+            }
+            Value::TextBuffer(text) if !choices.is_empty() => {
+                if let Some(_combo) = ui.begin_combo(&label, text.as_str()) {
+                    for choice in choices {
+                        if imgui::Selectable::new(choice)
+                            .selected(choice == text)
+                            .build(ui)
+                        {
+                            text.clone_from(choice);
+                        }
+                    }
+                }
+            }
             Value::TextBuffer(text) => {
-                ui.input_text(label, text).build();
+                ui.input_text(&label, text).build();
             }
             Value::FloatPair(f1, f2) => {
                 let clone = &mut [*f1, *f2];
-                ui.input_float2(label, clone).build();
+                ui.input_float2(&label, clone).build();
                 *f1 = clone[0];
                 *f2 = clone[1];
             }
             Value::IntPair(i1, i2) => {
                 let clone = &mut [*i1, *i2];
-                ui.input_int2(label, clone).build();
+                ui.input_int2(&label, clone).build();
                 *i1 = clone[0];
                 *i2 = clone[1];
             }
+            Value::BinaryVector(v) if scheme.is_none() && ImageFormat::detect(v).is_some() => {
+                ui.text(&label);
+                image_viewer::image_ui(&key, v, ui);
+            }
+            Value::BinaryVector(v) if scheme.is_none() && !preview::is_text(&key, v, ui) => {
+                ui.text(&label);
+                hex_editor::binary_ui(&key, v, ui);
+            }
             Value::BinaryVector(v) => {
                 ui.label_text("vector length", format!("{}", v.len()));
-
-                if self.name.starts_with("file::") {
-                    if let Some(mut content) = String::from_utf8(v.to_vec()).ok() {
-                        ui.input_text_multiline(
-                            format!("content of {}", self.name),
-                            &mut content,
-                            [800.0, 200.0],
-                        )
-                        .read_only(true)
-                        .build();
-                    }
-
-                    if ui.button(format!("reload {}", label)) {
-                        let name = self.name.to_owned();
-                        let filename = &name[6..];
-                        match fs::read_to_string(filename) {
-                            Ok(string) => {
-                                *v = string.as_bytes().to_vec();
-                            }
-                            Err(err) => {
-                                eprintln!("Could not load file '{}', for attribute labeled '{}', entity {}. Error: {}", &filename, label, self.id, err);
-                            }
-                        }
-                    }
-
-                    if ui.button(format!("write to disk {}", label)) {
-                        let name = self.name.to_owned();
-                        let filename = &name[6..];
-                        match fs::write(filename, v) {
-                            Ok(_) => {
-                                println!("Saved to {}", filename);
-                            }
-                            Err(err) => {
-                                eprintln!("Could not load file '{}', for attribute labeled '{}', entity {}. Error: {}", &filename, label, self.id, err);
-                            }
-                        }
-                    }
-                }
             }
             Value::Reference(r) => {
-                ui.label_text(label, format!("{:#5x}", r));
+                ui.label_text(&label, format!("{:#5x}", r));
             }
             Value::Symbol(symbol) => {
-                ui.label_text(label, symbol);
+                ui.label_text(&label, symbol);
             }
         };
+
+        if let Some((handler, path)) = scheme {
+            handler.edit_ui(path, &label, editing, ui);
+        }
     }
 }
 
 impl Attribute {
     /// helper function to show an editor for the internal state of the attribute
-    pub fn edit_attr(&mut self, ui: &Ui) {
-        if let Some(_) = self.transient {
-            self.edit_ui(ui);
-            if ui.button(format!("save changes [{} {}]", self.name(), self.id)) {
-                self.commit();
+    /// returns the previous name and value if changes were saved
+    ///
+    /// Changes are not validated, use `Schema::edit_attr` to validate them against the `Schema` resource
+    pub fn edit_attr(&mut self, ui: &Ui) -> Option<(String, Value)> {
+        self.edit_attr_with(ui, None)
+    }
+
+    /// helper function to show an editor for the internal state of the attribute,
+    /// when metadata is available, changes are validated before they can be saved
+    ///
+    /// returns the previous name and value if changes were saved
+    pub fn edit_attr_with(
+        &mut self,
+        ui: &Ui,
+        metadata: Option<&Metadata>,
+    ) -> Option<(String, Value)> {
+        self.edit_attr_merging(ui, metadata, |_, _| false)
+    }
+
+    /// shows the editor of `edit_attr_with`, `merge` shows a merge ui when the pending changes
+    /// have a conflict, and returns true while the conflict is not resolved
+    fn edit_attr_merging(
+        &mut self,
+        ui: &Ui,
+        metadata: Option<&Metadata>,
+        merge: impl FnOnce(&mut Attribute, &Ui) -> bool,
+    ) -> Option<(String, Value)> {
+        let read_only = metadata.is_some_and(|m| m.read_only);
+
+        ui.disabled(read_only, || self.edit_ui_with(ui, metadata));
+
+        if let Some(metadata) = metadata {
+            if let Some(description) = &metadata.description {
+                if ui.is_item_hovered() {
+                    ui.tooltip_text(description);
+                }
+            }
+
+            if let Some(units) = &metadata.units {
+                ui.same_line();
+                ui.text_disabled(units);
             }
+        }
+
+        if self.transient.is_some() {
+            let validation = metadata.map_or(Ok(()), |m| self.validate(m));
+
+            if let Err(err) = &validation {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], err.to_string());
+            }
+
+            let conflict = merge(self, ui);
+
+            let mut previous = None;
+            ui.disabled(validation.is_err() || conflict, || {
+                if ui.button(format!("save changes [{} {}]", self.name(), self.id)) {
+                    previous = match metadata {
+                        Some(metadata) => self.try_commit(metadata).unwrap_or_default(),
+                        None => self.commit_unchecked(),
+                    };
+                }
+            });
 
             ui.same_line();
             if ui.button(format!("reset changes [{} {}]", self.name(), self.id)) {
                 self.reset_editing();
             }
+
+            previous
         } else {
-            self.edit_ui(ui);
-            if ui.button(format!("edit [{} {}]", self.name(), self.id)) {
-                self.transient = Some((self.name.clone(), self.value.clone()));
+            if !read_only && ui.button(format!("edit [{} {}]", self.name(), self.id)) {
+                self.edit_self();
             }
+
+            None
         }
     }
 
     /// helper function to show an editor for the internal state of the attribute
     pub fn edit_value(&mut self, with_label: impl AsRef<str>, ui: &Ui) {
-        let mut input_label = format!("{} {:#4x}", self.name, self.id);
+        let key = format!("{} {:#4x}", self.name, self.id);
+        let mut input_label = key.clone();
 
         if !with_label.as_ref().is_empty() {
             input_label = with_label.as_ref().to_string();
@@ -582,19 +871,22 @@ impl Attribute {
                 if let Some((_, value)) = &mut self.transient {
                     ui.text("(transient)");
                     ui.same_line();
-                    value.edit_ui(input_label, ui);
+                    value.edit_ui_with_key(&input_label, &format!("{} transient", key), ui);
                 }
             }
             value => {
-                value.edit_ui(input_label, ui);
+                value.edit_ui_with_key(&input_label, &key, ui);
             }
         };
     }
 }
 
-#[derive(Debug, Clone, Component, Serialize, Deserialize, PartialEq, PartialOrd)]
+/// Values have a total order, variants are ordered by their declaration and then by their content,
+/// floats are compared with `total_cmp` so that NaN and signed zeros have a well-defined position
+#[derive(Debug, Clone, Default, Component, Serialize, Deserialize)]
 #[storage(DenseVecStorage)]
 pub enum Value {
+    #[default]
     Empty,
     Bool(bool),
     TextBuffer(String),
@@ -611,6 +903,13 @@ pub enum Value {
 
 impl Value {
     pub fn edit_ui(&mut self, label: impl AsRef<str>, ui: &imgui::Ui) {
+        let label = label.as_ref();
+        self.edit_ui_with_key(label, label, ui);
+    }
+
+    /// Shows the editor with `label`, the state of binary editors is kept between frames by `key`,
+    /// which must be unique to the value
+    fn edit_ui_with_key(&mut self, label: &str, key: &str, ui: &imgui::Ui) {
         match self {
             Value::Empty => {
                 ui.label_text(label, "empty");
@@ -647,53 +946,12 @@ impl Value {
             }
             Value::BinaryVector(v) => {
                 ui.label_text(label, format!("{} bytes", v.len()));
-                if let Some(text) = from_utf8(v).ok().filter(|s| !s.is_empty()) {
-                    let width = text
-                        .split_once("\n")
-                        .and_then(|(l, ..)| Some(l.len() as f32 * 16.0 + 400.0))
-                        .and_then(|w| Some(w.min(1360.0)))
-                        .unwrap_or(800.0);
-
-                    if ui.is_item_hovered()
-                        && (ui.is_key_down(Key::V) || ui.is_mouse_down(MouseButton::Middle))
-                    {
-                        ui.tooltip(|| {
-                            if !text.is_empty() {
-                                ui.text("Preview - Right+Click to pin/expand");
-                                ui.input_text_multiline(
-                                    "preview-tooltip",
-                                    &mut text.to_string(),
-                                    [width, 35.0 * 16.0],
-                                )
-                                .read_only(true)
-                                .build();
-                            }
-                        });
-                    }
-
-                    if ui.is_item_hovered()
-                        && !ui.is_key_down(Key::V)
-                        && !ui.is_mouse_down(MouseButton::Middle)
-                    {
-                        ui.tooltip_text("Hold+V or Middle+Mouse to peek at content");
-                    }
-
-                    ui.popup(&text, || {
-                        if !text.is_empty() {
-                            ui.text("Preview");
-                            ui.input_text_multiline(
-                                "preview",
-                                &mut text.to_string(),
-                                [1360.0, 35.0 * 16.0],
-                            )
-                            .read_only(true)
-                            .build();
-                        }
-                    });
-
-                    if ui.is_item_clicked_with_button(imgui::MouseButton::Right) {
-                        ui.open_popup(&text);
-                    }
+                if ImageFormat::detect(v).is_some() {
+                    image_viewer::image_ui(key, v, ui);
+                } else if preview::is_text(key, v, ui) {
+                    preview::preview_ui(key, v, ui);
+                } else {
+                    hex_editor::binary_ui(key, v, ui);
                 }
             }
             Value::Reference(r) => {
@@ -706,14 +964,88 @@ impl Value {
     }
 }
 
+/// The kind of a `Value`, without its content
+///
+/// Kinds are declared in the same order as the variants of `Value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ValueKind {
+    Empty,
+    Bool,
+    TextBuffer,
+    Int,
+    IntPair,
+    IntRange,
+    Float,
+    FloatPair,
+    FloatRange,
+    BinaryVector,
+    Reference,
+    Symbol,
+}
+
+impl Display for ValueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Value {
+    /// returns the kind of this value
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Value::Empty => ValueKind::Empty,
+            Value::Bool(_) => ValueKind::Bool,
+            Value::TextBuffer(_) => ValueKind::TextBuffer,
+            Value::Int(_) => ValueKind::Int,
+            Value::IntPair(..) => ValueKind::IntPair,
+            Value::IntRange(..) => ValueKind::IntRange,
+            Value::Float(_) => ValueKind::Float,
+            Value::FloatPair(..) => ValueKind::FloatPair,
+            Value::FloatRange(..) => ValueKind::FloatRange,
+            Value::BinaryVector(_) => ValueKind::BinaryVector,
+            Value::Reference(_) => ValueKind::Reference,
+            Value::Symbol(_) => ValueKind::Symbol,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
 impl Eq for Value {}
 
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Value {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if let Some(ordering) = self.partial_cmp(other) {
-            ordering
-        } else {
-            Ordering::Less
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Empty, Value::Empty) => Ordering::Equal,
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::TextBuffer(a), Value::TextBuffer(b)) => a.cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::IntPair(a1, a2), Value::IntPair(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Value::IntRange(a1, a2, a3), Value::IntRange(b1, b2, b3)) => {
+                (a1, a2, a3).cmp(&(b1, b2, b3))
+            }
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::FloatPair(a1, a2), Value::FloatPair(b1, b2)) => {
+                a1.total_cmp(b1).then_with(|| a2.total_cmp(b2))
+            }
+            (Value::FloatRange(a1, a2, a3), Value::FloatRange(b1, b2, b3)) => a1
+                .total_cmp(b1)
+                .then_with(|| a2.total_cmp(b2))
+                .then_with(|| a3.total_cmp(b3)),
+            (Value::BinaryVector(a), Value::BinaryVector(b)) => a.cmp(b),
+            (Value::Reference(a), Value::Reference(b)) => a.cmp(b),
+            (Value::Symbol(a), Value::Symbol(b)) => a.cmp(b),
+            _ => self.kind().cmp(&other.kind()),
         }
     }
 }
@@ -745,8 +1077,8 @@ impl Display for Value {
 }
 
 impl Value {
-    /// Converts to Value::Reference(), 
-    /// 
+    /// Converts to Value::Reference(),
+    ///
     /// If self is already Value::Reference(), returns self w/o rehashing
     pub fn to_ref(&self) -> Value {
         Value::Reference(match self {
@@ -760,12 +1092,6 @@ impl Value {
     }
 }
 
-impl Default for Value {
-    fn default() -> Self {
-        Value::Empty
-    }
-}
-
 impl Hash for Value {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
@@ -968,10 +1294,14 @@ where
             let renderer = Renderer::new(setup_imgui, &device, &queue, renderer_config);
             let staging_belt = StagingBelt::new(1024);
 
+            let image_textures = ImageTextures::new(&imgui);
+
             let gui = GUI {
                 window_title: title.to_string(),
+                title_dirty: false,
                 imgui,
                 renderer,
+                image_textures,
                 instance,
                 window,
                 physical_size,
@@ -993,10 +1323,10 @@ where
                 app_dispatcher: None,
             };
 
-            return (event_loop, gui);
+            (event_loop, gui)
         } else {
             panic!("Could not initialize hardware")
-        };
+        }
     };
 
     setup()
@@ -1004,7 +1334,7 @@ where
 
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
 
-fn create_depth_texture<'a>(
+fn create_depth_texture(
     device: &wgpu::Device,
     config: &wgpu::SurfaceConfiguration,
     label: &str,
@@ -1024,7 +1354,151 @@ fn create_depth_texture<'a>(
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
     };
     let texture = device.create_texture(&desc);
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Attribute;
+    use super::Value;
+    use proptest::prelude::*;
+    use std::cmp::Ordering;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::BTreeSet;
+    use std::hash::Hash;
+    use std::hash::Hasher;
+
+    fn hash_of(value: &impl Hash) -> u64 {
+        let state = &mut DefaultHasher::default();
+        value.hash(state);
+        state.finish()
+    }
+
+    /// floats with NaN, infinities and signed zeros showing up often
+    fn float() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            Just(f32::NAN),
+            Just(-f32::NAN),
+            Just(0.0),
+            Just(-0.0),
+            Just(f32::INFINITY),
+            Just(f32::NEG_INFINITY),
+            any::<f32>(),
+        ]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            Just(Value::Empty),
+            any::<bool>().prop_map(Value::Bool),
+            "[a-c]{0,3}".prop_map(Value::TextBuffer),
+            (-2..2).prop_map(Value::Int),
+            (-2..2, -2..2).prop_map(|(a, b)| Value::IntPair(a, b)),
+            (-2..2, -2..2, -2..2).prop_map(|(a, b, c)| Value::IntRange(a, b, c)),
+            float().prop_map(Value::Float),
+            (float(), float()).prop_map(|(a, b)| Value::FloatPair(a, b)),
+            (float(), float(), float()).prop_map(|(a, b, c)| Value::FloatRange(a, b, c)),
+            prop::collection::vec(0..3u8, 0..3).prop_map(Value::BinaryVector),
+            (0..3u64).prop_map(Value::Reference),
+            "[a-c]{0,3}".prop_map(Value::Symbol),
+        ]
+    }
+
+    fn attribute() -> impl Strategy<Value = Attribute> {
+        (
+            0..2u32,
+            "[ab]",
+            value(),
+            prop::option::of(("[ab]", value())),
+        )
+            .prop_map(|(id, name, value, transient)| {
+                let mut attribute = Attribute::new(id, name, value);
+                attribute.transient = transient;
+                attribute
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn value_order_is_total(a in value(), b in value(), c in value()) {
+            prop_assert_eq!(a.cmp(&a), Ordering::Equal);
+            prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+            prop_assert_eq!(a.partial_cmp(&b), Some(a.cmp(&b)));
+            if a <= b && b <= c {
+                prop_assert!(a <= c);
+            }
+        }
+
+        #[test]
+        fn value_eq_agrees_with_cmp_and_hash(a in value(), b in value()) {
+            prop_assert_eq!(a == b, a.cmp(&b) == Ordering::Equal);
+            if a == b {
+                prop_assert_eq!(hash_of(&a), hash_of(&b));
+            }
+        }
 
-    view
+        #[test]
+        fn values_of_different_kinds_are_ordered_by_kind(a in value(), b in value()) {
+            if a.kind() != b.kind() {
+                prop_assert_ne!(&a, &b);
+                prop_assert_eq!(a.cmp(&b), a.kind().cmp(&b.kind()));
+            }
+        }
+
+        #[test]
+        fn attribute_order_is_total(a in attribute(), b in attribute(), c in attribute()) {
+            prop_assert_eq!(a.cmp(&a), Ordering::Equal);
+            prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+            if a <= b && b <= c {
+                prop_assert!(a <= c);
+            }
+        }
+
+        #[test]
+        fn attribute_eq_agrees_with_cmp_and_hash(a in attribute(), b in attribute()) {
+            prop_assert_eq!(a == b, a.cmp(&b) == Ordering::Equal);
+            if a == b {
+                prop_assert_eq!(hash_of(&a), hash_of(&b));
+            }
+        }
+
+        #[test]
+        fn ordered_sets_deduplicate_attributes(attributes in prop::collection::vec(attribute(), 0..16)) {
+            let set = attributes.iter().cloned().collect::<BTreeSet<_>>();
+            let mut deduped = attributes;
+            deduped.sort();
+            deduped.dedup();
+            prop_assert_eq!(set.into_iter().collect::<Vec<_>>(), deduped);
+        }
+    }
+
+    #[test]
+    fn nan_equals_itself() {
+        assert_eq!(Value::Float(f32::NAN), Value::Float(f32::NAN));
+        assert_eq!(
+            hash_of(&Value::Float(f32::NAN)),
+            hash_of(&Value::Float(f32::NAN))
+        );
+        assert!(Value::Float(f32::INFINITY) < Value::Float(f32::NAN));
+        assert!(Value::Float(-f32::NAN) < Value::Float(f32::NEG_INFINITY));
+    }
+
+    #[test]
+    fn signed_zeros_are_distinct() {
+        assert_ne!(Value::Float(-0.0), Value::Float(0.0));
+        assert!(Value::Float(-0.0) < Value::Float(0.0));
+        assert_eq!(
+            BTreeSet::from([Value::Float(0.0), Value::Float(-0.0)]).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn kinds_are_ordered_by_declaration() {
+        assert!(Value::Empty < Value::Bool(false));
+        assert!(Value::Bool(true) < Value::TextBuffer(String::new()));
+        assert!(Value::Int(i32::MAX) < Value::IntPair(i32::MIN, i32::MIN));
+        assert!(Value::Float(f32::NAN) < Value::FloatPair(f32::NEG_INFINITY, 0.0));
+        assert!(Value::Reference(u64::MAX) < Value::Symbol(String::new()));
+    }
 }
@@ -0,0 +1,95 @@
+use imgui::Ui;
+
+use super::Attribute;
+use super::ConversionError;
+use super::Value;
+
+/// Maps a type to and from a set of attributes
+///
+/// This trait is usually implemented with `#[derive(Attributes)]`, each named field becomes an attribute
+/// with the same name. Field types must convert into a `Value` and back, see `Value::from` and `TryFrom<&Value>`.
+///
+/// Supported field options are `#[attributes(label = "..", min = .., max = .., read_only, hidden)]`,
+/// adding `#[attributes(app)]` to the struct also implements `App` with `edit_fields`/`display_fields`.
+pub trait Attributes: Sized {
+    /// returns an attribute for each field of self, owned by entity `id`
+    fn to_attributes(&self, id: u32) -> Vec<Attribute>;
+
+    /// reads each field of self from the attribute with the same name
+    fn from_attributes(attributes: &[Attribute]) -> Result<Self, ConversionError>;
+
+    /// shows an editor for each visible field of self
+    fn edit_fields(&mut self, ui: &Ui);
+
+    /// shows each visible field of self
+    fn display_fields(&self, ui: &Ui);
+}
+
+/// Options for a field shown by an `Attributes` editor
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldOptions {
+    /// label shown next to the field
+    pub label: &'static str,
+    /// shows a slider between (min, max) for `Int`/`Float` fields
+    pub range: Option<(f32, f32)>,
+    /// shows the field but does not allow changes
+    pub read_only: bool,
+}
+
+/// Finds the value of the attribute named `name`, used by `Attributes::from_attributes`
+pub fn find_field<'a>(
+    attributes: &'a [Attribute],
+    name: &str,
+) -> Result<&'a Value, ConversionError> {
+    attributes
+        .iter()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+        .ok_or_else(|| ConversionError::Missing {
+            name: name.to_string(),
+        })
+}
+
+/// Shows an editor for `field` with `Value::edit_ui`, used by `Attributes::edit_fields`
+pub fn edit_field<T>(ui: &Ui, options: &FieldOptions, field: &mut T)
+where
+    T: Clone + Into<Value> + for<'a> TryFrom<&'a Value, Error = ConversionError>,
+{
+    let mut value = with_range(field.clone().into(), options.range);
+
+    ui.disabled(options.read_only, || value.edit_ui(options.label, ui));
+
+    if options.read_only {
+        return;
+    }
+
+    if let Ok(next) = T::try_from(&without_range(value, options.range)) {
+        *field = next;
+    }
+}
+
+/// Shows `field` with a read-only `Value::edit_ui`, used by `Attributes::display_fields`
+pub fn display_field<T>(ui: &Ui, options: &FieldOptions, field: &T)
+where
+    T: Clone + Into<Value>,
+{
+    let mut value = with_range(field.clone().into(), options.range);
+
+    ui.disabled(true, || value.edit_ui(options.label, ui));
+}
+
+fn with_range(value: Value, range: Option<(f32, f32)>) -> Value {
+    match (value, range) {
+        (Value::Int(i), Some((min, max))) => Value::IntRange(i, min as i32, max as i32),
+        (Value::Float(f), Some((min, max))) => Value::FloatRange(f, min, max),
+        (value, _) => value,
+    }
+}
+
+fn without_range(value: Value, range: Option<(f32, f32)>) -> Value {
+    match (value, range) {
+        (Value::IntRange(i, ..), Some(_)) => Value::Int(i),
+        (Value::FloatRange(f, ..), Some(_)) => Value::Float(f),
+        (value, _) => value,
+    }
+}
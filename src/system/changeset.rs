@@ -0,0 +1,388 @@
+use specs::Entity;
+use specs::World;
+use specs::WorldExt;
+use specs::WriteStorage;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use super::Attribute;
+use super::Extension;
+use super::History;
+use super::Operation;
+use super::Schema;
+use super::ValidationError;
+use super::Value;
+
+/// Error returned when a changeset can't be committed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangesetError {
+    /// the attribute was removed from the entity after it was staged
+    Missing { entity: Entity },
+    /// the pending changes of the attribute were cleared after it was staged
+    NotStaged { entity: Entity },
+    /// the stable name/value of the attribute was changed after it was staged, and differs from the pending changes
+    Conflict { entity: Entity, name: String },
+    /// the pending value is not valid for the attribute's metadata
+    Invalid {
+        entity: Entity,
+        name: String,
+        error: ValidationError,
+    },
+}
+
+impl Display for ChangesetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangesetError::Missing { entity } => {
+                write!(f, "attribute on entity {} was removed", entity.id())
+            }
+            ChangesetError::NotStaged { entity } => {
+                write!(
+                    f,
+                    "attribute on entity {} has no pending changes",
+                    entity.id()
+                )
+            }
+            ChangesetError::Conflict { entity, name } => write!(
+                f,
+                "attribute {} on entity {} was changed after it was staged",
+                name,
+                entity.id()
+            ),
+            ChangesetError::Invalid {
+                entity,
+                name,
+                error,
+            } => write!(
+                f,
+                "attribute {} on entity {} is not valid, {}",
+                name,
+                entity.id(),
+                error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChangesetError {}
+
+/// A set of pending edits across many attributes that are committed, or rolled back, together
+///
+/// Staging an edit sets the transient value of the attribute, and remembers the stable name/value
+/// it was based on, so that commit can detect conflicting changes made by other systems in the meantime,
+/// and the pending changes it had before, so that rollback can restore them
+#[derive(Debug, Clone)]
+pub struct Changeset {
+    name: String,
+    staged: BTreeMap<Entity, (u64, Option<(String, Value)>)>,
+}
+
+impl Changeset {
+    /// returns a new empty changeset, `name` is used when recording to `History`
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self {
+            name: name.as_ref().to_string(),
+            staged: BTreeMap::new(),
+        }
+    }
+
+    /// name of this changeset
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// entities with staged edits
+    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
+        self.staged.keys()
+    }
+
+    /// returns true if nothing is staged
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// stages `edit` as the pending name/value of `attribute`, which is stored on `entity`
+    pub fn stage(&mut self, entity: Entity, attribute: &mut Attribute, edit: (String, Value)) {
+        self.track(entity, attribute);
+        attribute.edit(edit);
+    }
+
+    /// stages `value` as the pending value of `attribute`, which is stored on `entity`
+    pub fn stage_value(&mut self, entity: Entity, attribute: &mut Attribute, value: Value) {
+        self.track(entity, attribute);
+        attribute.edit_as(value);
+    }
+
+    /// adds `attribute`, which is stored on `entity`, to this changeset with its current pending changes,
+    /// if the attribute is already part of this changeset, the original base is kept
+    pub fn track(&mut self, entity: Entity, attribute: &Attribute) {
+        self.staged
+            .entry(entity)
+            .or_insert_with(|| (attribute.stable_hash(), attribute.transient().cloned()));
+    }
+
+    /// checks that every staged attribute can be committed
+    pub fn validate(
+        &self,
+        attributes: &WriteStorage<Attribute>,
+        schema: Option<&Schema>,
+    ) -> Result<(), Vec<ChangesetError>> {
+        let mut errors = vec![];
+
+        for (entity, (base, _)) in self.staged.iter() {
+            let entity = *entity;
+            let attribute = match attributes.get(entity) {
+                Some(attribute) => attribute,
+                None => {
+                    errors.push(ChangesetError::Missing { entity });
+                    continue;
+                }
+            };
+
+            if attribute.is_stable() {
+                errors.push(ChangesetError::NotStaged { entity });
+            } else if attribute.conflicts_with(*base) {
+                errors.push(ChangesetError::Conflict {
+                    entity,
+                    name: attribute.name().to_string(),
+                });
+            } else if let Some(metadata) = schema.and_then(|s| s.get(attribute.name())) {
+                if let Err(error) = attribute.validate(metadata) {
+                    errors.push(ChangesetError::Invalid {
+                        entity,
+                        name: attribute.name().to_string(),
+                        error,
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Commits every staged attribute if all of them are valid, otherwise rolls back all of them
+    ///
+    /// When a history is passed, the commits are recorded as a single transaction
+    pub fn commit(
+        self,
+        attributes: &mut WriteStorage<Attribute>,
+        schema: Option<&Schema>,
+        history: Option<&mut History>,
+    ) -> Result<(), Vec<ChangesetError>> {
+        if let Err(errors) = self.validate(attributes, schema) {
+            self.rollback(attributes);
+            return Err(errors);
+        }
+
+        let mut operations = vec![];
+        for entity in self.staged.keys() {
+            if let Some(attribute) = attributes.get_mut(*entity) {
+                if let Some(before) = attribute.commit_unchecked() {
+                    operations.push(Operation::Commit {
+                        entity: *entity,
+                        before,
+                        after: (attribute.name().to_string(), attribute.value().clone()),
+                    });
+                }
+            }
+        }
+
+        if let Some(history) = history {
+            history.begin(&self.name);
+            for operation in operations {
+                history.record(operation);
+            }
+            history.end();
+        }
+
+        Ok(())
+    }
+
+    /// restores the pending changes every staged attribute had before it was added to this changeset
+    pub fn rollback(self, attributes: &mut WriteStorage<Attribute>) {
+        for (entity, (_, transient)) in self.staged {
+            if let Some(attribute) = attributes.get_mut(entity) {
+                attribute.transient = transient;
+            }
+        }
+    }
+}
+
+/// Resource with changesets that are waiting to be committed by `AtomicCommit`
+#[derive(Debug, Default)]
+pub struct Changesets {
+    queued: Vec<Changeset>,
+    /// outcome of each changeset from the last maintain step that committed changesets
+    pub results: Vec<(String, Result<(), Vec<ChangesetError>>)>,
+}
+
+impl Changesets {
+    /// queues `changeset` to be committed on the next maintain step
+    pub fn submit(&mut self, changeset: Changeset) {
+        self.queued.push(changeset);
+    }
+}
+
+/// Extension that commits submitted changesets after the app world is maintained,
+/// since `on_maintain` has exclusive access to the world, no system can observe a half-applied changeset
+#[derive(Default)]
+pub struct AtomicCommit;
+
+impl Extension for AtomicCommit {
+    fn configure_app_world(world: &mut World) {
+        world.register::<Attribute>();
+        world.insert(Changesets::default());
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        let queued = std::mem::take(&mut app_world.write_resource::<Changesets>().queued);
+        if queued.is_empty() {
+            return;
+        }
+
+        let mut results = vec![];
+        {
+            let mut attributes = app_world.write_component::<Attribute>();
+            let schema = app_world.try_fetch::<Schema>();
+            let mut history = app_world.try_fetch_mut::<History>();
+
+            for changeset in queued {
+                let name = changeset.name().to_string();
+                let result =
+                    changeset.commit(&mut attributes, schema.as_deref(), history.as_deref_mut());
+                results.push((name, result));
+            }
+        }
+
+        app_world.write_resource::<Changesets>().results = results;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    use super::Changeset;
+    use super::ChangesetError;
+    use crate::system::Attribute;
+    use crate::system::History;
+    use crate::system::Metadata;
+    use crate::system::Schema;
+    use crate::system::Value;
+
+    fn setup() -> (World, Vec<specs::Entity>) {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let entities = (0..2)
+            .map(|i| {
+                world
+                    .create_entity()
+                    .with(Attribute::new(i, format!("a{}", i), Value::Int(i as i32)))
+                    .build()
+            })
+            .collect();
+        (world, entities)
+    }
+
+    #[test]
+    fn changesets_commit_every_attribute_as_one_transaction() {
+        let (mut world, entities) = setup();
+        let mut history = History::new(&mut world);
+        let mut attributes = world.write_storage::<Attribute>();
+
+        let mut changeset = Changeset::new("move");
+        for (i, entity) in entities.iter().enumerate() {
+            let attribute = attributes.get_mut(*entity).unwrap();
+            changeset.stage_value(*entity, attribute, Value::Int(10 + i as i32));
+        }
+        assert_eq!(
+            changeset.commit(&mut attributes, None, Some(&mut history)),
+            Ok(())
+        );
+
+        for (i, entity) in entities.iter().enumerate() {
+            let attribute = attributes.get(*entity).unwrap();
+            assert_eq!(attribute.value(), &Value::Int(10 + i as i32));
+            assert!(attribute.is_stable());
+        }
+        assert_eq!(history.undo_stack().len(), 1);
+        assert_eq!(history.undo_stack()[0].name, "move");
+        assert_eq!(history.undo_stack()[0].operations.len(), 2);
+    }
+
+    #[test]
+    fn invalid_changesets_roll_back_to_the_pending_changes_before_staging() {
+        let (world, entities) = setup();
+        let mut attributes = world.write_storage::<Attribute>();
+        let mut schema = Schema::default();
+        schema.insert(
+            "a1",
+            Metadata {
+                max: Some(10.0),
+                ..Default::default()
+            },
+        );
+
+        attributes
+            .get_mut(entities[0])
+            .unwrap()
+            .edit(("pending".to_string(), Value::Int(5)));
+
+        let mut changeset = Changeset::new("move");
+        let attribute = attributes.get_mut(entities[0]).unwrap();
+        changeset.stage_value(entities[0], attribute, Value::Int(6));
+        let attribute = attributes.get_mut(entities[1]).unwrap();
+        changeset.stage_value(entities[1], attribute, Value::Int(11));
+
+        let errors = changeset
+            .commit(&mut attributes, Some(&schema), None)
+            .unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [ChangesetError::Invalid { name, .. }] if name == "a1"
+        ));
+
+        let first = attributes.get(entities[0]).unwrap();
+        assert_eq!(first.value(), &Value::Int(0));
+        assert_eq!(
+            first.transient(),
+            Some(&("pending".to_string(), Value::Int(5)))
+        );
+        let second = attributes.get(entities[1]).unwrap();
+        assert_eq!(second.value(), &Value::Int(1));
+        assert!(second.is_stable());
+    }
+
+    #[test]
+    fn changes_made_after_staging_conflict() {
+        let (world, entities) = setup();
+        let mut attributes = world.write_storage::<Attribute>();
+
+        let mut changeset = Changeset::new("move");
+        for entity in entities.iter() {
+            let attribute = attributes.get_mut(*entity).unwrap();
+            changeset.stage_value(*entity, attribute, Value::Int(20));
+        }
+        attributes.get_mut(entities[1]).unwrap().set(Value::Int(30));
+
+        assert_eq!(
+            changeset.commit(&mut attributes, None, None),
+            Err(vec![ChangesetError::Conflict {
+                entity: entities[1],
+                name: "a1".to_string()
+            }])
+        );
+        assert_eq!(attributes.get(entities[0]).unwrap().value(), &Value::Int(0));
+        assert!(attributes.get(entities[0]).unwrap().is_stable());
+        assert_eq!(
+            attributes.get(entities[1]).unwrap().value(),
+            &Value::Int(30)
+        );
+    }
+}
@@ -0,0 +1,559 @@
+use imgui::ChildWindow;
+use imgui::InputTextCallbackHandler;
+use imgui::InputTextMultilineCallback;
+use imgui::StyleColor;
+use imgui::TextCallbackData;
+use imgui::Ui;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::grammars;
+use super::widget_cache::WidgetCache;
+use super::Diagnostic;
+use super::DiagnosticsProvider;
+use super::Grammar;
+use super::Severity;
+use super::Token;
+use super::TokenKind;
+
+/// Highlighting and diagnostics of the text the editor last showed
+struct Highlighted {
+    hash: u64,
+    tokens: Vec<Token>,
+    /// byte offset of the start of each line
+    lines: Vec<usize>,
+    /// index of the longest line
+    widest: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Code editor widget with syntax highlighting, bracket matching, line numbers, diagnostics and goto-line
+///
+/// The editor doesn't own the text it edits, editing is done by an imgui multiline input with invisible text,
+/// and the highlighted text is drawn under it with the window draw list
+#[derive(Clone)]
+pub struct CodeEditor {
+    /// grammar used for highlighting, plain text if None
+    pub grammar: Option<Grammar>,
+    /// used instead of the diagnostics of the grammar, if set
+    pub diagnostics: Option<Arc<dyn DiagnosticsProvider>>,
+    /// height of the editor
+    pub height: f32,
+    highlighted: Option<Arc<Highlighted>>,
+    goto_line: i32,
+    scroll_to: Option<usize>,
+    pub(super) cursor: usize,
+    pub(super) selection: Range<usize>,
+    select: Option<Range<usize>>,
+    reload: bool,
+}
+
+impl Debug for CodeEditor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CodeEditor")
+            .field("grammar", &self.grammar)
+            .field("cursor", &self.cursor)
+            .field("selection", &self.selection)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for CodeEditor {
+    fn default() -> Self {
+        Self {
+            grammar: None,
+            diagnostics: None,
+            height: 300.0,
+            highlighted: None,
+            goto_line: 1,
+            scroll_to: None,
+            cursor: 0,
+            selection: 0..0,
+            select: None,
+            reload: false,
+        }
+    }
+}
+
+impl CodeEditor {
+    /// returns an editor that highlights with the registered grammar for the extension of `path`
+    pub fn for_path(path: &str) -> Self {
+        Self::default().with_grammar(grammars().for_path(path).cloned())
+    }
+
+    pub fn with_grammar(mut self, grammar: Option<Grammar>) -> Self {
+        self.set_grammar(grammar);
+        self
+    }
+
+    pub fn with_diagnostics(mut self, provider: impl DiagnosticsProvider + 'static) -> Self {
+        self.diagnostics = Some(Arc::new(provider));
+        self.highlighted = None;
+        self
+    }
+
+    pub fn set_grammar(&mut self, grammar: Option<Grammar>) {
+        self.grammar = grammar;
+        self.highlighted = None;
+    }
+
+    /// diagnostics of the text the editor last showed
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.highlighted
+            .as_ref()
+            .map_or(&[], |h| h.diagnostics.as_slice())
+    }
+
+    /// byte offset of the cursor
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// byte range of the selection, empty if nothing is selected
+    pub fn selection(&self) -> Range<usize> {
+        self.selection.clone()
+    }
+
+    /// selects `range` of `text` the next time the editor is shown, and scrolls to it
+    pub fn select(&mut self, range: Range<usize>, text: &str) {
+        self.scroll_to = Some(text[..range.start.min(text.len())].matches('\n').count());
+        self.select = Some(range);
+    }
+
+    /// moves the cursor to the start of `line`, starting at 1
+    pub fn goto_line(&mut self, line: usize, text: &str) {
+        let start = match line.saturating_sub(1) {
+            0 => 0,
+            line => text
+                .match_indices('\n')
+                .nth(line - 1)
+                .map_or(text.len(), |(i, _)| i + 1),
+        };
+        self.select(start..start, text);
+    }
+
+    /// line and column of the cursor in `text`, starting at 1
+    pub fn cursor_position(&self, text: &str) -> (usize, usize) {
+        let before = &text[..self.cursor.min(text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        (line, column)
+    }
+
+    /// must be called when the text was changed by anything other than the editor, while it might be focused
+    pub fn text_changed(&mut self, text: &str) {
+        self.reload = true;
+        self.selection = 0..0;
+        self.cursor = self.cursor.min(text.len());
+        while !text.is_char_boundary(self.cursor) {
+            self.cursor -= 1;
+        }
+    }
+
+    /// Shows the editor for `text`, `id` must be unique in the current window
+    ///
+    /// Returns true if the text was changed
+    pub fn ui(&mut self, id: impl AsRef<str>, text: &mut String, ui: &Ui) -> bool {
+        let _id = ui.push_id(id.as_ref());
+        self.update(text);
+
+        ui.set_next_item_width(100.0);
+        let goto = ui
+            .input_int("##goto line", &mut self.goto_line)
+            .enter_returns_true(true)
+            .build();
+        ui.same_line();
+        if ui.button("go to line") || goto {
+            self.goto_line(self.goto_line.max(1) as usize, text);
+        }
+
+        let names = std::iter::once("plain text".to_string())
+            .chain(grammars().names().map(str::to_string))
+            .collect::<Vec<_>>();
+        let mut selected = self
+            .grammar
+            .as_ref()
+            .and_then(|g| names.iter().position(|n| *n == g.name))
+            .unwrap_or_default();
+        ui.same_line();
+        ui.set_next_item_width(120.0);
+        if ui.combo_simple_string("##grammar", &mut selected, &names) {
+            self.set_grammar(grammars().find(&names[selected]).cloned());
+            self.update(text);
+        }
+
+        let diagnostics = self.diagnostics();
+        if !diagnostics.is_empty() {
+            ui.same_line();
+            let color = severity_color(diagnostics.iter().map(|d| d.severity).max());
+            ui.text_colored(color, format!("{} problems", diagnostics.len()));
+        }
+
+        let mut changed = false;
+        ChildWindow::new("code")
+            .size([0.0, self.height])
+            .border(true)
+            .horizontal_scrollbar(true)
+            .build(ui, || changed = self.text_ui(text, ui));
+        changed
+    }
+
+    /// re-highlights and re-checks `text` if it changed since it was last shown
+    fn update(&mut self, text: &str) {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.highlighted.as_ref().is_some_and(|h| h.hash == hash) {
+            return;
+        }
+
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect::<Vec<_>>();
+        let widest = (0..lines.len())
+            .max_by_key(|&i| line_range(&lines, text, i).len())
+            .unwrap_or_default();
+        let provider = self
+            .diagnostics
+            .as_ref()
+            .or_else(|| self.grammar.as_ref().and_then(|g| g.diagnostics.as_ref()));
+
+        self.highlighted = Some(Arc::new(Highlighted {
+            hash,
+            tokens: self
+                .grammar
+                .as_ref()
+                .map(|g| g.highlight(text))
+                .unwrap_or_default(),
+            diagnostics: provider.map(|p| p.diagnostics(text)).unwrap_or_default(),
+            lines,
+            widest,
+        }));
+    }
+
+    fn text_ui(&mut self, text: &mut String, ui: &Ui) -> bool {
+        let highlighted = match self.highlighted.clone() {
+            Some(highlighted) => highlighted,
+            None => return false,
+        };
+        let padding = ui.clone_style().frame_padding;
+        let line_height = ui.text_line_height();
+        let lines = highlighted.lines.len();
+        let gutter = ui.calc_text_size(lines.to_string())[0] + padding[0] * 2.0;
+
+        let [x, y] = ui.cursor_pos();
+        if let Some(line) = self.scroll_to.take() {
+            let top = y + padding[1] + line as f32 * line_height;
+            let visible = ui.window_size()[1] - line_height * 2.0;
+            if top < ui.scroll_y() || top > ui.scroll_y() + visible {
+                ui.set_scroll_y((top - visible / 2.0).max(0.0));
+            }
+        }
+        ui.set_cursor_pos([x + gutter, y]);
+
+        if self.select.is_some() || self.reload {
+            ui.set_keyboard_focus_here();
+        }
+
+        let widest = &text[line_range(&highlighted.lines, text, highlighted.widest)];
+        let width = (ui.calc_text_size(widest)[0] + padding[0] * 2.0 + line_height)
+            .max(ui.content_region_avail()[0]);
+        let height = line_height * (lines + 1) as f32 + padding[1] * 2.0;
+        let callback = EditorCallback {
+            reload: self.reload.then(|| text.clone()),
+            select: self.select.take(),
+            cursor: &mut self.cursor,
+            selection: &mut self.selection,
+        };
+        self.reload = false;
+
+        // the input draws the selection, this editor draws the text, caret and background under it
+        let changed = {
+            let _text = ui.push_style_color(StyleColor::Text, [0.0; 4]);
+            let _background = ui.push_style_color(StyleColor::FrameBg, [0.0; 4]);
+            ui.input_text_multiline("##text", text, [width, height])
+                .allow_tab_input(true)
+                .callback(InputTextMultilineCallback::ALWAYS, callback)
+                .build()
+        };
+        let active = ui.is_item_active();
+        let frame = [ui.item_rect_min(), ui.item_rect_max()];
+
+        if changed {
+            self.update(text);
+            self.scroll_to = Some(text[..self.cursor.min(text.len())].matches('\n').count());
+        }
+        if let Some(highlighted) = self.highlighted.clone() {
+            self.draw(&highlighted, text, frame, active, ui);
+        }
+        changed
+    }
+
+    fn draw(
+        &self,
+        highlighted: &Highlighted,
+        text: &str,
+        [min, max]: [[f32; 2]; 2],
+        active: bool,
+        ui: &Ui,
+    ) {
+        let padding = ui.clone_style().frame_padding;
+        let line_height = ui.text_line_height();
+        let origin = [min[0] + padding[0], min[1] + padding[1]];
+        let lines = &highlighted.lines;
+        let tokens = &highlighted.tokens;
+        let foreground = ui.style_color(StyleColor::Text);
+        let disabled = ui.style_color(StyleColor::TextDisabled);
+
+        let window_top = ui.window_pos()[1];
+        let window_bottom = window_top + ui.window_size()[1];
+        let first = ((window_top - origin[1]) / line_height).floor().max(0.0) as usize;
+        let last =
+            (((window_bottom - origin[1]) / line_height).ceil().max(0.0) as usize).min(lines.len());
+
+        // screen position of a byte offset of the text
+        let position = |offset: usize| {
+            let line = lines.partition_point(|&start| start <= offset) - 1;
+            [
+                origin[0] + ui.calc_text_size(&text[lines[line]..offset])[0],
+                origin[1] + line as f32 * line_height,
+            ]
+        };
+        let (cursor_line, _) = self.cursor_position(text);
+
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_rect(min, max, ui.style_color(StyleColor::FrameBg))
+            .filled(true)
+            .build();
+
+        for line in first..last {
+            let range = line_range(lines, text, line);
+            let y = origin[1] + line as f32 * line_height;
+
+            let number = (line + 1).to_string();
+            let severity = highlighted
+                .diagnostics
+                .iter()
+                .filter(|d| d.line.clamp(1, lines.len()) == line + 1)
+                .map(|d| d.severity)
+                .max();
+            let color = match severity {
+                Some(_) => severity_color(severity),
+                None if line + 1 == cursor_line => foreground,
+                None => disabled,
+            };
+            let number_x = min[0] - padding[0] - ui.calc_text_size(&number)[0];
+            draw_list.add_text([number_x, y], color, &number);
+
+            let mut x = origin[0];
+            let mut at = range.start;
+            let mut next = tokens.partition_point(|t| t.range.end <= range.start);
+            while at < range.end {
+                let (end, color) = match tokens.get(next) {
+                    Some(token) if token.range.start < range.end => {
+                        if token.range.start > at {
+                            (token.range.start, foreground)
+                        } else {
+                            next += 1;
+                            (token.range.end.min(range.end), token.kind.color())
+                        }
+                    }
+                    _ => (range.end, foreground),
+                };
+                let segment = &text[at..end];
+                draw_list.add_text([x, y], color, segment);
+                x += ui.calc_text_size(segment)[0];
+                at = end;
+            }
+        }
+
+        if let Some((open, close)) = matching_bracket(tokens, text, self.cursor) {
+            for offset in [open, close] {
+                let [x, y] = position(offset);
+                let width = ui.calc_text_size(&text[offset..offset + 1])[0];
+                draw_list
+                    .add_rect([x, y], [x + width, y + line_height], foreground)
+                    .build();
+            }
+        }
+
+        if active && ui.time().fract() < 0.6 && self.cursor <= text.len() {
+            let [x, y] = position(self.cursor);
+            draw_list
+                .add_line([x, y], [x, y + line_height], foreground)
+                .build();
+        }
+
+        let mut hovered = None;
+        for diagnostic in &highlighted.diagnostics {
+            let line = diagnostic.line.clamp(1, lines.len()) - 1;
+            if line < first || line >= last {
+                continue;
+            }
+
+            let range = line_range(lines, text, line);
+            let content = &text[range.clone()];
+            let offset = |column: usize| {
+                content
+                    .char_indices()
+                    .nth(column)
+                    .map_or(content.len(), |(i, _)| i)
+            };
+            let start = offset(diagnostic.column.saturating_sub(1));
+            let end = offset(diagnostic.column.saturating_sub(1) + diagnostic.len);
+            let [x0, y] = position(range.start + start);
+            let x1 = (origin[0] + ui.calc_text_size(&content[..end])[0])
+                .max(x0 + ui.calc_text_size("m")[0]);
+            let y = y + line_height;
+
+            let color = severity_color(Some(diagnostic.severity));
+            let mut x = x0;
+            let mut up = false;
+            while x < x1 {
+                let step = (x1 - x).min(2.0);
+                let (from, to) = if up { (0.0, 2.0) } else { (2.0, 0.0) };
+                draw_list
+                    .add_line([x, y - from], [x + step, y - to], color)
+                    .build();
+                x += step;
+                up = !up;
+            }
+
+            if ui.is_mouse_hovering_rect([x0, y - line_height], [x1, y]) {
+                hovered = Some(diagnostic.message.as_str());
+            }
+        }
+        drop(draw_list);
+
+        if let Some(message) = hovered {
+            ui.tooltip_text(message);
+        }
+    }
+}
+
+/// color of the most severe diagnostic, errors are red and warnings are yellow
+fn severity_color(severity: Option<Severity>) -> [f32; 4] {
+    match severity {
+        Some(Severity::Error) => [1.0, 0.4, 0.4, 1.0],
+        _ => [1.0, 0.8, 0.3, 1.0],
+    }
+}
+
+/// byte range of `line`, without the line ending
+fn line_range(lines: &[usize], text: &str, line: usize) -> Range<usize> {
+    let end = lines.get(line + 1).map_or(text.len(), |next| next - 1);
+    lines[line]..end
+}
+
+/// byte offsets of the bracket at, or just before, `cursor` and the bracket that matches it
+fn matching_bracket(tokens: &[Token], text: &str, cursor: usize) -> Option<(usize, usize)> {
+    let start = tokens.partition_point(|t| t.range.end < cursor);
+    let at = (start..tokens.len().min(start + 2))
+        .filter(|&i| tokens[i].kind == TokenKind::Punctuation)
+        .find(|&i| tokens[i].range.start == cursor || tokens[i].range.end == cursor)?;
+
+    let bracket = text[tokens[at].range.clone()].chars().next()?;
+    let (other, forward) = match bracket {
+        '(' => (')', true),
+        '[' => (']', true),
+        '{' => ('}', true),
+        ')' => ('(', false),
+        ']' => ('[', false),
+        '}' => ('{', false),
+        _ => return None,
+    };
+
+    let candidates: Box<dyn Iterator<Item = &Token>> = if forward {
+        Box::new(tokens[at..].iter())
+    } else {
+        Box::new(tokens[..=at].iter().rev())
+    };
+    let mut depth = 0;
+    for token in candidates.filter(|t| t.kind == TokenKind::Punctuation) {
+        match text[token.range.clone()].chars().next() {
+            Some(c) if c == bracket => depth += 1,
+            Some(c) if c == other => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((tokens[at].range.start, token.range.start));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+struct EditorCallback<'a> {
+    reload: Option<String>,
+    select: Option<Range<usize>>,
+    cursor: &'a mut usize,
+    selection: &'a mut Range<usize>,
+}
+
+impl InputTextCallbackHandler for EditorCallback<'_> {
+    fn on_always(&mut self, mut data: TextCallbackData<'_>) {
+        // the active input keeps its own copy of the text, so changes made outside of it are pushed to it
+        if let Some(text) = self.reload.take() {
+            if data.str() != text {
+                data.clear();
+                data.push_str(&text);
+            }
+        }
+
+        if let Some(select) = self.select.take() {
+            if select.end <= data.str().len() {
+                *data.selection_start_mut() = select.start as i32;
+                *data.selection_end_mut() = select.end as i32;
+                data.set_cursor_pos(select.end);
+            }
+        }
+
+        *self.cursor = data.cursor_pos();
+        let selection = data.selection();
+        *self.selection = selection.start.min(selection.end)..selection.start.max(selection.end);
+    }
+}
+
+/// Shows a code editor for the text of the attribute called `name` and identified by `key`,
+/// returns true if the text was changed
+///
+/// The editor is kept between frames so that the cursor and highlighting are not lost, `key` must be unique to the attribute
+pub(super) fn text_buffer_ui(name: &str, key: &str, text: &mut String, ui: &Ui) -> bool {
+    WidgetCache::<CodeEditor>::lock(ui)
+        .get_or_insert_with(key, || CodeEditor::for_path(name))
+        .ui(key, text, ui)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matching_bracket;
+    use crate::system::Grammar;
+
+    #[test]
+    fn brackets_are_matched_in_both_directions() {
+        let text = "f(a[b], \")\")";
+        let tokens = Grammar::rust().highlight(text);
+        let close = text.len() - 1;
+
+        assert_eq!(matching_bracket(&tokens, text, 1), Some((1, close)));
+        assert_eq!(matching_bracket(&tokens, text, 2), Some((1, close)));
+        assert_eq!(matching_bracket(&tokens, text, 3), Some((3, 5)));
+        assert_eq!(matching_bracket(&tokens, text, 6), Some((5, 3)));
+        assert_eq!(
+            matching_bracket(&tokens, text, text.len()),
+            Some((close, 1))
+        );
+        assert_eq!(matching_bracket(&tokens, text, 0), None);
+
+        let text = "(()";
+        let tokens = Grammar::rust().highlight(text);
+        assert_eq!(matching_bracket(&tokens, text, 0), None);
+        assert_eq!(matching_bracket(&tokens, text, 3), Some((2, 1)));
+    }
+}
@@ -0,0 +1,444 @@
+use imgui::Ui;
+use specs::world::EntitiesRes;
+use specs::Entity;
+use specs::Join;
+use specs::ReadStorage;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::str::from_utf8;
+
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Extension;
+use super::Metadata;
+use super::Subscription;
+use super::Value;
+
+/// Largest number of line pairs compared by `diff_lines` before falling back to a full replace
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+impl Attribute {
+    /// Returns true if the stable name/value is no longer `base`,
+    /// and the pending changes are different from the current stable name/value
+    ///
+    /// This is a three-way comparison between the base the edit started from, the current stable
+    /// value (theirs), and the transient value (mine)
+    pub fn conflicts_with(&self, base: u64) -> bool {
+        match self.transient() {
+            Some((name, value)) => {
+                self.stable_hash() != base && (name != self.name() || value != self.value())
+            }
+            None => false,
+        }
+    }
+}
+
+/// Resource with the base of each pending edit, which is the hash of the stable name/value the edit started from
+///
+/// Bases are kept outside of `Attribute`, so that they don't affect its equality and order.
+/// `update` reads the events of a subscription to `AttributeEvents`, the base of an edit is the stable name/value
+/// of the attribute when it was last seen without pending changes
+#[derive(Debug)]
+pub struct MergeBases {
+    subscription: Subscription,
+    /// stable hash of each attribute when it was last seen, by entity id
+    stable: HashMap<u32, u64>,
+    /// base of each attribute with pending changes, by entity id
+    bases: HashMap<u32, u64>,
+    /// line diff shown by `merge_ui` for each entity id, with the hash of the (base, mine, theirs) it was computed for
+    diffs: HashMap<u32, (u64, Vec<(Change, String)>)>,
+}
+
+impl MergeBases {
+    /// subscribes to the `AttributeEvents` of `world`, which are added if there are none,
+    /// attributes that already have pending changes are based on their current stable name/value
+    pub fn new(world: &mut World) -> Self {
+        ChangeDetection::configure_app_world(world);
+        let subscription = world
+            .write_resource::<AttributeEvents>()
+            .subscribe(AttributeFilter::Any);
+        let attributes = world.read_storage::<Attribute>();
+        let stable = (&world.entities(), &attributes)
+            .join()
+            .map(|(e, a)| (e.id(), a.stable_hash()))
+            .collect::<HashMap<_, _>>();
+        let bases = (&world.entities(), &attributes)
+            .join()
+            .filter(|(_, a)| !a.is_stable())
+            .map(|(e, a)| (e.id(), a.stable_hash()))
+            .collect();
+
+        Self {
+            subscription,
+            stable,
+            bases,
+            diffs: HashMap::new(),
+        }
+    }
+
+    /// Reads the attribute events since the last update,
+    /// and remembers the base of attributes that started an edit
+    ///
+    /// This is called by `ConflictDetection` after the app world is maintained
+    pub fn update(
+        &mut self,
+        events: &mut AttributeEvents,
+        entities: &EntitiesRes,
+        attributes: &ReadStorage<Attribute>,
+    ) {
+        events.update(entities, attributes);
+        for event in events.drain(self.subscription) {
+            let index = event.entity;
+
+            match attributes.get(entities.entity(index)) {
+                Some(attribute) => {
+                    let stable = attribute.stable_hash();
+                    if attribute.is_stable() {
+                        self.bases.remove(&index);
+                        self.diffs.remove(&index);
+                    } else {
+                        let known = self.stable.get(&index).copied().unwrap_or(stable);
+                        self.bases.entry(index).or_insert(known);
+                    }
+                    self.stable.insert(index, stable);
+                }
+                None => {
+                    self.stable.remove(&index);
+                    self.bases.remove(&index);
+                    self.diffs.remove(&index);
+                }
+            }
+        }
+    }
+
+    /// returns the base of the pending changes of the attribute on `entity`
+    pub fn base(&self, entity: Entity) -> Option<u64> {
+        self.bases.get(&entity.id()).copied()
+    }
+
+    /// Returns true if the stable name/value of `attribute`, which is stored on `entity`, was changed
+    /// after its pending changes started, and the pending changes are different from that new stable name/value
+    pub fn has_conflict(&self, entity: Entity, attribute: &Attribute) -> bool {
+        self.base(entity)
+            .is_some_and(|base| attribute.conflicts_with(base))
+    }
+
+    /// Resolves a conflict by keeping the pending changes,
+    /// the current stable name/value becomes the new base
+    pub fn keep_mine(&mut self, entity: Entity, attribute: &Attribute) {
+        if !attribute.is_stable() {
+            self.bases.insert(entity.id(), attribute.stable_hash());
+        }
+    }
+
+    /// Resolves a conflict by discarding the pending changes in favor of the current stable name/value
+    pub fn take_theirs(&mut self, entity: Entity, attribute: &mut Attribute) {
+        attribute.reset_editing();
+        self.keep_mine(entity, attribute);
+    }
+
+    /// Shows an editor for `attribute`, which is stored on `entity`, with `Attribute::edit_attr_with`,
+    /// changes can't be saved while they conflict with the stable name/value, instead `merge_ui` is shown
+    ///
+    /// returns the previous name and value if changes were saved
+    pub fn edit_attr(
+        &mut self,
+        entity: Entity,
+        attribute: &mut Attribute,
+        ui: &Ui,
+        metadata: Option<&Metadata>,
+    ) -> Option<(String, Value)> {
+        if attribute.is_stable() {
+            // an edit started from this editor is based on what it shows
+            self.stable.insert(entity.id(), attribute.stable_hash());
+        }
+
+        attribute.edit_attr_merging(ui, metadata, |attribute, ui| {
+            self.has_conflict(entity, attribute) && {
+                self.merge_ui(entity, attribute, ui);
+                self.has_conflict(entity, attribute)
+            }
+        })
+    }
+
+    /// Shows the stable (theirs) and pending (mine) name/values of `attribute`, which is stored on `entity`,
+    /// side by side with buttons to resolve the conflict
+    pub fn merge_ui(&mut self, entity: Entity, attribute: &mut Attribute, ui: &Ui) {
+        let (name, value) = match attribute.transient() {
+            Some(transient) => transient,
+            None => return,
+        };
+
+        ui.text_colored(
+            [1.0, 0.7, 0.2, 1.0],
+            format!(
+                "{} {:#4x} was changed by another system while it was being edited",
+                attribute.name(),
+                attribute.id()
+            ),
+        );
+
+        if name != attribute.name() {
+            ui.text_colored(REMOVED, format!("- name: {}", attribute.name()));
+            ui.text_colored(ADDED, format!("+ name: {}", name));
+        }
+
+        if value != attribute.value() {
+            let key = {
+                let state = &mut DefaultHasher::default();
+                self.base(entity).hash(state);
+                value.hash(state);
+                attribute.value().hash(state);
+                state.finish()
+            };
+
+            imgui::ChildWindow::new(&format!("diff {} {:#4x}", attribute.name(), attribute.id()))
+                .size([800.0, 200.0])
+                .border(true)
+                .build(ui, || match (as_text(attribute.value()), as_text(value)) {
+                    (Some(theirs), Some(mine)) => {
+                        let (_, diff) = self
+                            .diffs
+                            .entry(entity.id())
+                            .and_modify(|(cached, diff)| {
+                                if *cached != key {
+                                    *cached = key;
+                                    *diff = owned(diff_lines(theirs, mine));
+                                }
+                            })
+                            .or_insert_with(|| (key, owned(diff_lines(theirs, mine))));
+
+                        for (change, line) in diff.iter() {
+                            match change {
+                                Change::Removed => ui.text_colored(REMOVED, format!("- {}", line)),
+                                Change::Added => ui.text_colored(ADDED, format!("+ {}", line)),
+                                Change::Unchanged => ui.text(format!("  {}", line)),
+                            }
+                        }
+                    }
+                    _ => {
+                        ui.text_colored(REMOVED, format!("- theirs: {:?}", attribute.value()));
+                        ui.text_colored(ADDED, format!("+ mine: {:?}", value));
+                    }
+                });
+        }
+
+        if ui.button(format!(
+            "keep mine [{} {}]",
+            attribute.name(),
+            attribute.id()
+        )) {
+            self.keep_mine(entity, attribute);
+        }
+
+        ui.same_line();
+        if ui.button(format!(
+            "take theirs [{} {}]",
+            attribute.name(),
+            attribute.id()
+        )) {
+            self.take_theirs(entity, attribute);
+        }
+    }
+}
+
+fn owned(diff: Vec<(Change, &str)>) -> Vec<(Change, String)> {
+    diff.into_iter()
+        .map(|(change, line)| (change, line.to_string()))
+        .collect()
+}
+
+/// Extension that adds a `MergeBases` resource to the app world, and updates it after the world is maintained
+#[derive(Default)]
+pub struct ConflictDetection;
+
+impl Extension for ConflictDetection {
+    fn configure_app_world(world: &mut World) {
+        if !world.has_value::<MergeBases>() {
+            let bases = MergeBases::new(world);
+            world.insert(bases);
+        }
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        app_world.write_resource::<MergeBases>().update(
+            &mut app_world.write_resource::<AttributeEvents>(),
+            &app_world.entities(),
+            &app_world.read_storage::<Attribute>(),
+        );
+    }
+}
+
+pub(super) const REMOVED: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+pub(super) const ADDED: [f32; 4] = [0.4, 1.0, 0.4, 1.0];
+
+pub(super) fn as_text(value: &Value) -> Option<&str> {
+    match value {
+        Value::TextBuffer(text) | Value::Symbol(text) => Some(text),
+        Value::BinaryVector(bytes) => from_utf8(bytes).ok(),
+        _ => None,
+    }
+}
+
+/// Kind of change for a line in a diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Unchanged,
+    Removed,
+    Added,
+}
+
+/// Returns a line diff between `before` and `after`, based on their longest common subsequence
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<(Change, &'a str)> {
+    let before = before.lines().collect::<Vec<_>>();
+    let after = after.lines().collect::<Vec<_>>();
+
+    // trim the common prefix/suffix, which is most of the text for typical edits
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(b, a)| b == a)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(b, a)| b == a)
+        .count();
+
+    let b = &before[prefix..before.len() - suffix];
+    let a = &after[prefix..after.len() - suffix];
+
+    let mut diff = before[..prefix]
+        .iter()
+        .map(|l| (Change::Unchanged, *l))
+        .collect::<Vec<_>>();
+
+    if (b.len() + 1) * (a.len() + 1) > MAX_DIFF_CELLS {
+        diff.extend(b.iter().map(|l| (Change::Removed, *l)));
+        diff.extend(a.iter().map(|l| (Change::Added, *l)));
+    } else {
+        // lcs[i][j] is the length of the lcs of b[i..] and a[j..]
+        let mut lcs = vec![vec![0usize; a.len() + 1]; b.len() + 1];
+        for i in (0..b.len()).rev() {
+            for j in (0..a.len()).rev() {
+                lcs[i][j] = if b[i] == a[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < b.len() || j < a.len() {
+            if i < b.len() && j < a.len() && b[i] == a[j] {
+                diff.push((Change::Unchanged, b[i]));
+                i += 1;
+                j += 1;
+            } else if i < b.len() && (j == a.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                diff.push((Change::Removed, b[i]));
+                i += 1;
+            } else {
+                diff.push((Change::Added, a[j]));
+                j += 1;
+            }
+        }
+    }
+
+    diff.extend(
+        before[before.len() - suffix..]
+            .iter()
+            .map(|l| (Change::Unchanged, *l)),
+    );
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MergeBases;
+    use crate::system::Attribute;
+    use crate::system::AttributeEvents;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn update(world: &World) {
+        world.write_resource::<MergeBases>().update(
+            &mut world.write_resource::<AttributeEvents>(),
+            &world.entities(),
+            &world.read_storage::<Attribute>(),
+        );
+    }
+
+    fn has_conflict(world: &World, entity: specs::Entity) -> bool {
+        let attributes = world.read_storage::<Attribute>();
+        world
+            .read_resource::<MergeBases>()
+            .has_conflict(entity, attributes.get(entity).unwrap())
+    }
+
+    #[test]
+    fn edits_conflict_when_the_stable_value_changes() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        let bases = MergeBases::new(&mut world);
+        world.insert(bases);
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .edit_as(Value::Int(2));
+        update(&world);
+        assert!(!has_conflict(&world, entity));
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .value = Value::Int(3);
+        update(&world);
+        assert!(has_conflict(&world, entity));
+
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(entity).unwrap();
+            world
+                .write_resource::<MergeBases>()
+                .keep_mine(entity, attribute);
+        }
+        update(&world);
+        assert!(!has_conflict(&world, entity));
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .commit_unchecked();
+        update(&world);
+        assert_eq!(world.read_resource::<MergeBases>().base(entity), None);
+    }
+
+    #[test]
+    fn bases_do_not_affect_equality() {
+        let mut edited = Attribute::new(0, "a", Value::Int(1));
+        edited.edit_as(Value::Int(2));
+        edited.reset_editing();
+
+        let mut other = Attribute::new(0, "a", Value::Int(1));
+        other.edit_as(Value::Int(1));
+        assert_eq!(edited, other);
+    }
+}
@@ -0,0 +1,548 @@
+use std::fmt::Display;
+
+use super::Value;
+use super::ValueKind;
+
+/// Error returned when a value can't be read as, or coerced to, another type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// The value is not of the kind that was expected
+    Mismatch {
+        expected: ValueKind,
+        found: ValueKind,
+    },
+    /// There is no coercion between these kinds
+    Unsupported { from: ValueKind, to: ValueKind },
+    /// A coercion exists, but the content of the value can't be converted
+    Invalid { to: ValueKind, reason: String },
+    /// An attribute that was expected in a set of attributes was not found
+    Missing { name: String },
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::Mismatch { expected, found } => {
+                write!(f, "expected a value of kind {}, found {}", expected, found)
+            }
+            ConversionError::Unsupported { from, to } => {
+                write!(f, "cannot coerce a value of kind {} to {}", from, to)
+            }
+            ConversionError::Invalid { to, reason } => {
+                write!(f, "cannot coerce value to {}, {}", to, reason)
+            }
+            ConversionError::Missing { name } => {
+                write!(f, "missing attribute '{}'", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl Value {
+    /// Returns a copy of this value coerced to `kind`
+    ///
+    /// Coercing to the same kind always succeeds, and any value can be coerced to a `Reference` or to `Empty`.
+    ///
+    /// A float is coerced to an int only if it is a whole number within the range of `i32`,
+    /// ints are coerced to the nearest float, which loses precision for ints beyond ±2^24
+    pub fn coerce(&self, kind: ValueKind) -> Result<Value, ConversionError> {
+        let invalid = |reason: &str| ConversionError::Invalid {
+            to: kind,
+            reason: reason.to_string(),
+        };
+
+        if self.kind() == kind {
+            return Ok(self.clone());
+        }
+
+        let coerced = match (self, kind) {
+            (_, ValueKind::Reference) => self.to_ref(),
+            (_, ValueKind::Empty) => Value::Empty,
+            (Value::Bool(b), ValueKind::Int) => Value::Int(*b as i32),
+            (Value::Int(i), ValueKind::Bool) => Value::Bool(*i != 0),
+            (Value::Int(i), ValueKind::Float) => Value::Float(*i as f32),
+            (Value::Float(f), ValueKind::Int) => {
+                // i32::MAX is not a float, it rounds up to 2^31 which is already out of range
+                if !f.is_finite() || *f < i32::MIN as f32 || *f >= i32::MAX as f32 {
+                    return Err(invalid("float is out of range for an int"));
+                }
+                if f.fract() != 0.0 {
+                    return Err(invalid("float has a fractional part"));
+                }
+                Value::Int(*f as i32)
+            }
+            (Value::IntPair(a, b), ValueKind::FloatPair) => Value::FloatPair(*a as f32, *b as f32),
+            (Value::IntRange(a, b, c), ValueKind::FloatRange) => {
+                Value::FloatRange(*a as f32, *b as f32, *c as f32)
+            }
+            (Value::TextBuffer(text), ValueKind::Symbol) => Value::Symbol(text.clone()),
+            (Value::Symbol(symbol), ValueKind::TextBuffer) => Value::TextBuffer(symbol.clone()),
+            (Value::TextBuffer(text), ValueKind::BinaryVector) => {
+                Value::BinaryVector(text.as_bytes().to_vec())
+            }
+            (Value::BinaryVector(bytes), ValueKind::TextBuffer) => {
+                match String::from_utf8(bytes.clone()) {
+                    Ok(text) => Value::TextBuffer(text),
+                    Err(err) => {
+                        return Err(invalid(&format!("bytes are not valid utf-8, {}", err)))
+                    }
+                }
+            }
+            (Value::TextBuffer(text), ValueKind::Int) => match text.trim().parse() {
+                Ok(i) => Value::Int(i),
+                Err(err) => return Err(invalid(&format!("could not parse '{}', {}", text, err))),
+            },
+            (Value::TextBuffer(text), ValueKind::Float) => match text.trim().parse() {
+                Ok(f) => Value::Float(f),
+                Err(err) => return Err(invalid(&format!("could not parse '{}', {}", text, err))),
+            },
+            (Value::TextBuffer(text), ValueKind::Bool) => match text.trim().parse() {
+                Ok(b) => Value::Bool(b),
+                Err(err) => return Err(invalid(&format!("could not parse '{}', {}", text, err))),
+            },
+            (Value::Bool(b), ValueKind::TextBuffer) => Value::TextBuffer(b.to_string()),
+            (Value::Int(i), ValueKind::TextBuffer) => Value::TextBuffer(i.to_string()),
+            (Value::Float(f), ValueKind::TextBuffer) => Value::TextBuffer(f.to_string()),
+            _ => {
+                return Err(ConversionError::Unsupported {
+                    from: self.kind(),
+                    to: kind,
+                })
+            }
+        };
+
+        Ok(coerced)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::TextBuffer(text)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::TextBuffer(text.to_string())
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<(i32, i32)> for Value {
+    fn from((i1, i2): (i32, i32)) -> Self {
+        Value::IntPair(i1, i2)
+    }
+}
+
+impl From<(i32, i32, i32)> for Value {
+    fn from((i1, i2, i3): (i32, i32, i32)) -> Self {
+        Value::IntRange(i1, i2, i3)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(f: f32) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<(f32, f32)> for Value {
+    fn from((f1, f2): (f32, f32)) -> Self {
+        Value::FloatPair(f1, f2)
+    }
+}
+
+impl From<(f32, f32, f32)> for Value {
+    fn from((f1, f2, f3): (f32, f32, f32)) -> Self {
+        Value::FloatRange(f1, f2, f3)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Self {
+        Value::BinaryVector(bytes)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Self {
+        Value::BinaryVector(bytes.to_vec())
+    }
+}
+
+impl From<u64> for Value {
+    fn from(r: u64) -> Self {
+        Value::Reference(r)
+    }
+}
+
+fn mismatch(expected: ValueKind, found: &Value) -> ConversionError {
+    ConversionError::Mismatch {
+        expected,
+        found: found.kind(),
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(mismatch(ValueKind::Bool, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for i32 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(i) => Ok(*i),
+            _ => Err(mismatch(ValueKind::Int, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for (i32, i32) {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::IntPair(i1, i2) => Ok((*i1, *i2)),
+            _ => Err(mismatch(ValueKind::IntPair, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for (i32, i32, i32) {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::IntRange(i1, i2, i3) => Ok((*i1, *i2, *i3)),
+            _ => Err(mismatch(ValueKind::IntRange, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for f32 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(f) => Ok(*f),
+            _ => Err(mismatch(ValueKind::Float, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for (f32, f32) {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::FloatPair(f1, f2) => Ok((*f1, *f2)),
+            _ => Err(mismatch(ValueKind::FloatPair, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for (f32, f32, f32) {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::FloatRange(f1, f2, f3) => Ok((*f1, *f2, *f3)),
+            _ => Err(mismatch(ValueKind::FloatRange, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for u64 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Reference(r) => Ok(*r),
+            _ => Err(mismatch(ValueKind::Reference, value)),
+        }
+    }
+}
+
+/// Reads the text of either a `TextBuffer` or a `Symbol`
+impl TryFrom<&Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::TextBuffer(text) | Value::Symbol(text) => Ok(text.clone()),
+            _ => Err(mismatch(ValueKind::TextBuffer, value)),
+        }
+    }
+}
+
+/// Reads the text of either a `TextBuffer` or a `Symbol`
+impl<'a> TryFrom<&'a Value> for &'a str {
+    type Error = ConversionError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::TextBuffer(text) | Value::Symbol(text) => Ok(text.as_str()),
+            _ => Err(mismatch(ValueKind::TextBuffer, value)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Vec<u8> {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::BinaryVector(bytes) => Ok(bytes.clone()),
+            _ => Err(mismatch(ValueKind::BinaryVector, value)),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for &'a [u8] {
+    type Error = ConversionError;
+
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::BinaryVector(bytes) => Ok(bytes.as_slice()),
+            _ => Err(mismatch(ValueKind::BinaryVector, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Attribute;
+    use super::ConversionError;
+    use super::Value;
+    use super::ValueKind;
+
+    #[test]
+    fn whole_floats_coerce_to_ints() {
+        assert_eq!(
+            Value::Float(-3.0).coerce(ValueKind::Int),
+            Ok(Value::Int(-3))
+        );
+        assert_eq!(
+            Value::Float(i32::MIN as f32).coerce(ValueKind::Int),
+            Ok(Value::Int(i32::MIN))
+        );
+    }
+
+    #[test]
+    fn lossy_floats_do_not_coerce_to_ints() {
+        for f in [2147483648.0, -2147483904.0, 0.5, f32::NAN, f32::INFINITY] {
+            assert!(
+                matches!(
+                    Value::Float(f).coerce(ValueKind::Int),
+                    Err(ConversionError::Invalid { .. })
+                ),
+                "{} was coerced",
+                f
+            );
+        }
+    }
+
+    #[test]
+    fn values_convert_from_and_to_their_types() {
+        assert_eq!(Value::from(true), Value::Bool(true));
+        assert_eq!(Value::from("a"), Value::TextBuffer("a".to_string()));
+        assert_eq!(
+            Value::from("a".to_string()),
+            Value::TextBuffer("a".to_string())
+        );
+        assert_eq!(Value::from(1), Value::Int(1));
+        assert_eq!(Value::from((1, 2)), Value::IntPair(1, 2));
+        assert_eq!(Value::from((1, 2, 3)), Value::IntRange(1, 2, 3));
+        assert_eq!(Value::from(1.5), Value::Float(1.5));
+        assert_eq!(Value::from((1.5, 2.5)), Value::FloatPair(1.5, 2.5));
+        assert_eq!(
+            Value::from((1.5, 2.5, 3.5)),
+            Value::FloatRange(1.5, 2.5, 3.5)
+        );
+        assert_eq!(Value::from(vec![1u8, 2]), Value::BinaryVector(vec![1, 2]));
+        assert_eq!(Value::from(&[1u8, 2][..]), Value::BinaryVector(vec![1, 2]));
+        assert_eq!(Value::from(7u64), Value::Reference(7));
+
+        assert_eq!(bool::try_from(&Value::Bool(true)), Ok(true));
+        assert_eq!(i32::try_from(&Value::Int(1)), Ok(1));
+        assert_eq!(<(i32, i32)>::try_from(&Value::IntPair(1, 2)), Ok((1, 2)));
+        assert_eq!(
+            <(i32, i32, i32)>::try_from(&Value::IntRange(1, 2, 3)),
+            Ok((1, 2, 3))
+        );
+        assert_eq!(f32::try_from(&Value::Float(1.5)), Ok(1.5));
+        assert_eq!(
+            <(f32, f32)>::try_from(&Value::FloatPair(1.5, 2.5)),
+            Ok((1.5, 2.5))
+        );
+        assert_eq!(
+            <(f32, f32, f32)>::try_from(&Value::FloatRange(1.5, 2.5, 3.5)),
+            Ok((1.5, 2.5, 3.5))
+        );
+        assert_eq!(u64::try_from(&Value::Reference(7)), Ok(7));
+        assert_eq!(
+            Vec::<u8>::try_from(&Value::BinaryVector(vec![1])),
+            Ok(vec![1])
+        );
+        assert_eq!(
+            <&[u8]>::try_from(&Value::BinaryVector(vec![1])),
+            Ok(&[1u8][..])
+        );
+
+        let symbol = Value::Symbol("s".to_string());
+        assert_eq!(String::try_from(&symbol), Ok("s".to_string()));
+        assert_eq!(
+            <&str>::try_from(&Value::TextBuffer("t".to_string())),
+            Ok("t")
+        );
+    }
+
+    #[test]
+    fn values_of_another_kind_are_mismatched() {
+        assert_eq!(
+            i32::try_from(&Value::Float(1.0)),
+            Err(ConversionError::Mismatch {
+                expected: ValueKind::Int,
+                found: ValueKind::Float,
+            })
+        );
+        assert_eq!(
+            <(f32, f32)>::try_from(&Value::IntPair(1, 2)),
+            Err(ConversionError::Mismatch {
+                expected: ValueKind::FloatPair,
+                found: ValueKind::IntPair,
+            })
+        );
+        assert_eq!(
+            String::try_from(&Value::BinaryVector(b"a".to_vec())),
+            Err(ConversionError::Mismatch {
+                expected: ValueKind::TextBuffer,
+                found: ValueKind::BinaryVector,
+            })
+        );
+    }
+
+    #[test]
+    fn attributes_get_and_set_their_value() {
+        let mut attribute = Attribute::new(1, "size", Value::Empty);
+        attribute.set((2, 3));
+        assert_eq!(attribute.value(), &Value::IntPair(2, 3));
+        assert_eq!(attribute.get::<(i32, i32)>(), Ok((2, 3)));
+        assert!(attribute.get::<i32>().is_err());
+
+        attribute.edit_self();
+        attribute.set("label");
+        assert_eq!(attribute.get::<&str>(), Ok("label"));
+    }
+
+    #[test]
+    fn text_and_binary_coerce_both_ways() {
+        let text = Value::TextBuffer("héllo".to_string());
+        let bytes = Value::BinaryVector("héllo".as_bytes().to_vec());
+        assert_eq!(text.coerce(ValueKind::BinaryVector), Ok(bytes.clone()));
+        assert_eq!(bytes.coerce(ValueKind::TextBuffer), Ok(text));
+        assert!(matches!(
+            Value::BinaryVector(vec![0xFF]).coerce(ValueKind::TextBuffer),
+            Err(ConversionError::Invalid {
+                to: ValueKind::TextBuffer,
+                ..
+            })
+        ));
+
+        let symbol = Value::Symbol("s".to_string());
+        assert_eq!(
+            symbol.coerce(ValueKind::TextBuffer),
+            Ok(Value::TextBuffer("s".to_string()))
+        );
+        assert_eq!(
+            Value::TextBuffer("s".to_string()).coerce(ValueKind::Symbol),
+            Ok(symbol)
+        );
+    }
+
+    #[test]
+    fn text_is_parsed_and_printed() {
+        let text = |t: &str| Value::TextBuffer(t.to_string());
+        assert_eq!(text(" 42 ").coerce(ValueKind::Int), Ok(Value::Int(42)));
+        assert_eq!(text("1.5").coerce(ValueKind::Float), Ok(Value::Float(1.5)));
+        assert_eq!(text("true").coerce(ValueKind::Bool), Ok(Value::Bool(true)));
+        assert!(matches!(
+            text("4x").coerce(ValueKind::Int),
+            Err(ConversionError::Invalid { .. })
+        ));
+
+        assert_eq!(Value::Int(-1).coerce(ValueKind::TextBuffer), Ok(text("-1")));
+        assert_eq!(
+            Value::Float(0.5).coerce(ValueKind::TextBuffer),
+            Ok(text("0.5"))
+        );
+        assert_eq!(
+            Value::Bool(false).coerce(ValueKind::TextBuffer),
+            Ok(text("false"))
+        );
+    }
+
+    #[test]
+    fn numbers_pairs_and_ranges_coerce() {
+        assert_eq!(Value::Bool(true).coerce(ValueKind::Int), Ok(Value::Int(1)));
+        assert_eq!(
+            Value::Int(0).coerce(ValueKind::Bool),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            Value::Int(3).coerce(ValueKind::Float),
+            Ok(Value::Float(3.0))
+        );
+        assert_eq!(
+            Value::IntPair(1, 2).coerce(ValueKind::FloatPair),
+            Ok(Value::FloatPair(1.0, 2.0))
+        );
+        assert_eq!(
+            Value::IntRange(1, 0, 5).coerce(ValueKind::FloatRange),
+            Ok(Value::FloatRange(1.0, 0.0, 5.0))
+        );
+        assert_eq!(
+            Value::FloatPair(1.0, 2.0).coerce(ValueKind::IntPair),
+            Err(ConversionError::Unsupported {
+                from: ValueKind::FloatPair,
+                to: ValueKind::IntPair,
+            })
+        );
+        assert_eq!(
+            Value::IntPair(1, 2).coerce(ValueKind::IntRange),
+            Err(ConversionError::Unsupported {
+                from: ValueKind::IntPair,
+                to: ValueKind::IntRange,
+            })
+        );
+    }
+
+    #[test]
+    fn anything_coerces_to_its_own_kind_a_reference_or_empty() {
+        let value = Value::FloatRange(1.0, 0.0, 2.0);
+        assert_eq!(value.coerce(ValueKind::FloatRange), Ok(value.clone()));
+        assert_eq!(value.coerce(ValueKind::Reference), Ok(value.to_ref()));
+        assert_eq!(value.coerce(ValueKind::Empty), Ok(Value::Empty));
+    }
+}
@@ -0,0 +1,286 @@
+use specs::storage::DenseVecStorage;
+use specs::Component;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Expr;
+use super::ExprError;
+use super::Extension;
+use super::Subscription;
+
+/// Component that computes the value of the attribute on the same entity from an expression
+///
+/// Names in the expression refer to the attributes with the same `Attribute::id` as the derived attribute
+#[derive(Debug, Clone, Component)]
+#[storage(DenseVecStorage)]
+pub struct Derived {
+    source: String,
+    expr: Expr,
+    /// error from the last evaluation, if any
+    pub error: Option<ExprError>,
+}
+
+impl Derived {
+    /// parses `source` as the expression for this derived attribute
+    pub fn new(source: impl AsRef<str>) -> Result<Self, ExprError> {
+        Ok(Self {
+            source: source.as_ref().to_string(),
+            expr: Expr::parse(source.as_ref())?,
+            error: None,
+        })
+    }
+
+    /// the expression as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// the parsed expression
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// Evaluates every derived attribute in `world` so that each one is evaluated after the derived attributes it reads
+    ///
+    /// Derived attributes that depend on each other are not evaluated, and get an `ExprError::Cycle` error.
+    /// The value of an attribute is only written if it changed
+    pub fn evaluate(world: &World) {
+        let entities = world.entities();
+        let mut attributes = world.write_component::<Attribute>();
+        let mut derived = world.write_component::<Derived>();
+
+        let index = (&entities, &attributes)
+            .join()
+            .map(|(e, a)| ((a.id(), a.name().to_string()), e))
+            .collect::<BTreeMap<_, _>>();
+
+        // edges from each derived attribute to the derived attributes it reads
+        let mut nodes = BTreeMap::<Entity, (u32, String, BTreeSet<Entity>)>::new();
+        for (entity, attribute, d) in (&entities, &attributes, &derived).join() {
+            nodes.insert(
+                entity,
+                (
+                    attribute.id(),
+                    attribute.name().to_string(),
+                    d.expr
+                        .dependencies()
+                        .into_iter()
+                        .filter_map(|name| index.get(&(attribute.id(), name)).copied())
+                        .collect(),
+                ),
+            );
+        }
+        for (_, _, inputs) in nodes.values_mut() {
+            inputs.retain(|input| derived.contains(*input));
+        }
+
+        let mut order = vec![];
+        let mut ready = nodes
+            .iter()
+            .filter(|(_, (_, _, inputs))| inputs.is_empty())
+            .map(|(e, _)| *e)
+            .collect::<Vec<_>>();
+        let mut remaining = nodes.clone();
+        while let Some(entity) = ready.pop() {
+            remaining.remove(&entity);
+            order.push(entity);
+            for (e, (_, _, inputs)) in remaining.iter_mut() {
+                if inputs.remove(&entity) && inputs.is_empty() {
+                    ready.push(*e);
+                }
+            }
+        }
+
+        if !remaining.is_empty() {
+            let names = remaining
+                .values()
+                .map(|(_, name, _)| name.to_string())
+                .collect::<Vec<_>>();
+            for entity in remaining.keys() {
+                if let Some(d) = derived.get_mut(*entity) {
+                    d.error = Some(ExprError::Cycle(names.clone()));
+                }
+            }
+        }
+
+        for entity in order {
+            let (id, _, _) = &nodes[&entity];
+            let d = match derived.get_mut(entity) {
+                Some(d) => d,
+                None => continue,
+            };
+
+            let lookup = |name: &str| {
+                index
+                    .get(&(*id, name.to_string()))
+                    .and_then(|e| attributes.get(*e))
+                    .map(|a| a.value().clone())
+            };
+
+            match d.expr.eval(&lookup) {
+                Ok(value) => {
+                    d.error = None;
+                    if attributes.get(entity).is_some_and(|a| *a.value() != value) {
+                        if let Some(attribute) = attributes.get_mut(entity) {
+                            attribute.value = value;
+                        }
+                    }
+                }
+                Err(err) => d.error = Some(err),
+            }
+        }
+    }
+}
+
+/// Extension that re-evaluates derived attributes when the attributes in the app world change
+#[derive(Default)]
+pub struct Derivations {
+    subscription: Option<Subscription>,
+    /// hash of the expressions that were last evaluated
+    derived: u64,
+}
+
+impl Extension for Derivations {
+    fn configure_app_world(world: &mut World) {
+        ChangeDetection::configure_app_world(world);
+        world.register::<Derived>();
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        let changed = {
+            let mut events = app_world.write_resource::<AttributeEvents>();
+            events.update(
+                &app_world.entities(),
+                &app_world.read_storage::<Attribute>(),
+            );
+
+            match self.subscription {
+                Some(subscription) => !events.drain(subscription).is_empty(),
+                None => {
+                    self.subscription = Some(events.subscribe(AttributeFilter::Any));
+                    true
+                }
+            }
+        };
+
+        let mut hasher = DefaultHasher::new();
+        for (entity, d) in (
+            &app_world.entities(),
+            &app_world.read_component::<Derived>(),
+        )
+            .join()
+        {
+            entity.hash(&mut hasher);
+            d.source.hash(&mut hasher);
+        }
+        let derived = hasher.finish();
+
+        if changed || derived != self.derived {
+            self.derived = derived;
+            Derived::evaluate(app_world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Derived;
+    use crate::system::Attribute;
+    use crate::system::ExprError;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::Entity;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        world.register::<Derived>();
+        world
+    }
+
+    fn derive(world: &mut World, name: &str, source: &str) -> Entity {
+        world
+            .create_entity()
+            .with(Attribute::new(0, name, Value::Empty))
+            .with(Derived::new(source).expect("expression is valid"))
+            .build()
+    }
+
+    fn value(world: &World, entity: Entity) -> Value {
+        world
+            .read_component::<Attribute>()
+            .get(entity)
+            .unwrap()
+            .value()
+            .clone()
+    }
+
+    fn error(world: &World, entity: Entity) -> Option<ExprError> {
+        world
+            .read_component::<Derived>()
+            .get(entity)
+            .unwrap()
+            .error
+            .clone()
+    }
+
+    #[test]
+    fn derived_attributes_are_evaluated_after_their_inputs() {
+        let mut world = world();
+        // created before its input, so that it's not evaluated first by accident
+        let total = derive(&mut world, "total", "double + 1");
+        let double = derive(&mut world, "double", "width * 2");
+        world
+            .create_entity()
+            .with(Attribute::new(0, "width", Value::Int(3)))
+            .build();
+
+        Derived::evaluate(&world);
+        assert_eq!(value(&world, double), Value::Int(6));
+        assert_eq!(value(&world, total), Value::Int(7));
+        assert_eq!(error(&world, total), None);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let mut world = world();
+        let a = derive(&mut world, "a", "b + 1");
+        let b = derive(&mut world, "b", "a + 1");
+        let c = derive(&mut world, "c", "1");
+
+        Derived::evaluate(&world);
+        let cycle = Some(ExprError::Cycle(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(error(&world, a), cycle);
+        assert_eq!(error(&world, b), cycle);
+        assert_eq!(value(&world, a), Value::Empty);
+        assert_eq!(value(&world, c), Value::Int(1));
+    }
+
+    #[test]
+    fn evaluation_errors_are_kept_on_the_component() {
+        let mut world = world();
+        let missing = derive(&mut world, "missing", "height * 2");
+        let zero = derive(&mut world, "zero", "1 / 0");
+
+        Derived::evaluate(&world);
+        assert_eq!(
+            error(&world, missing),
+            Some(ExprError::UnknownAttribute("height".to_string()))
+        );
+        assert_eq!(error(&world, zero), Some(ExprError::DivideByZero));
+        assert_eq!(value(&world, zero), Value::Empty);
+    }
+}
@@ -0,0 +1,367 @@
+use regex::Regex;
+use specs::shrev::ReaderId;
+use specs::storage::ComponentEvent;
+use specs::world::EntitiesRes;
+use specs::Entity;
+use specs::Join;
+use specs::ReadStorage;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use super::Attribute;
+use super::Extension;
+
+/// Kind of change to the `Attribute` storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttributeEventKind {
+    Inserted,
+    /// the name, value, or pending changes of the attribute changed
+    Modified,
+    Removed,
+}
+
+/// A change to the attribute stored on an entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeEvent {
+    pub kind: AttributeEventKind,
+    /// id of the entity the attribute is stored on
+    pub entity: u32,
+    /// name of the attribute, for removed attributes this is the last known name
+    pub name: String,
+    /// previous name of the attribute, if it was renamed
+    pub renamed_from: Option<String>,
+}
+
+/// Selects which events a subscription receives
+#[derive(Debug, Clone)]
+pub enum AttributeFilter {
+    /// every event
+    Any,
+    /// events for attributes with a name that matches the pattern, renamed attributes match either name
+    Name(Regex),
+    /// events for the attribute stored on an entity
+    Entity(Entity),
+}
+
+impl AttributeFilter {
+    /// returns a filter that matches attribute names with `pattern`
+    pub fn name(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(AttributeFilter::Name(Regex::new(pattern)?))
+    }
+
+    /// returns true if `event` passes this filter
+    pub fn matches(&self, event: &AttributeEvent) -> bool {
+        match self {
+            AttributeFilter::Any => true,
+            AttributeFilter::Name(pattern) => {
+                pattern.is_match(&event.name)
+                    || event
+                        .renamed_from
+                        .as_ref()
+                        .is_some_and(|from| pattern.is_match(from))
+            }
+            AttributeFilter::Entity(entity) => entity.id() == event.entity,
+        }
+    }
+}
+
+/// Handle to a subscription registered with `AttributeEvents`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Subscription(u64);
+
+/// Resource that turns the component events of the `Attribute` storage into `AttributeEvent`s,
+/// and queues them for each subscription with a matching filter
+///
+/// Storage events are flagged whenever an attribute is borrowed mutably, modified events are only
+/// queued when the attribute actually changed
+#[derive(Debug)]
+pub struct AttributeEvents {
+    reader: ReaderId<ComponentEvent>,
+    known: HashMap<u32, (String, u64)>,
+    subscriptions: BTreeMap<Subscription, (AttributeFilter, Vec<AttributeEvent>)>,
+    next: u64,
+}
+
+impl AttributeEvents {
+    /// registers a reader on the `Attribute` storage of `world`, attributes that already exist are not reported
+    pub fn new(world: &World) -> Self {
+        let mut attributes = world.write_storage::<Attribute>();
+        let reader = attributes.register_reader();
+        let known = (&world.entities(), &attributes)
+            .join()
+            .map(|(e, a)| (e.id(), (a.name().to_string(), hash(a))))
+            .collect();
+
+        Self {
+            reader,
+            known,
+            subscriptions: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// starts queuing events that pass `filter`
+    pub fn subscribe(&mut self, filter: AttributeFilter) -> Subscription {
+        let subscription = Subscription(self.next);
+        self.next += 1;
+        self.subscriptions.insert(subscription, (filter, vec![]));
+        subscription
+    }
+
+    /// stops queuing events for `subscription`
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        self.subscriptions.remove(&subscription);
+    }
+
+    /// takes the events queued for `subscription` since the last call, oldest first
+    pub fn drain(&mut self, subscription: Subscription) -> Vec<AttributeEvent> {
+        self.subscriptions
+            .get_mut(&subscription)
+            .map(|(_, queue)| std::mem::take(queue))
+            .unwrap_or_default()
+    }
+
+    /// Reads the events of the `Attribute` storage since the last update, and queues them for each subscription
+    ///
+    /// This is called by `ChangeDetection` after the app world is maintained,
+    /// systems that need events sooner can call this with their own system data
+    pub fn update(&mut self, entities: &EntitiesRes, attributes: &ReadStorage<Attribute>) {
+        let mut events = vec![];
+        for event in attributes.channel().read(&mut self.reader) {
+            let (kind, index) = match event {
+                ComponentEvent::Inserted(index) => (AttributeEventKind::Inserted, *index),
+                ComponentEvent::Modified(index) => (AttributeEventKind::Modified, *index),
+                ComponentEvent::Removed(index) => (AttributeEventKind::Removed, *index),
+            };
+
+            let current = attributes.get(entities.entity(index));
+            let event = match (kind, current) {
+                (AttributeEventKind::Removed, _) | (_, None) => {
+                    self.known.remove(&index).map(|(name, _)| AttributeEvent {
+                        kind: AttributeEventKind::Removed,
+                        entity: index,
+                        name,
+                        renamed_from: None,
+                    })
+                }
+                (kind, Some(attribute)) => {
+                    let name = attribute.name().to_string();
+                    let previous = self.known.insert(index, (name.clone(), hash(attribute)));
+                    match (kind, previous) {
+                        (AttributeEventKind::Modified, Some((_, h))) if h == hash(attribute) => {
+                            None
+                        }
+                        (_, previous) => Some(AttributeEvent {
+                            kind: if previous.is_some() && kind == AttributeEventKind::Inserted {
+                                // replacing an attribute with insert is reported as a modification
+                                AttributeEventKind::Modified
+                            } else {
+                                kind
+                            },
+                            entity: index,
+                            renamed_from: previous
+                                .map(|(from, _)| from)
+                                .filter(|from| *from != name),
+                            name,
+                        }),
+                    }
+                }
+            };
+
+            if let Some(event) = event {
+                events.push(event);
+            }
+        }
+
+        for event in events {
+            for (filter, queue) in self.subscriptions.values_mut() {
+                if filter.matches(&event) {
+                    queue.push(event.clone());
+                }
+            }
+        }
+    }
+}
+
+fn hash(attribute: &Attribute) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    attribute.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Extension that adds an `AttributeEvents` resource to the app world, and updates it after the world is maintained
+#[derive(Default)]
+pub struct ChangeDetection;
+
+impl Extension for ChangeDetection {
+    fn configure_app_world(world: &mut World) {
+        world.register::<Attribute>();
+        if !world.has_value::<AttributeEvents>() {
+            let events = AttributeEvents::new(world);
+            world.insert(events);
+        }
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        app_world.write_resource::<AttributeEvents>().update(
+            &app_world.entities(),
+            &app_world.read_storage::<Attribute>(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AttributeEvent;
+    use super::AttributeEventKind;
+    use super::AttributeEvents;
+    use super::AttributeFilter;
+    use crate::system::Attribute;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn update(world: &World, events: &mut AttributeEvents) {
+        events.update(&world.entities(), &world.read_storage::<Attribute>());
+    }
+
+    fn event(kind: AttributeEventKind, entity: u32, name: &str) -> AttributeEvent {
+        AttributeEvent {
+            kind,
+            entity,
+            name: name.to_string(),
+            renamed_from: None,
+        }
+    }
+
+    #[test]
+    fn filters_match_names_renames_and_entities() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let entity = world.create_entity().build();
+        let other = world.create_entity().build();
+
+        let mut renamed = event(AttributeEventKind::Modified, entity.id(), "size");
+        renamed.renamed_from = Some("width".to_string());
+
+        let width = AttributeFilter::name("^width$").unwrap();
+        assert!(width.matches(&renamed));
+        assert!(AttributeFilter::name("^size$").unwrap().matches(&renamed));
+        assert!(!AttributeFilter::name("^height$").unwrap().matches(&renamed));
+        assert!(AttributeFilter::Entity(entity).matches(&renamed));
+        assert!(!AttributeFilter::Entity(other).matches(&renamed));
+        assert!(AttributeFilter::Any.matches(&renamed));
+        assert!(AttributeFilter::name("(").is_err());
+    }
+
+    #[test]
+    fn storage_events_become_attribute_events() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let existing = world
+            .create_entity()
+            .with(Attribute::new(0, "existing", Value::Int(0)))
+            .build();
+        let mut events = AttributeEvents::new(&world);
+        let all = events.subscribe(AttributeFilter::Any);
+
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        update(&world, &mut events);
+        assert_eq!(
+            events.drain(all),
+            [event(AttributeEventKind::Inserted, entity.id(), "a")],
+            "attributes that existed before are not reported"
+        );
+
+        // borrowing mutably flags the attribute, without changing it
+        world.write_storage::<Attribute>().get_mut(existing);
+        update(&world, &mut events);
+        assert_eq!(events.drain(all), []);
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .edit_as(Value::Int(2));
+        update(&world, &mut events);
+        assert_eq!(
+            events.drain(all),
+            [event(AttributeEventKind::Modified, entity.id(), "a")],
+            "pending changes are modifications"
+        );
+
+        world
+            .write_storage::<Attribute>()
+            .insert(entity, Attribute::new(0, "b", Value::Int(3)))
+            .unwrap();
+        update(&world, &mut events);
+        let mut renamed = event(AttributeEventKind::Modified, entity.id(), "b");
+        renamed.renamed_from = Some("a".to_string());
+        assert_eq!(
+            events.drain(all),
+            [renamed],
+            "inserting over an attribute renames it"
+        );
+
+        world.write_storage::<Attribute>().remove(entity);
+        world.delete_entity(existing).unwrap();
+        world.maintain();
+        update(&world, &mut events);
+        assert_eq!(
+            events.drain(all),
+            [
+                event(AttributeEventKind::Removed, entity.id(), "b"),
+                event(AttributeEventKind::Removed, existing.id(), "existing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn each_subscription_has_its_own_queue() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let mut events = AttributeEvents::new(&world);
+        let all = events.subscribe(AttributeFilter::Any);
+        let b = events.subscribe(AttributeFilter::name("^b$").unwrap());
+
+        let first = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        let second = world
+            .create_entity()
+            .with(Attribute::new(0, "b", Value::Int(1)))
+            .build();
+        update(&world, &mut events);
+
+        assert_eq!(
+            events.drain(all),
+            [
+                event(AttributeEventKind::Inserted, first.id(), "a"),
+                event(AttributeEventKind::Inserted, second.id(), "b"),
+            ]
+        );
+        assert_eq!(events.drain(all), [], "drained events are not queued again");
+
+        let late = events.subscribe(AttributeFilter::Any);
+        events.unsubscribe(b);
+        world.write_storage::<Attribute>().remove(second);
+        update(&world, &mut events);
+
+        assert_eq!(events.drain(b), []);
+        assert_eq!(
+            events.drain(late),
+            [event(AttributeEventKind::Removed, second.id(), "b")],
+            "events are queued from when the subscription started"
+        );
+    }
+}
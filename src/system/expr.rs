@@ -0,0 +1,832 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use super::ConversionError;
+use super::Value;
+use super::ValueKind;
+
+/// Error returned when an expression can't be parsed or evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    /// the expression is not valid, `position` is the byte offset of the problem
+    Parse {
+        position: usize,
+        message: String,
+    },
+    /// no attribute with this name was found
+    UnknownAttribute(String),
+    UnknownFunction(String),
+    /// wrong number of arguments were passed to a function
+    Arity {
+        function: String,
+        expected: String,
+    },
+    /// an operator or function can't be applied to these values
+    Type {
+        operation: String,
+        found: String,
+    },
+    DivideByZero,
+    Overflow,
+    Conversion(ConversionError),
+    /// the derived attributes depend on each other
+    Cycle(Vec<String>),
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprError::Parse { position, message } => {
+                write!(f, "parse error at {}, {}", position, message)
+            }
+            ExprError::UnknownAttribute(name) => write!(f, "unknown attribute '{}'", name),
+            ExprError::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            ExprError::Arity { function, expected } => {
+                write!(f, "{} expects {} arguments", function, expected)
+            }
+            ExprError::Type { operation, found } => {
+                write!(f, "can't {} {}", operation, found)
+            }
+            ExprError::DivideByZero => write!(f, "divide by zero"),
+            ExprError::Overflow => write!(f, "integer overflow"),
+            ExprError::Conversion(err) => write!(f, "{}", err),
+            ExprError::Cycle(names) => write!(f, "cycle between {}", names.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl From<ConversionError> for ExprError {
+    fn from(err: ConversionError) -> Self {
+        ExprError::Conversion(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+/// An expression over the values of attributes
+///
+/// Supports literals (`1`, `1.5`, `"text"`, `true`), attribute names (`width`, or `` `any name` `` for names
+/// with other characters), tuples of 2 or 3 numbers, arithmetic (`+ - * / %`) on ints, floats, pairs and ranges,
+/// `+` on text, comparisons, `&& || !`, and the functions
+/// `concat`, `len`, `upper`, `lower`, `min`, `max`, `abs`, `if`, `str`, `int`, and `float`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Attribute(String),
+    Tuple(Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// parses an expression
+    pub fn parse(source: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+            len: source.len(),
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            Some((position, token)) => Err(ExprError::Parse {
+                position: *position,
+                message: format!("unexpected {:?}", token),
+            }),
+            None => Ok(expr),
+        }
+    }
+
+    /// names of the attributes this expression reads
+    pub fn dependencies(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_dependencies(&mut names);
+        names
+    }
+
+    fn collect_dependencies(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Attribute(name) => {
+                names.insert(name.to_string());
+            }
+            Expr::Unary(_, expr) => expr.collect_dependencies(names),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.collect_dependencies(names);
+                rhs.collect_dependencies(names);
+            }
+            Expr::Tuple(exprs) | Expr::Call(_, exprs) => {
+                for expr in exprs {
+                    expr.collect_dependencies(names);
+                }
+            }
+        }
+    }
+
+    /// evaluates this expression, `lookup` returns the value of the attribute with a name
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Value, ExprError> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Attribute(name) => {
+                lookup(name).ok_or_else(|| ExprError::UnknownAttribute(name.to_string()))
+            }
+            Expr::Tuple(exprs) => {
+                let values = exprs
+                    .iter()
+                    .map(|e| e.eval(lookup))
+                    .collect::<Result<Vec<_>, _>>()?;
+                tuple(&values)
+            }
+            Expr::Unary(op, expr) => {
+                let value = expr.eval(lookup)?;
+                match (op, value) {
+                    (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    (UnaryOp::Neg, value) => {
+                        arithmetic(BinaryOp::Sub, &zero_like(&value), &value, "negate")
+                    }
+                    (_, value) => Err(type_error("negate", &[value])),
+                }
+            }
+            Expr::Binary(BinaryOp::And, lhs, rhs) => match lhs.eval(lookup)? {
+                Value::Bool(false) => Ok(Value::Bool(false)),
+                Value::Bool(true) => boolean(rhs.eval(lookup)?, "and"),
+                value => Err(type_error("and", &[value])),
+            },
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => match lhs.eval(lookup)? {
+                Value::Bool(true) => Ok(Value::Bool(true)),
+                Value::Bool(false) => boolean(rhs.eval(lookup)?, "or"),
+                value => Err(type_error("or", &[value])),
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                match op {
+                    BinaryOp::Add => arithmetic(*op, &lhs, &rhs, "add"),
+                    BinaryOp::Sub => arithmetic(*op, &lhs, &rhs, "subtract"),
+                    BinaryOp::Mul => arithmetic(*op, &lhs, &rhs, "multiply"),
+                    BinaryOp::Div => arithmetic(*op, &lhs, &rhs, "divide"),
+                    BinaryOp::Rem => arithmetic(*op, &lhs, &rhs, "take the remainder of"),
+                    _ => compare(*op, &lhs, &rhs),
+                }
+            }
+            Expr::Call(function, args) => call(function, args, lookup),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i32),
+    Float(f32),
+    Text(String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+const OPERATORS: [&str; 16] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "=", "|",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let error = |position: usize, message: &str| ExprError::Parse {
+        position,
+        message: message.to_string(),
+    };
+
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, c)) => text.push(c),
+                            None => return Err(error(start, "unterminated string")),
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => return Err(error(start, "unterminated string")),
+                    }
+                }
+                Token::Text(text)
+            }
+            '`' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '`')) => break,
+                        Some((_, c)) => name.push(c),
+                        None => return Err(error(start, "unterminated name")),
+                    }
+                }
+                Token::Ident(name)
+            }
+            c if c.is_ascii_digit() => {
+                let mut end = start + 1;
+                let mut float = false;
+                while let Some((i, c)) = chars.peek() {
+                    if c.is_ascii_digit() || (*c == '.' && !float) {
+                        float |= *c == '.';
+                        end = i + 1;
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let number = &source[start..end];
+                if float {
+                    Token::Float(number.parse().map_err(|_| error(start, "invalid float"))?)
+                } else {
+                    Token::Int(
+                        number
+                            .parse()
+                            .map_err(|_| error(start, "int is out of range"))?,
+                    )
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.peek() {
+                    if c.is_alphanumeric() || *c == '_' || *c == '.' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Token::Ident(source[start..end].to_string())
+            }
+            _ => {
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| source[start..].starts_with(*op))
+                    .ok_or_else(|| error(start, &format!("unexpected character '{}'", c)))?;
+                if *op == "=" || *op == "|" {
+                    return Err(error(start, &format!("unexpected character '{}'", c)));
+                }
+                for _ in 1..op.len() {
+                    chars.next();
+                }
+                Token::Op(op)
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.next)
+    }
+
+    fn position(&self) -> usize {
+        self.peek().map(|(p, _)| *p).unwrap_or(self.len)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek().is_some_and(|(_, t)| t == token) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ExprError> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(ExprError::Parse {
+                position: self.position(),
+                message: format!("expected {:?}", token),
+            })
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinaryOp)],
+        next: fn(&mut Self) -> Result<Expr, ExprError>,
+        repeat: bool,
+    ) -> Result<Expr, ExprError> {
+        let mut lhs = next(self)?;
+        while let Some((_, Token::Op(op))) = self.peek() {
+            let op = match ops.iter().find(|(o, _)| o == op) {
+                Some((_, op)) => *op,
+                None => break,
+            };
+            self.next += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(next(self)?));
+            if !repeat {
+                break;
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("||", BinaryOp::Or)], Self::and, true)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&[("&&", BinaryOp::And)], Self::comparison, true)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExprError> {
+        self.binary(
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                ("<", BinaryOp::Lt),
+                ("<=", BinaryOp::Le),
+                (">", BinaryOp::Gt),
+                (">=", BinaryOp::Ge),
+            ],
+            Self::sum,
+            false,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        self.binary(
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            Self::product,
+            true,
+        )
+    }
+
+    fn product(&mut self) -> Result<Expr, ExprError> {
+        self.binary(
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+            Self::unary,
+            true,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat(&Token::Op("-")) {
+            Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.unary()?)))
+        } else if self.eat(&Token::Op("!")) {
+            Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        let position = self.position();
+        let (_, token) = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or(ExprError::Parse {
+                position,
+                message: "unexpected end of expression".to_string(),
+            })?;
+        self.next += 1;
+
+        match token {
+            Token::Int(i) => Ok(Expr::Literal(Value::Int(i))),
+            Token::Float(f) => Ok(Expr::Literal(Value::Float(f))),
+            Token::Text(text) => Ok(Expr::Literal(Value::TextBuffer(text))),
+            Token::Ident(name) if name == "true" => Ok(Expr::Literal(Value::Bool(true))),
+            Token::Ident(name) if name == "false" => Ok(Expr::Literal(Value::Bool(false))),
+            Token::Ident(name) if self.eat(&Token::Open) => {
+                let args = self.list()?;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Ident(name) => Ok(Expr::Attribute(name)),
+            Token::Open => {
+                let mut exprs = self.list()?;
+                match exprs.len() {
+                    1 => Ok(exprs.remove(0)),
+                    2 | 3 => Ok(Expr::Tuple(exprs)),
+                    _ => Err(ExprError::Parse {
+                        position,
+                        message: "tuples must have 2 or 3 values".to_string(),
+                    }),
+                }
+            }
+            token => Err(ExprError::Parse {
+                position,
+                message: format!("unexpected {:?}", token),
+            }),
+        }
+    }
+
+    /// parses a comma separated list of expressions after an opening parenthesis
+    fn list(&mut self) -> Result<Vec<Expr>, ExprError> {
+        let mut exprs = vec![];
+        if self.eat(&Token::Close) {
+            return Ok(exprs);
+        }
+        loop {
+            exprs.push(self.or()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::Close)?;
+        Ok(exprs)
+    }
+}
+
+fn type_error(operation: &str, values: &[Value]) -> ExprError {
+    ExprError::Type {
+        operation: operation.to_string(),
+        found: values
+            .iter()
+            .map(|v| v.kind().to_string())
+            .collect::<Vec<_>>()
+            .join(" and "),
+    }
+}
+
+fn boolean(value: Value, operation: &str) -> Result<Value, ExprError> {
+    match value {
+        Value::Bool(_) => Ok(value),
+        value => Err(type_error(operation, &[value])),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(i32),
+    Float(f32),
+}
+
+impl Number {
+    fn float(self) -> f32 {
+        match self {
+            Number::Int(i) => i as f32,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+/// returns the components of a numeric value
+fn numbers(value: &Value) -> Option<Vec<Number>> {
+    Some(match value {
+        Value::Int(i) => vec![Number::Int(*i)],
+        Value::Float(f) => vec![Number::Float(*f)],
+        Value::IntPair(a, b) => vec![Number::Int(*a), Number::Int(*b)],
+        Value::FloatPair(a, b) => vec![Number::Float(*a), Number::Float(*b)],
+        Value::IntRange(a, b, c) => vec![Number::Int(*a), Number::Int(*b), Number::Int(*c)],
+        Value::FloatRange(a, b, c) => {
+            vec![Number::Float(*a), Number::Float(*b), Number::Float(*c)]
+        }
+        _ => return None,
+    })
+}
+
+/// returns the value with the same shape as `numbers`
+fn from_numbers(numbers: &[Number]) -> Option<Value> {
+    let ints = numbers
+        .iter()
+        .map(|n| match n {
+            Number::Int(i) => Some(*i),
+            Number::Float(_) => None,
+        })
+        .collect::<Option<Vec<_>>>();
+
+    Some(match (ints, numbers.len()) {
+        (Some(i), 1) => Value::Int(i[0]),
+        (Some(i), 2) => Value::IntPair(i[0], i[1]),
+        (Some(i), 3) => Value::IntRange(i[0], i[1], i[2]),
+        (None, 1) => Value::Float(numbers[0].float()),
+        (None, 2) => Value::FloatPair(numbers[0].float(), numbers[1].float()),
+        (None, 3) => Value::FloatRange(numbers[0].float(), numbers[1].float(), numbers[2].float()),
+        _ => return None,
+    })
+}
+
+fn tuple(values: &[Value]) -> Result<Value, ExprError> {
+    let numbers = values
+        .iter()
+        .map(|v| match v {
+            Value::Int(i) => Some(Number::Int(*i)),
+            Value::Float(f) => Some(Number::Float(*f)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+
+    numbers
+        .and_then(|n| from_numbers(&n))
+        .ok_or_else(|| type_error("make a tuple of", values))
+}
+
+fn zero_like(value: &Value) -> Value {
+    match numbers(value) {
+        Some(numbers) => from_numbers(
+            &numbers
+                .iter()
+                .map(|n| match n {
+                    Number::Int(_) => Number::Int(0),
+                    Number::Float(_) => Number::Float(0.0),
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap_or(Value::Int(0)),
+        None => Value::Int(0),
+    }
+}
+
+fn as_text(value: &Value) -> Option<&str> {
+    match value {
+        Value::TextBuffer(text) | Value::Symbol(text) => Some(text),
+        _ => None,
+    }
+}
+
+/// text used for a value by `concat` and `str`
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::TextBuffer(text) | Value::Symbol(text) => text.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Empty => String::default(),
+        _ => format!("{:?}", value),
+    }
+}
+
+/// applies an arithmetic operator component-wise, a scalar is applied to every component of a pair or range
+fn arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value, operation: &str) -> Result<Value, ExprError> {
+    if let (BinaryOp::Add, Some(lhs), Some(rhs)) = (op, as_text(lhs), as_text(rhs)) {
+        return Ok(Value::TextBuffer(format!("{}{}", lhs, rhs)));
+    }
+
+    let (a, b) = match (numbers(lhs), numbers(rhs)) {
+        (Some(a), Some(b)) if a.len() == b.len() => (a, b),
+        (Some(a), Some(b)) if b.len() == 1 => (a.clone(), vec![b[0]; a.len()]),
+        (Some(a), Some(b)) if a.len() == 1 => (vec![a[0]; b.len()], b),
+        _ => return Err(type_error(operation, &[lhs.clone(), rhs.clone()])),
+    };
+
+    let result = a
+        .into_iter()
+        .zip(b)
+        .map(|pair| match pair {
+            (Number::Int(a), Number::Int(b)) => {
+                let result = match op {
+                    BinaryOp::Add => a.checked_add(b),
+                    BinaryOp::Sub => a.checked_sub(b),
+                    BinaryOp::Mul => a.checked_mul(b),
+                    BinaryOp::Div | BinaryOp::Rem if b == 0 => return Err(ExprError::DivideByZero),
+                    BinaryOp::Div => a.checked_div(b),
+                    _ => a.checked_rem(b),
+                };
+                result.map(Number::Int).ok_or(ExprError::Overflow)
+            }
+            (a, b) => {
+                let (a, b) = (a.float(), b.float());
+                Ok(Number::Float(match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    _ => a % b,
+                }))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    from_numbers(&result).ok_or_else(|| type_error(operation, &[lhs.clone(), rhs.clone()]))
+}
+
+fn compare(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value, ExprError> {
+    let ordering = match (lhs, rhs) {
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_))
+            if lhs.kind() != rhs.kind() =>
+        {
+            let a = numbers(lhs).expect("value is a number")[0].float();
+            let b = numbers(rhs).expect("value is a number")[0].float();
+            a.total_cmp(&b)
+        }
+        _ if lhs.kind() == rhs.kind() => lhs.cmp(rhs),
+        _ if matches!(op, BinaryOp::Eq | BinaryOp::Ne) => {
+            return Ok(Value::Bool(op == BinaryOp::Ne))
+        }
+        _ => return Err(type_error("compare", &[lhs.clone(), rhs.clone()])),
+    };
+
+    Ok(Value::Bool(match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Ne => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }))
+}
+
+fn call(
+    function: &str,
+    args: &[Expr],
+    lookup: &dyn Fn(&str) -> Option<Value>,
+) -> Result<Value, ExprError> {
+    let arity = |expected: &str| ExprError::Arity {
+        function: function.to_string(),
+        expected: expected.to_string(),
+    };
+
+    // if only evaluates the branch that is taken
+    if function == "if" {
+        return match args {
+            [condition, then, otherwise] => match condition.eval(lookup)? {
+                Value::Bool(true) => then.eval(lookup),
+                Value::Bool(false) => otherwise.eval(lookup),
+                value => Err(type_error("branch on", &[value])),
+            },
+            _ => Err(arity("3")),
+        };
+    }
+
+    let values = args
+        .iter()
+        .map(|a| a.eval(lookup))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match (function, values.as_slice()) {
+        ("concat", values) => Ok(Value::TextBuffer(
+            values.iter().map(to_text).collect::<String>(),
+        )),
+        ("str", [value]) => Ok(Value::TextBuffer(to_text(value))),
+        ("len", [Value::TextBuffer(text) | Value::Symbol(text)]) => {
+            Ok(Value::Int(text.chars().count() as i32))
+        }
+        ("len", [Value::BinaryVector(bytes)]) => Ok(Value::Int(bytes.len() as i32)),
+        ("upper", [Value::TextBuffer(text) | Value::Symbol(text)]) => {
+            Ok(Value::TextBuffer(text.to_uppercase()))
+        }
+        ("lower", [Value::TextBuffer(text) | Value::Symbol(text)]) => {
+            Ok(Value::TextBuffer(text.to_lowercase()))
+        }
+        // unlike `coerce`, int() truncates floats towards zero
+        ("int", [Value::Float(f)]) => Ok(Value::Float(f.trunc()).coerce(ValueKind::Int)?),
+        ("int", [value]) => Ok(value.coerce(ValueKind::Int)?),
+        ("float", [value]) => Ok(value.coerce(ValueKind::Float)?),
+        ("abs", [value]) => match value {
+            Value::Int(i) => i.checked_abs().map(Value::Int).ok_or(ExprError::Overflow),
+            Value::Float(f) => Ok(Value::Float(f.abs())),
+            value => Err(type_error(
+                "take the absolute value of",
+                std::slice::from_ref(value),
+            )),
+        },
+        ("min" | "max", [first, rest @ ..]) => {
+            let mut result = first.clone();
+            for value in rest {
+                let less = compare(BinaryOp::Lt, value, &result)? == Value::Bool(true);
+                if less == (function == "min") && value != &result {
+                    result = value.clone();
+                }
+            }
+            Ok(result)
+        }
+        ("len" | "upper" | "lower", [_]) => {
+            Err(type_error(&format!("call {} with", function), &values))
+        }
+        ("str" | "len" | "upper" | "lower" | "int" | "float" | "abs", _) => Err(arity("1")),
+        ("min" | "max", _) => Err(arity("at least 1")),
+        _ => Err(ExprError::UnknownFunction(function.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+    use super::ExprError;
+    use crate::system::Value;
+
+    fn eval(source: &str) -> Result<Value, ExprError> {
+        let lookup = |name: &str| match name {
+            "width" => Some(Value::Int(4)),
+            "label" => Some(Value::TextBuffer("a".to_string())),
+            _ => None,
+        };
+        Expr::parse(source)?.eval(&lookup)
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(Value::Int(7)));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(Value::Int(9)));
+        assert_eq!(eval("10 - 4 - 3"), Ok(Value::Int(3)));
+        assert_eq!(eval("7 % 4 * 2"), Ok(Value::Int(6)));
+        assert_eq!(eval("-2 * 3"), Ok(Value::Int(-6)));
+        assert_eq!(eval("width * 2 > 7"), Ok(Value::Bool(true)));
+        assert_eq!(eval("!false && 1 < 2"), Ok(Value::Bool(true)));
+        assert_eq!(eval("true || false && false"), Ok(Value::Bool(true)));
+        assert_eq!(
+            eval("label + \"b\""),
+            Ok(Value::TextBuffer("ab".to_string()))
+        );
+    }
+
+    #[test]
+    fn mismatched_types_are_errors() {
+        for source in ["1 + true", "\"a\" - \"b\"", "!1", "1 && true", "false || 1"] {
+            assert!(
+                matches!(eval(source), Err(ExprError::Type { .. })),
+                "{} is a type error, found {:?}",
+                source,
+                eval(source)
+            );
+        }
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        assert_eq!(eval("1 / 0"), Err(ExprError::DivideByZero));
+        assert_eq!(eval("1 % (width - 4)"), Err(ExprError::DivideByZero));
+        assert_eq!(eval("2147483647 + 1"), Err(ExprError::Overflow));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_eq!(
+            eval("height + 1"),
+            Err(ExprError::UnknownAttribute("height".to_string()))
+        );
+        assert_eq!(
+            eval("sqrt(4)"),
+            Err(ExprError::UnknownFunction("sqrt".to_string()))
+        );
+        assert_eq!(
+            Expr::parse("width + height")
+                .unwrap()
+                .dependencies()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["height".to_string(), "width".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_errors_have_a_position() {
+        assert!(matches!(
+            Expr::parse("1 +"),
+            Err(ExprError::Parse { position: 3, .. })
+        ));
+        assert!(matches!(
+            Expr::parse("1 = 2"),
+            Err(ExprError::Parse { position: 2, .. })
+        ));
+        assert!(
+            matches!(
+                Expr::parse("1 < 2 == true"),
+                Err(ExprError::Parse { position: 6, .. })
+            ),
+            "comparisons don't chain"
+        );
+        assert!(matches!(
+            Expr::parse("(1"),
+            Err(ExprError::Parse { position: 2, .. })
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 
 
+#[allow(clippy::manual_ok_err)]
 pub fn cascadia_code() -> Option<Vec<u8>>{
     if let Ok(home) = std::env::var("HOME") {
         let path =&format!("{}/Library/Fonts/CascadiaCode.ttf", home);
@@ -15,6 +16,7 @@ pub fn cascadia_code() -> Option<Vec<u8>>{
     }
 }
 
+#[allow(clippy::manual_ok_err)]
 pub fn monaco() -> Option<Vec<u8>>{
     if let Ok(r) = std::fs::read(std::path::Path::new("/System/Library/Fonts/Monaco.ttf")) {
         Some(r)
@@ -25,6 +27,7 @@ pub fn monaco() -> Option<Vec<u8>>{
     }
 }
 
+#[allow(clippy::manual_ok_err)]
 pub fn segoe_ui() -> Option<Vec<u8>>{
     if let Ok(r) = std::fs::read(std::path::Path::new("/System/Library/Fonts/segoeui.ttf")) {
         Some(r)
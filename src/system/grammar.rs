@@ -0,0 +1,688 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+
+/// Kind of a highlighted token, each kind is drawn with its own color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Literal,
+    Number,
+    String,
+    Comment,
+    Function,
+    Variable,
+    /// brackets, which are matched by the code editor
+    Punctuation,
+    Heading,
+}
+
+impl TokenKind {
+    /// color the code editor draws this kind of token with
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            TokenKind::Keyword => [0.78, 0.47, 0.87, 1.0],
+            TokenKind::Type => [0.35, 0.78, 0.75, 1.0],
+            TokenKind::Literal => [0.35, 0.6, 0.95, 1.0],
+            TokenKind::Number => [0.71, 0.81, 0.66, 1.0],
+            TokenKind::String => [0.81, 0.57, 0.47, 1.0],
+            TokenKind::Comment => [0.42, 0.6, 0.33, 1.0],
+            TokenKind::Function => [0.86, 0.86, 0.67, 1.0],
+            TokenKind::Variable => [0.61, 0.86, 0.99, 1.0],
+            TokenKind::Punctuation => [0.85, 0.85, 0.85, 1.0],
+            TokenKind::Heading => [0.35, 0.6, 0.95, 1.0],
+        }
+    }
+}
+
+/// Highlighted range of text, tokens never span more than one line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+
+/// Delimited part of text that is highlighted as a whole, e.g. a string or a block comment
+#[derive(Debug, Clone)]
+pub struct GrammarSpan {
+    pub open: String,
+    pub close: String,
+    pub kind: TokenKind,
+    /// character that escapes the next character, so it can't close the span
+    pub escape: Option<char>,
+    /// if false the span ends at the end of the line, even if it wasn't closed
+    pub multiline: bool,
+}
+
+/// Severity of a diagnostic, the code editor underlines errors in red and warnings in yellow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Message about a range of text, shown by the code editor as a squiggle with a tooltip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// line of the diagnostic, starting at 1
+    pub line: usize,
+    /// column of the first character that is underlined, starting at 1
+    pub column: usize,
+    /// number of characters that are underlined
+    pub len: usize,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// returns an error that underlines a single character
+    pub fn error(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            len: 1,
+            message: message.into(),
+            severity: Severity::Error,
+        }
+    }
+
+    /// returns a warning that underlines a single character
+    pub fn warning(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(line, column, message)
+        }
+    }
+
+    /// underlines `len` characters
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = len;
+        self
+    }
+}
+
+/// Checks text and returns diagnostics for it, closures that take text and return diagnostics are providers
+pub trait DiagnosticsProvider: Send + Sync {
+    fn diagnostics(&self, text: &str) -> Vec<Diagnostic>;
+}
+
+impl<F> DiagnosticsProvider for F
+where
+    F: Fn(&str) -> Vec<Diagnostic> + Send + Sync,
+{
+    fn diagnostics(&self, text: &str) -> Vec<Diagnostic> {
+        self(text)
+    }
+}
+
+/// Reports syntax errors in JSON
+pub struct JsonDiagnostics;
+
+impl DiagnosticsProvider for JsonDiagnostics {
+    fn diagnostics(&self, text: &str) -> Vec<Diagnostic> {
+        if text.trim().is_empty() {
+            return vec![];
+        }
+
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(_) => vec![],
+            Err(err) => vec![Diagnostic::error(
+                err.line().max(1),
+                err.column().max(1),
+                err.to_string(),
+            )],
+        }
+    }
+}
+
+/// Rules for highlighting a language
+///
+/// Text is highlighted in a single pass, at each position the first rule that matches wins, in this order:
+/// line prefixes at the start of a line, the line comment, spans in the order they were added, variables,
+/// numbers, then words
+#[derive(Clone)]
+pub struct Grammar {
+    pub name: String,
+    /// file extensions, without the leading `.`
+    pub extensions: Vec<String>,
+    pub line_comment: Option<String>,
+    pub spans: Vec<GrammarSpan>,
+    pub keywords: Vec<String>,
+    pub types: Vec<String>,
+    pub literals: Vec<String>,
+    /// lines that start with one of these prefixes, after indentation, are highlighted as a whole
+    pub line_prefixes: Vec<(String, TokenKind)>,
+    /// character that starts a variable, e.g. `$` in shell
+    pub variable_prefix: Option<char>,
+    /// if true, words that start with an uppercase letter are types
+    pub capitalized_types: bool,
+    /// if true, words that are followed by `(` or `!` are functions
+    pub calls: bool,
+    pub diagnostics: Option<Arc<dyn DiagnosticsProvider>>,
+}
+
+impl Debug for Grammar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Grammar")
+            .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Grammar {
+    /// returns a grammar that doesn't highlight anything
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            extensions: vec![],
+            line_comment: None,
+            spans: vec![],
+            keywords: vec![],
+            types: vec![],
+            literals: vec![],
+            line_prefixes: vec![],
+            variable_prefix: None,
+            capitalized_types: false,
+            calls: false,
+            diagnostics: None,
+        }
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions
+            .extend(extensions.iter().map(|e| e.to_string()));
+        self
+    }
+
+    pub fn with_line_comment(mut self, prefix: impl Into<String>) -> Self {
+        self.line_comment = Some(prefix.into());
+        self
+    }
+
+    /// adds a span that is highlighted as `kind`
+    pub fn with_span(
+        mut self,
+        open: impl Into<String>,
+        close: impl Into<String>,
+        kind: TokenKind,
+        escape: Option<char>,
+        multiline: bool,
+    ) -> Self {
+        self.spans.push(GrammarSpan {
+            open: open.into(),
+            close: close.into(),
+            kind,
+            escape,
+            multiline,
+        });
+        self
+    }
+
+    pub fn with_keywords(mut self, keywords: &[&str]) -> Self {
+        self.keywords.extend(keywords.iter().map(|k| k.to_string()));
+        self
+    }
+
+    pub fn with_types(mut self, types: &[&str]) -> Self {
+        self.types.extend(types.iter().map(|t| t.to_string()));
+        self
+    }
+
+    pub fn with_literals(mut self, literals: &[&str]) -> Self {
+        self.literals.extend(literals.iter().map(|l| l.to_string()));
+        self
+    }
+
+    pub fn with_line_prefix(mut self, prefix: impl Into<String>, kind: TokenKind) -> Self {
+        self.line_prefixes.push((prefix.into(), kind));
+        self
+    }
+
+    pub fn with_variable_prefix(mut self, prefix: char) -> Self {
+        self.variable_prefix = Some(prefix);
+        self
+    }
+
+    pub fn with_capitalized_types(mut self) -> Self {
+        self.capitalized_types = true;
+        self
+    }
+
+    pub fn with_calls(mut self) -> Self {
+        self.calls = true;
+        self
+    }
+
+    pub fn with_diagnostics(mut self, provider: impl DiagnosticsProvider + 'static) -> Self {
+        self.diagnostics = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn rust() -> Self {
+        Self::new("Rust")
+            .with_extensions(&["rs"])
+            .with_line_comment("//")
+            .with_span("/*", "*/", TokenKind::Comment, None, true)
+            .with_span("r#\"", "\"#", TokenKind::String, None, true)
+            .with_span("\"", "\"", TokenKind::String, Some('\\'), true)
+            .with_keywords(&[
+                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
+                "enum", "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+                "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super",
+                "trait", "type", "unsafe", "use", "where", "while",
+            ])
+            .with_types(&[
+                "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str",
+                "u8", "u16", "u32", "u64", "u128", "usize",
+            ])
+            .with_literals(&["true", "false", "None", "Some", "Ok", "Err"])
+            .with_variable_prefix('\'')
+            .with_capitalized_types()
+            .with_calls()
+    }
+
+    pub fn toml() -> Self {
+        Self::new("TOML")
+            .with_extensions(&["toml"])
+            .with_line_prefix("[", TokenKind::Type)
+            .with_line_comment("#")
+            .with_span("\"\"\"", "\"\"\"", TokenKind::String, Some('\\'), true)
+            .with_span("'''", "'''", TokenKind::String, None, true)
+            .with_span("\"", "\"", TokenKind::String, Some('\\'), false)
+            .with_span("'", "'", TokenKind::String, None, false)
+            .with_literals(&["true", "false", "inf", "nan"])
+    }
+
+    pub fn json() -> Self {
+        Self::new("JSON")
+            .with_extensions(&["json"])
+            .with_span("\"", "\"", TokenKind::String, Some('\\'), false)
+            .with_literals(&["true", "false", "null"])
+            .with_diagnostics(JsonDiagnostics)
+    }
+
+    pub fn shell() -> Self {
+        Self::new("Shell")
+            .with_extensions(&["sh", "bash", "zsh"])
+            .with_line_comment("#")
+            .with_span("\"", "\"", TokenKind::String, Some('\\'), true)
+            .with_span("'", "'", TokenKind::String, None, true)
+            .with_span("`", "`", TokenKind::String, Some('\\'), true)
+            .with_keywords(&[
+                "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function",
+                "if", "in", "local", "return", "select", "then", "until", "while",
+            ])
+            .with_literals(&["true", "false"])
+            .with_variable_prefix('$')
+    }
+
+    pub fn markdown() -> Self {
+        Self::new("Markdown")
+            .with_extensions(&["md", "markdown"])
+            .with_line_prefix("#", TokenKind::Heading)
+            .with_line_prefix(">", TokenKind::Comment)
+            .with_span("```", "```", TokenKind::String, None, true)
+            .with_span("`", "`", TokenKind::String, None, false)
+            .with_span("**", "**", TokenKind::Keyword, None, false)
+            .with_span("[", "]", TokenKind::Function, None, false)
+    }
+
+    /// returns the highlighted tokens of `text`, in order
+    pub fn highlight(&self, text: &str) -> Vec<Token> {
+        let mut tokens = vec![];
+        let mut pos = 0;
+        let mut line_start = true;
+
+        while let Some(c) = text[pos..].chars().next() {
+            let rest = &text[pos..];
+            if c == '\n' {
+                pos += 1;
+                line_start = true;
+                continue;
+            }
+            let line_end = pos + rest.find('\n').unwrap_or(rest.len());
+
+            if std::mem::take(&mut line_start) {
+                let indent = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+                if let Some((_, kind)) = self
+                    .line_prefixes
+                    .iter()
+                    .find(|(prefix, _)| rest[indent..].starts_with(prefix.as_str()))
+                {
+                    push(&mut tokens, text, pos + indent..line_end, *kind);
+                    pos = line_end;
+                    continue;
+                }
+            }
+
+            if self
+                .line_comment
+                .as_ref()
+                .is_some_and(|comment| rest.starts_with(comment.as_str()))
+            {
+                push(&mut tokens, text, pos..line_end, TokenKind::Comment);
+                pos = line_end;
+                continue;
+            }
+
+            if let Some(span) = self.spans.iter().find(|s| rest.starts_with(&s.open)) {
+                let end = span_end(span, text, pos);
+                push(&mut tokens, text, pos..end, span.kind);
+                pos = end;
+                continue;
+            }
+
+            let after_word = text[..pos].chars().next_back().is_some_and(is_word);
+            if Some(c) == self.variable_prefix && !after_word {
+                let prefix = c.len_utf8();
+                let len = match rest[prefix..].strip_prefix('{') {
+                    Some(braced) => braced.find('}').map_or(0, |end| end + 2),
+                    None => word_len(&rest[prefix..]),
+                };
+                if len > 0 {
+                    push(
+                        &mut tokens,
+                        text,
+                        pos..pos + prefix + len,
+                        TokenKind::Variable,
+                    );
+                    pos += prefix + len;
+                    continue;
+                }
+            }
+
+            if c.is_ascii_digit() && !after_word {
+                let len = rest
+                    .find(|c: char| !(is_word(c) || c == '.'))
+                    .unwrap_or(rest.len());
+                push(&mut tokens, text, pos..pos + len, TokenKind::Number);
+                pos += len;
+                continue;
+            }
+
+            if is_word(c) {
+                let len = word_len(rest);
+                let word = &rest[..len];
+                let next = rest[len..].chars().next();
+                let kind = if self.keywords.iter().any(|k| k == word) {
+                    Some(TokenKind::Keyword)
+                } else if self.literals.iter().any(|l| l == word) {
+                    Some(TokenKind::Literal)
+                } else if self.types.iter().any(|t| t == word)
+                    || (self.capitalized_types && c.is_uppercase())
+                {
+                    Some(TokenKind::Type)
+                } else if self.calls && matches!(next, Some('(' | '!')) {
+                    Some(TokenKind::Function)
+                } else {
+                    None
+                };
+                if let Some(kind) = kind {
+                    push(&mut tokens, text, pos..pos + len, kind);
+                }
+                pos += len;
+                continue;
+            }
+
+            if matches!(c, '(' | ')' | '[' | ']' | '{' | '}') {
+                push(&mut tokens, text, pos..pos + 1, TokenKind::Punctuation);
+            }
+            pos += c.len_utf8();
+        }
+
+        tokens
+    }
+}
+
+/// adds a token for `range`, split at line endings
+fn push(tokens: &mut Vec<Token>, text: &str, range: Range<usize>, kind: TokenKind) {
+    let mut start = range.start;
+    for (offset, _) in text[range.clone()].match_indices('\n') {
+        let end = range.start + offset;
+        if end > start {
+            tokens.push(Token {
+                range: start..end,
+                kind,
+            });
+        }
+        start = end + 1;
+    }
+    if range.end > start {
+        tokens.push(Token {
+            range: start..range.end,
+            kind,
+        });
+    }
+}
+
+/// returns the end of the span that opens at `start`
+fn span_end(span: &GrammarSpan, text: &str, start: usize) -> usize {
+    let mut pos = start + span.open.len();
+    while let Some(c) = text[pos..].chars().next() {
+        if text[pos..].starts_with(&span.close) {
+            return pos + span.close.len();
+        }
+        if c == '\n' && !span.multiline {
+            return pos;
+        }
+
+        pos += c.len_utf8();
+        if Some(c) == span.escape {
+            pos += text[pos..].chars().next().map_or(0, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn word_len(text: &str) -> usize {
+    text.find(|c: char| !is_word(c)).unwrap_or(text.len())
+}
+
+/// Registry of grammars, by name
+///
+/// The default registry has grammars for Rust, TOML, JSON, shell and Markdown.
+/// The code editor looks grammars up in the process-wide registry, see `register_grammar`
+#[derive(Debug, Clone)]
+pub struct Grammars {
+    grammars: BTreeMap<String, Grammar>,
+}
+
+impl Default for Grammars {
+    fn default() -> Self {
+        let mut grammars = Self::empty();
+        grammars.register(Grammar::rust());
+        grammars.register(Grammar::toml());
+        grammars.register(Grammar::json());
+        grammars.register(Grammar::shell());
+        grammars.register(Grammar::markdown());
+        grammars
+    }
+}
+
+impl Grammars {
+    /// returns a registry without any grammars
+    pub fn empty() -> Self {
+        Self {
+            grammars: BTreeMap::new(),
+        }
+    }
+
+    /// adds a grammar, replacing the grammar that was registered with the same name
+    pub fn register(&mut self, grammar: Grammar) {
+        self.grammars.insert(grammar.name.clone(), grammar);
+    }
+
+    /// removes the grammar called `name`
+    pub fn unregister(&mut self, name: &str) {
+        self.grammars.remove(name);
+    }
+
+    /// registered grammar names, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.grammars.keys().map(|n| n.as_str())
+    }
+
+    /// returns the grammar called `name`
+    pub fn find(&self, name: &str) -> Option<&Grammar> {
+        self.grammars.get(name)
+    }
+
+    /// returns the grammar for the extension of `path`
+    pub fn for_path(&self, path: &str) -> Option<&Grammar> {
+        let (_, extension) = path.rsplit_once('.')?;
+        self.grammars.values().find(|g| {
+            g.extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+}
+
+fn global() -> &'static RwLock<Grammars> {
+    static GRAMMARS: OnceLock<RwLock<Grammars>> = OnceLock::new();
+    GRAMMARS.get_or_init(|| RwLock::new(Grammars::default()))
+}
+
+/// adds a grammar to the process-wide registry that the code editor uses
+pub fn register_grammar(grammar: Grammar) {
+    global()
+        .write()
+        .expect("grammar registry is not poisoned")
+        .register(grammar);
+}
+
+/// Returns the process-wide registry, `register_grammar` waits until the returned guard is dropped
+pub fn grammars() -> RwLockReadGuard<'static, Grammars> {
+    global().read().expect("grammar registry is not poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::span_end;
+    use super::DiagnosticsProvider;
+    use super::Grammar;
+    use super::Grammars;
+    use super::JsonDiagnostics;
+    use super::Severity;
+    use super::Token;
+    use super::TokenKind;
+
+    fn kinds<'a>(text: &'a str, tokens: &[Token]) -> Vec<(&'a str, TokenKind)> {
+        tokens
+            .iter()
+            .map(|t| (&text[t.range.clone()], t.kind))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_split_per_line() {
+        let text = "/* a\nb */ fn";
+        assert_eq!(
+            kinds(text, &Grammar::rust().highlight(text)),
+            [
+                ("/* a", TokenKind::Comment),
+                ("b */", TokenKind::Comment),
+                ("fn", TokenKind::Keyword),
+            ]
+        );
+
+        let text = "# title\n  > quote\ntext";
+        assert_eq!(
+            kinds(text, &Grammar::markdown().highlight(text)),
+            [
+                ("# title", TokenKind::Heading),
+                ("> quote", TokenKind::Comment)
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_do_not_close_strings() {
+        let text = r#""a\"b" true "c\\" 1"#;
+        assert_eq!(
+            kinds(text, &Grammar::json().highlight(text)),
+            [
+                (r#""a\"b""#, TokenKind::String),
+                ("true", TokenKind::Literal),
+                (r#""c\\""#, TokenKind::String),
+                ("1", TokenKind::Number),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_spans_end_at_the_line_or_the_text() {
+        let json = Grammar::json();
+        let text = "\"open\nnull";
+        assert_eq!(
+            kinds(text, &json.highlight(text)),
+            [("\"open", TokenKind::String), ("null", TokenKind::Literal)]
+        );
+        assert_eq!(span_end(&json.spans[0], text, 0), 5);
+
+        let rust = Grammar::rust();
+        let text = "/* open\nfn";
+        assert_eq!(span_end(&rust.spans[0], text, 0), text.len());
+        assert_eq!(
+            kinds(text, &rust.highlight(text)),
+            [("/* open", TokenKind::Comment), ("fn", TokenKind::Comment)]
+        );
+    }
+
+    #[test]
+    fn variables_can_have_multibyte_prefixes() {
+        let text = "$a ${b c} x$d";
+        assert_eq!(
+            kinds(text, &Grammar::shell().highlight(text)),
+            [("$a", TokenKind::Variable), ("${b c}", TokenKind::Variable)]
+        );
+
+        let grammar = Grammar::new("test").with_variable_prefix('§');
+        let text = "§name §";
+        assert_eq!(
+            kinds(text, &grammar.highlight(text)),
+            [("§name", TokenKind::Variable)]
+        );
+    }
+
+    #[test]
+    fn grammars_are_found_by_extension() {
+        let grammars = Grammars::default();
+        assert_eq!(grammars.for_path("src/main.RS").unwrap().name, "Rust");
+        assert_eq!(
+            grammars.for_path("file::a.b/Cargo.toml").unwrap().name,
+            "TOML"
+        );
+        assert!(grammars.for_path("Makefile").is_none());
+        assert!(grammars.for_path("notes.txt").is_none());
+
+        let mut grammars = Grammars::empty();
+        grammars.register(Grammar::new("Rust"));
+        assert!(grammars.for_path("main.rs").is_none());
+        grammars.register(Grammar::rust());
+        assert_eq!(grammars.names().collect::<Vec<_>>(), ["Rust"]);
+        grammars.unregister("Rust");
+        assert!(grammars.find("Rust").is_none());
+    }
+
+    #[test]
+    fn json_syntax_errors_are_reported() {
+        assert!(JsonDiagnostics.diagnostics("").is_empty());
+        assert!(JsonDiagnostics.diagnostics("{\"a\": [1, 2]}").is_empty());
+
+        let diagnostics = JsonDiagnostics.diagnostics("{\n  \"a\": }");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+}
@@ -6,8 +6,10 @@ use winit::event::WindowEvent;
 use winit::event_loop::ControlFlow;
 
 use super::create_depth_texture;
+use super::image_viewer::ImageTextures;
 use super::App;
 use super::Extension;
+use super::UnsavedChanges;
 
 pub struct GUI<A, E>
 where
@@ -15,6 +17,8 @@ where
     E: Extension + 'static,
 {
     pub window_title: String,
+    /// true while the window title shows that there are unsaved changes
+    pub title_dirty: bool,
     pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
@@ -28,6 +32,8 @@ where
     pub platform: imgui_winit_support::WinitPlatform,
     pub imgui: imgui::Context,
     pub renderer: imgui_wgpu::Renderer,
+    /// textures of the images shown by the image viewer, uploaded to `renderer`
+    pub image_textures: ImageTextures,
     pub hidpi_scale_factor: f64,
     pub font_size: f32,
     pub last_frame: Option<Instant>,
@@ -41,6 +47,7 @@ where
 pub struct GUIUpdate {
     pub event: Event<'static, ()>,
 }
+#[allow(clippy::extra_unused_lifetimes)]
 impl<'a> Component for GUIUpdate {
     type Storage = HashMapStorage<Self>;
 }
@@ -48,12 +55,22 @@ impl<'a> Component for GUIUpdate {
 pub struct ControlState {
     pub control_flow: Option<ControlFlow>,
 }
+#[allow(clippy::derivable_impls)]
 impl Default for ControlState {
     fn default() -> Self {
         ControlState { control_flow: None }
     }
 }
 
+/// Response to a request to close the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseResponse {
+    /// the window can be closed
+    Allow,
+    /// the window should stay open, for example to ask the user to confirm first
+    Deny,
+}
+
 #[derive(SystemData)]
 pub struct GUISystemData<'a> {
     control_state: Write<'a, ControlState>,
@@ -67,6 +84,7 @@ where
 {
     type SystemData = GUISystemData<'a>;
 
+    #[allow(clippy::needless_borrow)]
     fn setup(&mut self, world: &mut World) {
         <Self::SystemData as DynamicSystemData>::setup(&self.accessor(), world);
 
@@ -76,6 +94,8 @@ where
         let mut app_world = &mut self.app_world;
         let mut app_dispatcher = DispatcherBuilder::new();
 
+        // extensions can set the control flow of the app world to exit the event loop
+        app_world.insert(ControlState::default());
         app_world.insert(wgpu::Color {
             r: 0.1,
             g: 0.2,
@@ -108,6 +128,7 @@ where
         );
     }
 
+    #[allow(clippy::needless_borrow)]
     fn run(&mut self, data: Self::SystemData) {
         // Since we're using a nested world, we need to manually call run_now on the main app
         // since the main app needs the main thread to render it's ui
@@ -125,6 +146,19 @@ where
             self.app_world.maintain();
 
             self.extension.on_maintain(&mut self.app_world);
+
+            let dirty = self
+                .app_world
+                .try_fetch::<UnsavedChanges>()
+                .is_some_and(|unsaved| unsaved.is_dirty());
+            if dirty != self.title_dirty {
+                self.title_dirty = dirty;
+                if dirty {
+                    self.window.set_title(&format!("{} *", self.window_title));
+                } else {
+                    self.window.set_title(&self.window_title);
+                }
+            }
         }
 
         let mut control_state = data.control_state;
@@ -166,7 +200,14 @@ where
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
-                } => control_state.control_flow = Some(ControlFlow::Exit),
+                } => {
+                    // both are asked, so that each has a chance to prompt the user
+                    let extension = self.extension.on_close_requested(&self.app_world);
+                    let app = self.app.on_close_requested();
+                    if extension == CloseResponse::Allow && app == CloseResponse::Allow {
+                        control_state.control_flow = Some(ControlFlow::Exit)
+                    }
+                }
                 Event::MainEventsCleared => self.window.request_redraw(),
                 Event::RedrawEventsCleared => {
                     let now = Instant::now();
@@ -191,6 +232,15 @@ where
                     self.extension.on_ui(&self.app_world, &ui);
                     self.app_world.maintain();
 
+                    if let Some(control_flow) = self
+                        .app_world
+                        .write_resource::<ControlState>()
+                        .control_flow
+                        .take()
+                    {
+                        control_state.control_flow = Some(control_flow);
+                    }
+
                     // This is where we actually render the app's ui
                     // whatever state the app is in at this point is what the ui will see
                     // Repeating this information here from above...
@@ -270,6 +320,9 @@ where
                         &mut self.staging_belt,
                     );
 
+                    self.image_textures
+                        .upload(&self.device, &self.queue, &mut self.renderer);
+
                     {
                         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                             label: None,
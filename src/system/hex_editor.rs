@@ -0,0 +1,515 @@
+use imgui::ChildWindow;
+use imgui::Key;
+use imgui::ListClipper;
+use imgui::Selectable;
+use imgui::Ui;
+use std::ops::Range;
+
+use super::preview;
+use super::widget_cache::WidgetCache;
+
+/// Number of bytes shown on each row
+const BYTES_PER_ROW: usize = 16;
+
+/// Hex and ASCII viewer/editor for binary data
+///
+/// Only the visible rows are drawn, so large blobs stay responsive. Typing hex digits overwrites the selected
+/// byte a nibble at a time, or after clicking the ASCII column, typing overwrites bytes with characters
+#[derive(Debug, Clone)]
+pub struct HexEditor {
+    /// height of the editor
+    pub height: f32,
+    /// if true the data inspector reads values as little endian
+    pub little_endian: bool,
+    anchor: usize,
+    cursor: usize,
+    /// high nibble typed for the byte at the cursor
+    nibble: Option<u8>,
+    ascii: bool,
+    offset: String,
+    pattern: String,
+    text_pattern: bool,
+    status: Option<String>,
+    scroll_to: Option<usize>,
+}
+
+impl Default for HexEditor {
+    fn default() -> Self {
+        Self {
+            height: 300.0,
+            little_endian: true,
+            anchor: 0,
+            cursor: 0,
+            nibble: None,
+            ascii: false,
+            offset: String::new(),
+            pattern: String::new(),
+            text_pattern: false,
+            status: None,
+            scroll_to: None,
+        }
+    }
+}
+
+impl HexEditor {
+    /// offset of the byte at the cursor
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// range of the selected bytes, the byte at the cursor is always selected
+    pub fn selection(&self) -> Range<usize> {
+        self.anchor.min(self.cursor)..self.anchor.max(self.cursor) + 1
+    }
+
+    /// selects `range` and scrolls to it
+    pub fn select(&mut self, range: Range<usize>) {
+        self.anchor = range.start;
+        self.cursor = range.end.max(range.start + 1) - 1;
+        self.nibble = None;
+        self.scroll_to = Some(range.start / BYTES_PER_ROW);
+    }
+
+    /// moves the cursor to `offset` and scrolls to it
+    pub fn goto(&mut self, offset: usize) {
+        self.select(offset..offset + 1);
+    }
+
+    /// Shows the editor for `bytes`, `id` must be unique in the current window
+    ///
+    /// Returns true if the bytes were changed
+    pub fn ui(&mut self, id: impl AsRef<str>, bytes: &mut Vec<u8>, ui: &Ui) -> bool {
+        let _id = ui.push_id(id.as_ref());
+        let last = bytes.len().saturating_sub(1);
+        self.anchor = self.anchor.min(last);
+        self.cursor = self.cursor.min(last);
+        let mut changed = false;
+
+        ui.set_next_item_width(120.0);
+        let goto = ui
+            .input_text("##offset", &mut self.offset)
+            .hint("offset")
+            .enter_returns_true(true)
+            .build();
+        ui.same_line();
+        if ui.button("go to") || goto {
+            match parse_offset(&self.offset) {
+                Some(offset) if offset < bytes.len() => {
+                    self.goto(offset);
+                    self.status = None;
+                }
+                Some(offset) => self.status = Some(format!("{:#x} is past the end", offset)),
+                None => self.status = Some(format!("'{}' is not an offset", self.offset)),
+            }
+        }
+
+        ui.same_line();
+        ui.set_next_item_width(200.0);
+        let find = ui
+            .input_text("##pattern", &mut self.pattern)
+            .hint(if self.text_pattern {
+                "text"
+            } else {
+                "bytes, e.g. DE AD ?? EF"
+            })
+            .enter_returns_true(true)
+            .build();
+        ui.same_line();
+        if ui.button("find next") || find {
+            let pattern = if self.text_pattern {
+                Ok(self.pattern.bytes().map(Some).collect())
+            } else {
+                parse_pattern(&self.pattern)
+            };
+            match pattern {
+                Ok(pattern) => match find_pattern(bytes, &pattern, self.cursor + 1) {
+                    Some(at) => {
+                        self.select(at..at + pattern.len());
+                        self.status = None;
+                    }
+                    None => self.status = Some("not found".to_string()),
+                },
+                Err(err) => self.status = Some(err),
+            }
+        }
+        ui.same_line();
+        ui.checkbox("text", &mut self.text_pattern);
+
+        let selection = self.selection();
+        let selected = selection.start..selection.end.min(bytes.len());
+        ui.disabled(bytes.is_empty(), || {
+            if ui.button("copy hex") {
+                ui.set_clipboard_text(to_hex(&bytes[selected.clone()]));
+            }
+            ui.same_line();
+            if ui.button("copy base64") {
+                ui.set_clipboard_text(base64::encode(&bytes[selected.clone()]));
+            }
+            ui.same_line();
+            if ui.button("delete") {
+                bytes.drain(selected.clone());
+                self.goto(selected.start);
+                changed = true;
+            }
+        });
+        ui.same_line();
+        if ui.button("insert") {
+            let at = if bytes.is_empty() { 0 } else { self.cursor };
+            bytes.insert(at, 0);
+            self.goto(at);
+            changed = true;
+        }
+        ui.same_line();
+        ui.text_disabled(format!(
+            "{} bytes, selected {:#x}..{:#x} ({} bytes)",
+            bytes.len(),
+            selected.start,
+            selected.end,
+            selected.len()
+        ));
+        if let Some(status) = &self.status {
+            ui.same_line();
+            ui.text_colored([1.0, 0.7, 0.2, 1.0], status);
+        }
+
+        let offset_digits = format!("{:x}", bytes.len()).len().max(8);
+        let columns = Columns::new(offset_digits, ui);
+        let style = ui.clone_style();
+        let width = columns.ascii(BYTES_PER_ROW) + style.scrollbar_size + style.window_padding[0];
+
+        ChildWindow::new("bytes")
+            .size([width, self.height])
+            .border(true)
+            .build(ui, || {
+                changed |= self.bytes_ui(bytes, &columns, ui);
+            });
+
+        ui.same_line();
+        ChildWindow::new("inspector")
+            .size([0.0, self.height])
+            .border(true)
+            .build(ui, || self.inspector_ui(bytes, ui));
+
+        changed
+    }
+
+    fn bytes_ui(&mut self, bytes: &mut [u8], columns: &Columns, ui: &Ui) -> bool {
+        let row_height = ui.text_line_height_with_spacing();
+        let rows = bytes.len().div_ceil(BYTES_PER_ROW);
+        if let Some(row) = self.scroll_to.take() {
+            let top = row as f32 * row_height;
+            let visible = ui.window_size()[1] - row_height * 2.0;
+            if top < ui.scroll_y() || top > ui.scroll_y() + visible {
+                ui.set_scroll_y((top - visible / 2.0).max(0.0));
+            }
+        }
+
+        let selection = self.selection();
+        let mut clicked = None;
+        let mut clipper = ListClipper::new(rows as i32)
+            .items_height(row_height)
+            .begin(ui);
+        while clipper.step() {
+            for row in clipper.display_start()..clipper.display_end() {
+                let start = row as usize * BYTES_PER_ROW;
+                let end = (start + BYTES_PER_ROW).min(bytes.len());
+                ui.text_disabled(format!("{:0width$x}", start, width = columns.offset_digits));
+
+                for (i, (offset, byte)) in (start..end).zip(&bytes[start..end]).enumerate() {
+                    let label = match self.nibble {
+                        Some(high) if offset == self.cursor => format!("{:X}_", high),
+                        _ => format!("{:02X}", byte),
+                    };
+                    ui.same_line_with_pos(columns.hex(i));
+                    if Selectable::new(format!("{}##hex{}", label, offset))
+                        .selected(selection.contains(&offset))
+                        .size([columns.char_width * 2.0, 0.0])
+                        .build(ui)
+                    {
+                        clicked = Some((offset, false));
+                    }
+                }
+
+                for (i, (offset, byte)) in (start..end).zip(&bytes[start..end]).enumerate() {
+                    let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    };
+                    ui.same_line_with_pos(columns.ascii(i));
+                    if Selectable::new(format!("{}##ascii{}", c, offset))
+                        .selected(selection.contains(&offset))
+                        .size([columns.char_width, 0.0])
+                        .build(ui)
+                    {
+                        clicked = Some((offset, true));
+                    }
+                }
+            }
+        }
+        clipper.end();
+
+        if let Some((offset, ascii)) = clicked {
+            self.cursor = offset;
+            if !ui.io().key_shift {
+                self.anchor = offset;
+            }
+            self.ascii = ascii;
+            self.nibble = None;
+        }
+
+        if ui.is_window_focused() && !ui.is_any_item_active() {
+            self.keyboard(bytes, ui)
+        } else {
+            false
+        }
+    }
+
+    /// moves the cursor with the arrow keys, and overwrites bytes with typed characters
+    fn keyboard(&mut self, bytes: &mut [u8], ui: &Ui) -> bool {
+        if bytes.is_empty() {
+            return false;
+        }
+
+        let last = bytes.len() - 1;
+        let moved = if ui.is_key_pressed(Key::LeftArrow) {
+            Some(self.cursor.saturating_sub(1))
+        } else if ui.is_key_pressed(Key::RightArrow) {
+            Some((self.cursor + 1).min(last))
+        } else if ui.is_key_pressed(Key::UpArrow) {
+            Some(self.cursor.saturating_sub(BYTES_PER_ROW))
+        } else if ui.is_key_pressed(Key::DownArrow) {
+            Some((self.cursor + BYTES_PER_ROW).min(last))
+        } else {
+            None
+        };
+        if let Some(cursor) = moved {
+            self.cursor = cursor;
+            if !ui.io().key_shift {
+                self.anchor = cursor;
+            }
+            self.nibble = None;
+            self.scroll_to = Some(cursor / BYTES_PER_ROW);
+        }
+
+        if ui.io().key_ctrl && ui.is_key_pressed(Key::C) {
+            if let Some(selected) = bytes.get(self.selection()) {
+                ui.set_clipboard_text(to_hex(selected));
+            }
+            return false;
+        }
+
+        let mut changed = false;
+        for c in ui.io().input_queue_characters() {
+            if self.ascii {
+                if c.is_ascii() && !c.is_ascii_control() {
+                    bytes[self.cursor] = c as u8;
+                    self.advance(last);
+                    changed = true;
+                }
+            } else if let Some(digit) = c.to_digit(16) {
+                match self.nibble.take() {
+                    Some(high) => {
+                        bytes[self.cursor] = high << 4 | digit as u8;
+                        self.advance(last);
+                        changed = true;
+                    }
+                    None => self.nibble = Some(digit as u8),
+                }
+            }
+        }
+        changed
+    }
+
+    fn advance(&mut self, last: usize) {
+        self.cursor = (self.cursor + 1).min(last);
+        self.anchor = self.cursor;
+        self.scroll_to = Some(self.cursor / BYTES_PER_ROW);
+    }
+
+    fn inspector_ui(&mut self, bytes: &[u8], ui: &Ui) {
+        ui.text(format!("at {:#x}", self.cursor));
+        ui.checkbox("little endian", &mut self.little_endian);
+        ui.separator();
+
+        let at = self.cursor;
+        let le = self.little_endian;
+        macro_rules! inspect {
+            ($($name:literal => $ty:ty),* $(,)?) => {
+                $(
+                    let value = bytes
+                        .get(at..at + std::mem::size_of::<$ty>())
+                        .and_then(|b| b.try_into().ok())
+                        .map(|b| if le { <$ty>::from_le_bytes(b) } else { <$ty>::from_be_bytes(b) })
+                        .map_or("-".to_string(), |v| v.to_string());
+                    ui.text_disabled($name);
+                    ui.same_line_with_pos(60.0);
+                    ui.text(value);
+                )*
+            };
+        }
+        inspect!(
+            "u8" => u8,
+            "i8" => i8,
+            "u16" => u16,
+            "i16" => i16,
+            "u32" => u32,
+            "i32" => i32,
+            "u64" => u64,
+            "i64" => i64,
+            "f32" => f32,
+            "f64" => f64,
+        );
+        if let Some(byte) = bytes.get(at) {
+            ui.text_disabled("bits");
+            ui.same_line_with_pos(60.0);
+            ui.text(format!("{:08b}", byte));
+        }
+    }
+}
+
+/// Horizontal positions of the columns of a row
+struct Columns {
+    offset_digits: usize,
+    char_width: f32,
+    padding: f32,
+}
+
+impl Columns {
+    fn new(offset_digits: usize, ui: &Ui) -> Self {
+        Self {
+            offset_digits,
+            char_width: ui.calc_text_size("0")[0],
+            padding: ui.clone_style().window_padding[0],
+        }
+    }
+
+    /// position of the hex digits of the `i`th byte of a row, with an extra gap after the 8th byte
+    fn hex(&self, i: usize) -> f32 {
+        let gap = if i >= BYTES_PER_ROW / 2 { 1.0 } else { 0.0 };
+        self.padding + self.char_width * ((self.offset_digits + 1) as f32 + i as f32 * 2.5 + gap)
+    }
+
+    /// position of the character of the `i`th byte of a row
+    fn ascii(&self, i: usize) -> f32 {
+        self.hex(BYTES_PER_ROW) + self.char_width * (1.0 + i as f32)
+    }
+}
+
+/// parses a decimal offset, or a hex offset that starts with `0x`
+fn parse_offset(offset: &str) -> Option<usize> {
+    let offset = offset.trim();
+    match offset
+        .strip_prefix("0x")
+        .or_else(|| offset.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => offset.parse().ok(),
+    }
+}
+
+/// parses hex bytes, optionally separated by whitespace, `??` matches any byte
+fn parse_pattern(pattern: &str) -> Result<Vec<Option<u8>>, String> {
+    let digits = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return Err(format!("'{}' is not a byte pattern", pattern));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| match pair {
+            ['?', '?'] => Ok(None),
+            [high, low] => match (high.to_digit(16), low.to_digit(16)) {
+                (Some(high), Some(low)) => Ok(Some((high << 4 | low) as u8)),
+                _ => Err(format!("'{}{}' is not a byte", high, low)),
+            },
+            _ => unreachable!("chunks of 2"),
+        })
+        .collect()
+}
+
+/// returns the offset of the first match of `pattern` at or after `from`, wrapping around to the start
+fn find_pattern(bytes: &[u8], pattern: &[Option<u8>], from: usize) -> Option<usize> {
+    if pattern.is_empty() || pattern.len() > bytes.len() {
+        return None;
+    }
+
+    let matches = |at: &usize| {
+        pattern
+            .iter()
+            .zip(&bytes[*at..])
+            .all(|(p, b)| p.is_none_or(|p| p == *b))
+    };
+    let starts = bytes.len() - pattern.len() + 1;
+    (from.min(starts)..starts)
+        .chain(0..from.min(starts))
+        .find(matches)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Shows a hex editor for the bytes of the attribute identified by `key`, returns true if the bytes were changed
+///
+/// The editor is kept between frames so that the cursor and search are not lost, `key` must be unique to the attribute
+pub(super) fn binary_ui(key: &str, bytes: &mut Vec<u8>, ui: &Ui) -> bool {
+    let changed = WidgetCache::<HexEditor>::lock(ui)
+        .get_or_insert_with(key, HexEditor::default)
+        .ui(key, bytes, ui);
+    if changed {
+        // bytes edited in place may keep their fingerprint
+        preview::invalidate(key, ui);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_pattern;
+    use super::parse_offset;
+    use super::parse_pattern;
+
+    #[test]
+    fn offsets_are_decimal_or_hex() {
+        assert_eq!(parse_offset("42"), Some(42));
+        assert_eq!(parse_offset(" 0x2A "), Some(42));
+        assert_eq!(parse_offset("0X2a"), Some(42));
+        assert_eq!(parse_offset("2A"), None);
+        assert_eq!(parse_offset("0x"), None);
+        assert_eq!(parse_offset("-1"), None);
+    }
+
+    #[test]
+    fn patterns_are_hex_bytes_with_wildcards() {
+        assert_eq!(
+            parse_pattern("de AD ?? ef"),
+            Ok(vec![Some(0xDE), Some(0xAD), None, Some(0xEF)])
+        );
+        assert_eq!(parse_pattern("00ff"), Ok(vec![Some(0x00), Some(0xFF)]));
+        assert!(parse_pattern("").is_err());
+        assert!(parse_pattern("abc").is_err());
+        assert!(parse_pattern("zz").is_err());
+        assert!(parse_pattern("?0").is_err());
+    }
+
+    #[test]
+    fn patterns_are_found_from_an_offset_and_wrap_around() {
+        let bytes = [1, 2, 3, 1, 2, 4];
+        assert_eq!(find_pattern(&bytes, &[Some(1), Some(2)], 0), Some(0));
+        assert_eq!(find_pattern(&bytes, &[Some(1), Some(2)], 1), Some(3));
+        assert_eq!(find_pattern(&bytes, &[Some(1), Some(2)], 4), Some(0));
+        assert_eq!(find_pattern(&bytes, &[Some(2), None, Some(4)], 0), None);
+        assert_eq!(find_pattern(&bytes, &[Some(1), None, Some(4)], 0), Some(3));
+        assert_eq!(find_pattern(&bytes, &[Some(4)], 100), Some(5));
+        assert_eq!(find_pattern(&bytes, &[], 0), None);
+        assert_eq!(find_pattern(&[1], &[Some(1), Some(1)], 0), None);
+    }
+}
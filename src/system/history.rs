@@ -0,0 +1,769 @@
+use imgui::Key;
+use imgui::Ui;
+use specs::world::EntitiesRes;
+use specs::Entity;
+use specs::Join;
+use specs::ReadStorage;
+use specs::World;
+use specs::WorldExt;
+use specs::WriteStorage;
+use std::collections::HashMap;
+
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Extension;
+use super::Metadata;
+use super::Schema;
+use super::Subscription;
+use super::ValidationError;
+use super::Value;
+
+/// A reversible change to the `Attribute` storage
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// the stable name/value of the attribute on `entity` was committed from `before` to `after`
+    Commit {
+        entity: Entity,
+        before: (String, Value),
+        after: (String, Value),
+    },
+    /// `attribute` was inserted on `entity`
+    Create {
+        entity: Entity,
+        attribute: Attribute,
+    },
+    /// `attribute` was removed from `entity`
+    Delete {
+        entity: Entity,
+        attribute: Attribute,
+    },
+}
+
+impl Operation {
+    /// returns a short description of this operation
+    pub fn describe(&self) -> String {
+        match self {
+            Operation::Commit { before, after, .. } if before.0 != after.0 => {
+                format!("rename {} to {}", before.0, after.0)
+            }
+            Operation::Commit { after, .. } => format!("edit {}", after.0),
+            Operation::Create { attribute, .. } => format!("create {}", attribute.name()),
+            Operation::Delete { attribute, .. } => format!("delete {}", attribute.name()),
+        }
+    }
+
+    /// returns the entity this operation applies to
+    pub fn entity(&self) -> Entity {
+        match self {
+            Operation::Commit { entity, .. }
+            | Operation::Create { entity, .. }
+            | Operation::Delete { entity, .. } => *entity,
+        }
+    }
+
+    fn entity_mut(&mut self) -> &mut Entity {
+        match self {
+            Operation::Commit { entity, .. }
+            | Operation::Create { entity, .. }
+            | Operation::Delete { entity, .. } => entity,
+        }
+    }
+
+    /// Applies or reverts this operation
+    ///
+    /// An attribute that is restored on an entity that was deleted is restored on a new entity,
+    /// in that case the deleted entity and the new entity are returned, so that other operations can be remapped
+    fn apply(
+        &self,
+        attributes: &mut WriteStorage<Attribute>,
+        reverse: bool,
+    ) -> Option<(Entity, Entity)> {
+        match (self, reverse) {
+            (
+                Operation::Commit {
+                    entity,
+                    before,
+                    after,
+                },
+                reverse,
+            ) => {
+                let (name, value) = if reverse { before } else { after };
+                if let Some(attribute) = attributes.get_mut(*entity) {
+                    attribute.name = name.clone();
+                    attribute.value = value.clone();
+                }
+                None
+            }
+            (Operation::Create { entity, .. }, true)
+            | (Operation::Delete { entity, .. }, false) => {
+                attributes.remove(*entity);
+                None
+            }
+            (Operation::Create { entity, attribute }, false)
+            | (Operation::Delete { entity, attribute }, true) => {
+                let entities = attributes.fetched_entities();
+                let target = if entities.is_alive(*entity) {
+                    *entity
+                } else {
+                    entities.create()
+                };
+
+                if let Err(err) = attributes.insert(target, attribute.clone()) {
+                    eprintln!("Could not restore attribute {}, {}", attribute.name(), err);
+                }
+                (target != *entity).then_some((*entity, target))
+            }
+        }
+    }
+}
+
+/// A named group of operations that are undone/redone together
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub name: String,
+    pub operations: Vec<Operation>,
+}
+
+/// Resource that records changes to attributes so that they can be undone
+///
+/// Operations recorded between `begin` and `end` are grouped into a single transaction,
+/// otherwise each operation is recorded as its own transaction.
+///
+/// A history created with `History::new` also records changes that were made without it,
+/// e.g. with `Attribute::commit`, see `observe`
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    pending: Option<Transaction>,
+    depth: usize,
+    observer: Option<Observer>,
+}
+
+/// Subscription to `AttributeEvents`, with the stable state of each attribute
+/// so that changes can be recorded with the state they replaced
+#[derive(Debug)]
+struct Observer {
+    subscription: Subscription,
+    /// entity and stable state of each attribute by entity id, without pending changes,
+    /// the entity is kept so that deletes are recorded with the generation that was deleted
+    stable: HashMap<u32, (Entity, Attribute)>,
+}
+
+impl History {
+    /// returns a history that also records changes to the `Attribute` storage of `world` that are made without it,
+    /// adds an `AttributeEvents` resource to `world` if there is none
+    pub fn new(world: &mut World) -> Self {
+        ChangeDetection::configure_app_world(world);
+        let subscription = world
+            .write_resource::<AttributeEvents>()
+            .subscribe(AttributeFilter::Any);
+        let stable = (&world.entities(), &world.read_storage::<Attribute>())
+            .join()
+            .map(|(e, a)| (e.id(), (e, stable(a))))
+            .collect();
+
+        Self {
+            observer: Some(Observer {
+                subscription,
+                stable,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Records the commits, creates and deletes that were made since the last call without going through this history
+    ///
+    /// Each change is recorded as its own operation, changes made with `record` are not recorded twice.
+    /// This is called by `HistoryPanel` after the app world is maintained, does nothing if the history
+    /// was not created with `History::new`
+    pub fn observe(
+        &mut self,
+        events: &mut AttributeEvents,
+        entities: &EntitiesRes,
+        attributes: &ReadStorage<Attribute>,
+    ) {
+        let observer = match &mut self.observer {
+            Some(observer) => observer,
+            None => return,
+        };
+
+        events.update(entities, attributes);
+        let mut operations = vec![];
+        for event in events.drain(observer.subscription) {
+            let index = event.entity;
+            let entity = entities.entity(index);
+
+            match (observer.stable.get(&index), attributes.get(entity)) {
+                (Some((_, known)), Some(current)) => {
+                    let before = (known.name().to_string(), known.value().clone());
+                    let after = (current.name().to_string(), current.value().clone());
+                    if before != after {
+                        operations.push(Operation::Commit {
+                            entity,
+                            before,
+                            after,
+                        });
+                    }
+                }
+                (None, Some(current)) => operations.push(Operation::Create {
+                    entity,
+                    attribute: stable(current),
+                }),
+                (Some((known_entity, known)), None) => operations.push(Operation::Delete {
+                    entity: *known_entity,
+                    attribute: known.clone(),
+                }),
+                (None, None) => {}
+            }
+
+            match attributes.get(entity) {
+                Some(current) => observer.stable.insert(index, (entity, stable(current))),
+                None => observer.stable.remove(&index),
+            };
+        }
+
+        for operation in operations {
+            self.record(operation);
+        }
+    }
+
+    /// starts a transaction, nested transactions are merged into the outermost one
+    pub fn begin(&mut self, name: impl AsRef<str>) {
+        if self.depth == 0 {
+            self.pending = Some(Transaction {
+                name: name.as_ref().to_string(),
+                operations: vec![],
+            });
+        }
+        self.depth += 1;
+    }
+
+    /// ends the current transaction, empty transactions are discarded
+    pub fn end(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            if let Some(transaction) = self.pending.take() {
+                if !transaction.operations.is_empty() {
+                    self.push(transaction);
+                }
+            }
+        }
+    }
+
+    /// records an operation that has already been applied
+    pub fn record(&mut self, operation: Operation) {
+        self.remember(&operation, false);
+        match &mut self.pending {
+            Some(transaction) => transaction.operations.push(operation),
+            None => self.push(Transaction {
+                name: operation.describe(),
+                operations: vec![operation],
+            }),
+        }
+    }
+
+    /// commits the pending changes of `attribute`, which is stored on `entity`, and records the commit
+    ///
+    /// When a schema is passed, the pending changes are only committed if they are valid for it
+    pub fn commit(
+        &mut self,
+        entity: Entity,
+        attribute: &mut Attribute,
+        schema: Option<&Schema>,
+    ) -> Result<(), ValidationError> {
+        let before = match schema {
+            Some(schema) => schema.commit(attribute)?,
+            None => attribute.commit_unchecked(),
+        };
+        if let Some(before) = before {
+            self.record_commit(entity, attribute, before);
+        }
+        Ok(())
+    }
+
+    /// shows an editor for `attribute` with `Attribute::edit_attr_with` and records any changes that are saved
+    pub fn edit_attr(
+        &mut self,
+        entity: Entity,
+        attribute: &mut Attribute,
+        ui: &Ui,
+        metadata: Option<&Metadata>,
+    ) {
+        if let Some(before) = attribute.edit_attr_with(ui, metadata) {
+            self.record_commit(entity, attribute, before);
+        }
+    }
+
+    /// inserts `attribute` on `entity` and records the creation
+    pub fn create(
+        &mut self,
+        attributes: &mut WriteStorage<Attribute>,
+        entity: Entity,
+        attribute: Attribute,
+    ) {
+        match attributes.insert(entity, attribute.clone()) {
+            Ok(Some(replaced)) => {
+                self.begin(format!("create {}", attribute.name()));
+                self.record(Operation::Delete {
+                    entity,
+                    attribute: replaced,
+                });
+                self.record(Operation::Create { entity, attribute });
+                self.end();
+            }
+            Ok(None) => self.record(Operation::Create { entity, attribute }),
+            Err(err) => eprintln!("Could not create attribute {}, {}", attribute.name(), err),
+        }
+    }
+
+    /// removes the attribute from `entity` and records the deletion
+    pub fn delete(&mut self, attributes: &mut WriteStorage<Attribute>, entity: Entity) {
+        if let Some(attribute) = attributes.remove(entity) {
+            self.record(Operation::Delete { entity, attribute });
+        }
+    }
+
+    /// reverts the last transaction, returns false if there was nothing to undo
+    pub fn undo(&mut self, attributes: &mut WriteStorage<Attribute>) -> bool {
+        self.close_pending();
+
+        match self.undo.pop() {
+            Some(mut transaction) => {
+                for i in (0..transaction.operations.len()).rev() {
+                    self.apply(&mut transaction, i, attributes, true);
+                }
+                self.redo.push(transaction);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// re-applies the last transaction that was undone, returns false if there was nothing to redo
+    pub fn redo(&mut self, attributes: &mut WriteStorage<Attribute>) -> bool {
+        self.close_pending();
+
+        match self.redo.pop() {
+            Some(mut transaction) => {
+                for i in 0..transaction.operations.len() {
+                    self.apply(&mut transaction, i, attributes, false);
+                }
+                self.undo.push(transaction);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// returns true if there is a transaction to undo
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// returns true if there is a transaction to redo
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// transactions that can be undone, oldest first
+    pub fn undo_stack(&self) -> &[Transaction] {
+        &self.undo
+    }
+
+    /// transactions that can be redone, most recently undone last
+    pub fn redo_stack(&self) -> &[Transaction] {
+        &self.redo
+    }
+
+    /// forgets all recorded transactions
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending = None;
+        self.depth = 0;
+    }
+
+    fn record_commit(&mut self, entity: Entity, attribute: &Attribute, before: (String, Value)) {
+        let after = (attribute.name().to_string(), attribute.value().clone());
+        if before != after {
+            self.record(Operation::Commit {
+                entity,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// applies the operation at `index` of `transaction`, which was taken from the undo or redo stack,
+    /// if it was restored on a new entity, every recorded operation on the deleted entity is moved to it
+    fn apply(
+        &mut self,
+        transaction: &mut Transaction,
+        index: usize,
+        attributes: &mut WriteStorage<Attribute>,
+        reverse: bool,
+    ) {
+        if let Some((deleted, restored)) = transaction.operations[index].apply(attributes, reverse)
+        {
+            for operation in self
+                .undo
+                .iter_mut()
+                .chain(self.redo.iter_mut())
+                .chain(std::iter::once(&mut *transaction))
+                .flat_map(|t| t.operations.iter_mut())
+            {
+                if operation.entity() == deleted {
+                    *operation.entity_mut() = restored;
+                }
+            }
+        }
+        self.remember(&transaction.operations[index], reverse);
+    }
+
+    /// updates the known stable state with an operation that was applied, so that `observe` doesn't record it again
+    fn remember(&mut self, operation: &Operation, reverse: bool) {
+        let stable = match &mut self.observer {
+            Some(observer) => &mut observer.stable,
+            None => return,
+        };
+
+        match (operation, reverse) {
+            (
+                Operation::Commit {
+                    entity,
+                    before,
+                    after,
+                },
+                reverse,
+            ) => {
+                let (name, value) = if reverse { before } else { after };
+                if let Some((_, attribute)) = stable.get_mut(&entity.id()) {
+                    attribute.name = name.clone();
+                    attribute.value = value.clone();
+                }
+            }
+            (Operation::Create { entity, .. }, true)
+            | (Operation::Delete { entity, .. }, false) => {
+                stable.remove(&entity.id());
+            }
+            (Operation::Create { entity, attribute }, false)
+            | (Operation::Delete { entity, attribute }, true) => {
+                stable.insert(entity.id(), (*entity, self::stable(attribute)));
+            }
+        }
+    }
+
+    fn push(&mut self, transaction: Transaction) {
+        self.undo.push(transaction);
+        self.redo.clear();
+    }
+
+    /// an unfinished transaction can't be undone, so it's recorded as is before undo/redo
+    fn close_pending(&mut self) {
+        if self.pending.is_some() {
+            self.depth = 1;
+            self.end();
+        }
+    }
+}
+
+/// returns a copy of `attribute` without its pending changes
+fn stable(attribute: &Attribute) -> Attribute {
+    let mut stable = attribute.clone();
+    stable.take_transient();
+    stable
+}
+
+/// Extension that adds a `History` resource to the app world, records changes that were made without it
+/// after the world is maintained, handles Ctrl+Z/Ctrl+Shift+Z (or Ctrl+Y), and shows a history panel
+#[derive(Default)]
+pub struct HistoryPanel {
+    /// when true, the history window is shown
+    pub opened: bool,
+}
+
+impl Extension for HistoryPanel {
+    fn configure_app_world(world: &mut World) {
+        if !world.has_value::<History>() {
+            let history = History::new(world);
+            world.insert(history);
+        }
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        app_world.write_resource::<History>().observe(
+            &mut app_world.write_resource::<AttributeEvents>(),
+            &app_world.entities(),
+            &app_world.read_storage::<Attribute>(),
+        );
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        let mut history = app_world.write_resource::<History>();
+        let mut attributes = app_world.write_component::<Attribute>();
+
+        let io = ui.io();
+        if (io.key_ctrl || io.key_super) && !io.want_text_input {
+            if (ui.is_key_pressed(Key::Z) && io.key_shift) || ui.is_key_pressed(Key::Y) {
+                history.redo(&mut attributes);
+            } else if ui.is_key_pressed(Key::Z) {
+                history.undo(&mut attributes);
+            }
+        }
+
+        if !self.opened {
+            return;
+        }
+
+        imgui::Window::new("History")
+            .size([400.0, 600.0], imgui::Condition::FirstUseEver)
+            .opened(&mut self.opened)
+            .build(ui, || {
+                ui.disabled(!history.can_undo(), || {
+                    if ui.button("undo") {
+                        history.undo(&mut attributes);
+                    }
+                });
+                ui.same_line();
+                ui.disabled(!history.can_redo(), || {
+                    if ui.button("redo") {
+                        history.redo(&mut attributes);
+                    }
+                });
+                ui.separator();
+
+                let mut undo_to = None;
+                for (i, transaction) in history.undo_stack().iter().enumerate() {
+                    if imgui::Selectable::new(format!("{}##undo{}", transaction.name, i))
+                        .selected(i + 1 == history.undo_stack().len())
+                        .build(ui)
+                    {
+                        undo_to = Some(i + 1);
+                    }
+                }
+
+                let mut redo_to = None;
+                for (i, transaction) in history.redo_stack().iter().enumerate().rev() {
+                    ui.text_disabled(&transaction.name);
+                    if ui.is_item_clicked() {
+                        redo_to = Some(i);
+                    }
+                }
+
+                if let Some(len) = undo_to {
+                    while history.undo_stack().len() > len && history.undo(&mut attributes) {}
+                }
+
+                if let Some(len) = redo_to {
+                    while history.redo_stack().len() > len && history.redo(&mut attributes) {}
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::system::Attribute;
+    use crate::system::AttributeEvents;
+    use crate::system::Metadata;
+    use crate::system::Schema;
+    use crate::system::ValidationError;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::Join;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn observe(world: &World) {
+        world.write_resource::<History>().observe(
+            &mut world.write_resource::<AttributeEvents>(),
+            &world.entities(),
+            &world.read_storage::<Attribute>(),
+        );
+    }
+
+    #[test]
+    fn changes_made_without_the_history_are_recorded() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let history = History::new(&mut world);
+        world.insert(history);
+
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        observe(&world);
+
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(entity).unwrap();
+            attribute.edit_as(Value::Int(2));
+        }
+        observe(&world);
+        assert_eq!(
+            world.read_resource::<History>().undo_stack().len(),
+            1,
+            "pending changes are not recorded"
+        );
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .commit_unchecked();
+        observe(&world);
+
+        let names = world
+            .read_resource::<History>()
+            .undo_stack()
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["create a", "edit a"]);
+
+        let mut history = world.write_resource::<History>();
+        let mut attributes = world.write_storage::<Attribute>();
+        assert!(history.undo(&mut attributes));
+        assert_eq!(attributes.get(entity).unwrap().value(), &Value::Int(1));
+        assert!(history.undo(&mut attributes));
+        assert!(attributes.get(entity).is_none());
+        drop((history, attributes));
+
+        observe(&world);
+        let history = world.read_resource::<History>();
+        assert_eq!(history.undo_stack().len(), 0, "undo is not recorded");
+        assert_eq!(history.redo_stack().len(), 2);
+    }
+
+    #[test]
+    fn changes_made_with_the_history_are_recorded_once() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let history = History::new(&mut world);
+        world.insert(history);
+
+        let entity = world.create_entity().build();
+        {
+            let mut history = world.write_resource::<History>();
+            let mut attributes = world.write_storage::<Attribute>();
+            history.create(
+                &mut attributes,
+                entity,
+                Attribute::new(0, "a", Value::Int(1)),
+            );
+
+            let attribute = attributes.get_mut(entity).unwrap();
+            attribute.edit(("b".to_string(), Value::Int(2)));
+            history.commit(entity, attribute, None).unwrap();
+        }
+        observe(&world);
+
+        let names = world
+            .read_resource::<History>()
+            .undo_stack()
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["create a", "rename a to b"]);
+    }
+
+    #[test]
+    fn invalid_commits_are_not_recorded() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let mut history = History::new(&mut world);
+        let entity = world.create_entity().build();
+        let mut schema = Schema::default();
+        schema.insert(
+            "a",
+            Metadata {
+                max: Some(10.0),
+                ..Default::default()
+            },
+        );
+
+        let mut attributes = world.write_storage::<Attribute>();
+        history.create(
+            &mut attributes,
+            entity,
+            Attribute::new(0, "a", Value::Int(1)),
+        );
+        let attribute = attributes.get_mut(entity).unwrap();
+        attribute.edit_as(Value::Int(11));
+        assert!(matches!(
+            history.commit(entity, attribute, Some(&schema)),
+            Err(ValidationError::OutOfRange { .. })
+        ));
+        assert_eq!(attribute.value(), &Value::Int(1));
+        assert_eq!(history.undo_stack().len(), 1);
+
+        attribute.edit_as(Value::Int(10));
+        assert_eq!(history.commit(entity, attribute, Some(&schema)), Ok(()));
+        assert_eq!(attribute.value(), &Value::Int(10));
+        assert_eq!(history.undo_stack().len(), 2);
+    }
+
+    #[test]
+    fn undoing_an_entity_delete_restores_it_on_a_new_entity() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let history = History::new(&mut world);
+        world.insert(history);
+
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        observe(&world);
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(entity).unwrap();
+            attribute.edit_as(Value::Int(2));
+            world
+                .write_resource::<History>()
+                .commit(entity, attribute, None)
+                .unwrap();
+        }
+        world.delete_entity(entity).unwrap();
+        world.maintain();
+        observe(&world);
+        assert_eq!(world.read_resource::<History>().undo_stack().len(), 3);
+
+        let undo = |world: &World| {
+            world
+                .write_resource::<History>()
+                .undo(&mut world.write_storage::<Attribute>())
+        };
+        let attributes = |world: &World| {
+            (&world.entities(), &world.read_storage::<Attribute>())
+                .join()
+                .map(|(e, a)| (e, a.value().clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(undo(&world));
+        world.maintain();
+        let restored = match attributes(&world)[..] {
+            [(restored, Value::Int(2))] => restored,
+            ref other => panic!("the attribute was not restored, {:?}", other),
+        };
+        assert_ne!(restored, entity);
+
+        assert!(undo(&world), "earlier operations follow the new entity");
+        assert_eq!(attributes(&world), [(restored, Value::Int(1))]);
+
+        observe(&world);
+        let history = world.read_resource::<History>();
+        assert_eq!(history.undo_stack().len(), 1, "undo is not recorded");
+        assert!(history
+            .redo_stack()
+            .iter()
+            .flat_map(|t| t.operations.iter())
+            .all(|operation| operation.entity() == restored));
+    }
+}
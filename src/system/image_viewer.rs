@@ -0,0 +1,660 @@
+use image::RgbaImage;
+use imgui::ChildWindow;
+use imgui::ColorButton;
+use imgui::Condition;
+use imgui::MouseButton;
+use imgui::TextureId;
+use imgui::Ui;
+use imgui_wgpu::Renderer;
+use imgui_wgpu::Texture;
+use imgui_wgpu::TextureConfig;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::Weak;
+
+use super::preview::Fingerprint;
+use super::widget_cache::WidgetCache;
+use super::widget_cache::EXPIRE_FRAMES;
+
+/// Largest width or height of a texture, larger images are scaled down before they are uploaded
+const MAX_TEXTURE_SIZE: u32 = 8192;
+
+/// Largest width or height of the thumbnail
+const THUMBNAIL_SIZE: f32 = 256.0;
+
+/// Image formats that can be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Bmp,
+}
+
+impl Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageFormat::Png => write!(f, "PNG"),
+            ImageFormat::Jpeg => write!(f, "JPEG"),
+            ImageFormat::Bmp => write!(f, "BMP"),
+        }
+    }
+}
+
+impl ImageFormat {
+    /// returns the format of `bytes` from their signature
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if bytes.starts_with(b"BM") && bytes.len() > 26 {
+            Some(ImageFormat::Bmp)
+        } else {
+            None
+        }
+    }
+
+    fn decoder_format(self) -> image::ImageFormat {
+        match self {
+            ImageFormat::Png => image::ImageFormat::Png,
+            ImageFormat::Jpeg => image::ImageFormat::Jpeg,
+            ImageFormat::Bmp => image::ImageFormat::Bmp,
+        }
+    }
+}
+
+enum ImageState {
+    /// waiting for, or being decoded by, the decoder thread, which skips the image if the token was dropped
+    Decoding {
+        result: Receiver<Result<RgbaImage, String>>,
+        _wanted: Arc<()>,
+    },
+    /// decoded, the texture is uploaded by `upload_textures` before the next frame is rendered
+    Decoded {
+        image: Arc<RgbaImage>,
+        texture: Option<TextureId>,
+    },
+    Failed(String),
+}
+
+/// a decoded image and its texture, if it was uploaded already
+type Decoded = (Arc<RgbaImage>, Option<TextureId>);
+
+struct CachedImage {
+    fingerprint: Fingerprint,
+    state: ImageState,
+    frame: i32,
+}
+
+/// Images are not kept in a `WidgetCache`, the textures of expired images must be removed from the renderer
+#[derive(Default)]
+struct Images {
+    /// images by the key of their attribute, images that were changed are decoded again
+    entries: BTreeMap<String, CachedImage>,
+    /// textures of dropped images, removed from the renderer by `ImageTextures::upload`
+    retired: Vec<TextureId>,
+}
+
+impl Images {
+    fn retire(&mut self, state: ImageState) {
+        if let ImageState::Decoded {
+            texture: Some(texture),
+            ..
+        } = state
+        {
+            self.retired.push(texture);
+        }
+    }
+
+    /// returns the decoded image and its texture, `Ok(None)` while the image is decoding
+    fn lookup(
+        &mut self,
+        key: &str,
+        format: ImageFormat,
+        bytes: &[u8],
+        frame: i32,
+    ) -> Result<Option<Decoded>, String> {
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, cached)| frame - cached.frame >= EXPIRE_FRAMES)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in expired {
+            if let Some(cached) = self.entries.remove(&key) {
+                self.retire(cached.state);
+            }
+        }
+
+        let fingerprint = Fingerprint::of(bytes);
+        if let Some(changed) = self
+            .entries
+            .remove(key)
+            .filter(|cached| cached.fingerprint != fingerprint)
+        {
+            self.retire(changed.state);
+        }
+        let cached = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| CachedImage {
+                fingerprint,
+                state: decode(format, bytes),
+                frame,
+            });
+        cached.frame = frame;
+
+        if let ImageState::Decoding { result, .. } = &cached.state {
+            cached.state = match result.try_recv() {
+                Ok(Ok(image)) if image.width() == 0 || image.height() == 0 => {
+                    ImageState::Failed("image is empty".to_string())
+                }
+                Ok(Ok(image)) => ImageState::Decoded {
+                    image: Arc::new(image),
+                    texture: None,
+                },
+                Ok(Err(err)) => ImageState::Failed(err),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => {
+                    ImageState::Failed("decoder stopped unexpectedly".to_string())
+                }
+            };
+        }
+
+        match &cached.state {
+            ImageState::Decoded { image, texture } => Ok(Some((image.clone(), *texture))),
+            ImageState::Failed(err) => Err(err.clone()),
+            ImageState::Decoding { .. } => Ok(None),
+        }
+    }
+}
+
+/// Images of each imgui context, the textures of an image are only valid for the renderer of its context
+///
+/// Contexts are identified by the address of their `Io`, which lives as long as the context
+fn images() -> &'static Mutex<HashMap<usize, Images>> {
+    static IMAGES: OnceLock<Mutex<HashMap<usize, Images>>> = OnceLock::new();
+    IMAGES.get_or_init(Default::default)
+}
+
+fn context_id(io: &imgui::Io) -> usize {
+    io as *const imgui::Io as usize
+}
+
+/// an image to decode, where to send the result, and whether it is still wanted
+type DecodeJob = (
+    ImageFormat,
+    Vec<u8>,
+    Sender<Result<RgbaImage, String>>,
+    Weak<()>,
+);
+
+/// Queues `bytes` to be decoded by the decoder thread, which decodes one image at a time
+fn decode(format: ImageFormat, bytes: &[u8]) -> ImageState {
+    static DECODER: OnceLock<Mutex<Sender<DecodeJob>>> = OnceLock::new();
+    let decoder = DECODER.get_or_init(|| {
+        let (jobs, queue) = channel::<DecodeJob>();
+        let spawned = std::thread::Builder::new()
+            .name("image decoder".to_string())
+            .spawn(move || {
+                for (format, bytes, result, wanted) in queue {
+                    // the image was dropped from the cache while it was queued
+                    if wanted.upgrade().is_none() {
+                        continue;
+                    }
+                    let decoded =
+                        image::load_from_memory_with_format(&bytes, format.decoder_format())
+                            .map(|image| image.to_rgba8())
+                            .map_err(|err| err.to_string());
+                    result.send(decoded).ok();
+                }
+            });
+        if let Err(err) = spawned {
+            eprintln!("could not start the image decoder, {}", err);
+        }
+        Mutex::new(jobs)
+    });
+
+    let (sender, receiver) = channel();
+    let wanted = Arc::new(());
+    decoder
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .send((format, bytes.to_vec(), sender, Arc::downgrade(&wanted)))
+        .ok();
+    ImageState::Decoding {
+        result: receiver,
+        _wanted: wanted,
+    }
+}
+
+/// returns the decoded image in `bytes`, which are the value of the attribute identified by `key`,
+/// and its texture for the renderer of the current context
+fn lookup(
+    key: &str,
+    format: ImageFormat,
+    bytes: &[u8],
+    ui: &Ui,
+) -> Result<Option<Decoded>, String> {
+    images()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(context_id(ui.io()))
+        .or_default()
+        .lookup(key, format, bytes, ui.frame_count())
+}
+
+/// Textures of the images shown by one imgui context, owned by the window that renders it
+///
+/// The images of the context are dropped with it, their textures are dropped with the renderer
+pub struct ImageTextures {
+    context: usize,
+}
+
+impl ImageTextures {
+    pub(super) fn new(imgui: &imgui::Context) -> Self {
+        Self {
+            context: context_id(imgui.io()),
+        }
+    }
+
+    /// Uploads the textures of decoded images, and removes the textures of dropped images from `renderer`
+    ///
+    /// Called by the event loop after the ui is built, before the frame is rendered
+    pub(super) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut Renderer,
+    ) {
+        let mut images = images().lock().unwrap_or_else(|e| e.into_inner());
+        let images = match images.get_mut(&self.context) {
+            Some(images) => images,
+            None => return,
+        };
+        for texture in images.retired.drain(..) {
+            renderer.textures.remove(texture);
+        }
+
+        for cached in images.entries.values_mut() {
+            if let ImageState::Decoded {
+                image,
+                texture: texture @ None,
+            } = &mut cached.state
+            {
+                let scaled;
+                let pixels: &RgbaImage = if image.width().max(image.height()) > MAX_TEXTURE_SIZE {
+                    scaled = image::imageops::thumbnail(
+                        image.as_ref(),
+                        (image.width() as u64 * MAX_TEXTURE_SIZE as u64
+                            / image.width().max(image.height()) as u64)
+                            .max(1) as u32,
+                        (image.height() as u64 * MAX_TEXTURE_SIZE as u64
+                            / image.width().max(image.height()) as u64)
+                            .max(1) as u32,
+                    );
+                    &scaled
+                } else {
+                    image
+                };
+
+                let mut config = TextureConfig {
+                    size: wgpu::Extent3d {
+                        width: pixels.width(),
+                        height: pixels.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    label: Some("image viewer"),
+                    format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+                    ..Default::default()
+                };
+                // pixels stay sharp when zoomed in, so they can be inspected
+                config.sampler_desc.mag_filter = wgpu::FilterMode::Nearest;
+
+                let uploaded = Texture::new(device, renderer, config);
+                uploaded.write(queue, pixels.as_raw(), pixels.width(), pixels.height());
+                *texture = Some(renderer.textures.insert(uploaded));
+            }
+        }
+    }
+}
+
+impl Drop for ImageTextures {
+    fn drop(&mut self) {
+        images()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.context);
+    }
+}
+
+/// Viewer for PNG, JPEG and BMP images, with a thumbnail and a zoomable, pannable window with pixel inspection
+///
+/// Images are decoded on a background thread and cached by the key of the viewer until their bytes change,
+/// textures are uploaded through the texture map of the `imgui_wgpu::Renderer` of the window by its `ImageTextures`
+#[derive(Debug, Clone)]
+pub struct ImageViewer {
+    /// true if the viewer window is open
+    pub open: bool,
+    zoom: f32,
+    pan: [f32; 2],
+    /// if true the image is zoomed to fit the window
+    fit: bool,
+}
+
+impl Default for ImageViewer {
+    fn default() -> Self {
+        Self {
+            open: false,
+            zoom: 1.0,
+            pan: [0.0, 0.0],
+            fit: true,
+        }
+    }
+}
+
+impl ImageViewer {
+    /// Shows a thumbnail of the image in `bytes`, and the viewer window if it is open
+    ///
+    /// Shows nothing if `bytes` are not in a supported format, `id` must be unique to the image
+    pub fn ui(&mut self, id: &str, bytes: &[u8], ui: &Ui) {
+        let format = match ImageFormat::detect(bytes) {
+            Some(format) => format,
+            None => return,
+        };
+
+        let (image, texture) = match lookup(id, format, bytes, ui) {
+            Ok(Some(decoded)) => decoded,
+            Ok(None) => {
+                ui.text_disabled(format!("decoding {} image...", format));
+                return;
+            }
+            Err(err) => {
+                ui.text_colored(
+                    [1.0, 0.4, 0.4, 1.0],
+                    format!("could not decode {} image, {}", format, err),
+                );
+                return;
+            }
+        };
+
+        let _id = ui.push_id(id);
+        ui.text(format!(
+            "{} image, {}x{}",
+            format,
+            image.width(),
+            image.height()
+        ));
+        ui.same_line();
+        if ui.button("view") {
+            self.open = true;
+        }
+
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return,
+        };
+
+        let scale = (THUMBNAIL_SIZE / image.width().max(image.height()) as f32).min(1.0);
+        let size = [image.width() as f32 * scale, image.height() as f32 * scale];
+        imgui::Image::new(texture, size).build(ui);
+        if ui.is_item_hovered() {
+            let [x, y] = ui.item_rect_min();
+            let [mouse_x, mouse_y] = ui.io().mouse_pos;
+            pixel_tooltip(&image, (mouse_x - x) / scale, (mouse_y - y) / scale, ui);
+        }
+        if ui.is_item_clicked() {
+            self.open = true;
+        }
+
+        if self.open {
+            let mut open = self.open;
+            imgui::Window::new(format!("{} image {}##image viewer", format, id))
+                .size([800.0, 600.0], Condition::FirstUseEver)
+                .opened(&mut open)
+                .build(ui, || self.viewer_ui(&image, texture, ui));
+            self.open = open;
+        }
+    }
+
+    fn viewer_ui(&mut self, image: &RgbaImage, texture: TextureId, ui: &Ui) {
+        let size = [image.width() as f32, image.height() as f32];
+
+        if ui.button("fit") {
+            self.fit = true;
+        }
+        ui.same_line();
+        let actual_size = ui.button("1:1");
+        ui.same_line();
+        let mut percent = self.zoom * 100.0;
+        ui.set_next_item_width(200.0);
+        let zoomed = imgui::Slider::new("zoom %", 5.0, 6400.0)
+            .flags(imgui::SliderFlags::LOGARITHMIC)
+            .build(ui, &mut percent);
+
+        ChildWindow::new("canvas")
+            .border(true)
+            .scroll_bar(false)
+            .scrollable(false)
+            .build(ui, || {
+                let origin = ui.cursor_screen_pos();
+                let canvas = ui.content_region_avail();
+                if canvas[0] <= 0.0 || canvas[1] <= 0.0 {
+                    return;
+                }
+                ui.invisible_button("canvas", canvas);
+                let hovered = ui.is_item_hovered();
+                let [mouse_x, mouse_y] = ui.io().mouse_pos;
+                let center = [canvas[0] / 2.0, canvas[1] / 2.0];
+
+                if self.fit {
+                    self.zoom = (canvas[0] / size[0]).min(canvas[1] / size[1]);
+                    self.pan = [
+                        (canvas[0] - size[0] * self.zoom) / 2.0,
+                        (canvas[1] - size[1] * self.zoom) / 2.0,
+                    ];
+                }
+
+                if actual_size {
+                    self.zoom_to(1.0, center);
+                } else if zoomed {
+                    self.zoom_to(percent / 100.0, center);
+                }
+                let wheel = ui.io().mouse_wheel;
+                if hovered && wheel != 0.0 {
+                    self.zoom_to(
+                        self.zoom * 1.2f32.powf(wheel),
+                        [mouse_x - origin[0], mouse_y - origin[1]],
+                    );
+                }
+                if ui.is_item_active() && ui.is_mouse_dragging(MouseButton::Left) {
+                    let [dx, dy] = ui.io().mouse_delta;
+                    self.pan = [self.pan[0] + dx, self.pan[1] + dy];
+                    self.fit = false;
+                }
+
+                let min = [origin[0] + self.pan[0], origin[1] + self.pan[1]];
+                let max = [min[0] + size[0] * self.zoom, min[1] + size[1] * self.zoom];
+                let draw_list = ui.get_window_draw_list();
+                draw_list.with_clip_rect_intersect(
+                    origin,
+                    [origin[0] + canvas[0], origin[1] + canvas[1]],
+                    || {
+                        draw_list.add_image(texture, min, max).build();
+
+                        let x = ((mouse_x - min[0]) / self.zoom).floor();
+                        let y = ((mouse_y - min[1]) / self.zoom).floor();
+                        if hovered && self.zoom >= 4.0 && x >= 0.0 && y >= 0.0 {
+                            let pixel = [min[0] + x * self.zoom, min[1] + y * self.zoom];
+                            draw_list
+                                .add_rect(
+                                    pixel,
+                                    [pixel[0] + self.zoom, pixel[1] + self.zoom],
+                                    [1.0, 1.0, 1.0, 0.8],
+                                )
+                                .build();
+                        }
+                    },
+                );
+                drop(draw_list);
+
+                if hovered {
+                    pixel_tooltip(
+                        image,
+                        (mouse_x - min[0]) / self.zoom,
+                        (mouse_y - min[1]) / self.zoom,
+                        ui,
+                    );
+                }
+            });
+    }
+
+    /// zooms around `at`, relative to the canvas, so the pixel under it stays in place
+    fn zoom_to(&mut self, zoom: f32, at: [f32; 2]) {
+        let zoom = zoom.clamp(0.05, 64.0);
+        self.pan = [
+            at[0] - (at[0] - self.pan[0]) * zoom / self.zoom,
+            at[1] - (at[1] - self.pan[1]) * zoom / self.zoom,
+        ];
+        self.zoom = zoom;
+        self.fit = false;
+    }
+}
+
+/// shows the position and color of the pixel at `x`, `y` of `image`, if it is inside the image
+fn pixel_tooltip(image: &RgbaImage, x: f32, y: f32, ui: &Ui) {
+    if x < 0.0 || y < 0.0 {
+        return;
+    }
+
+    let (x, y) = (x as u32, y as u32);
+    if let Some(pixel) = image.get_pixel_checked(x, y) {
+        let [r, g, b, a] = pixel.0;
+        ui.tooltip(|| {
+            ColorButton::new("##pixel", [r, g, b, a].map(|c| c as f32 / 255.0)).build(ui);
+            ui.same_line();
+            ui.text(format!(
+                "{}, {}\nrgba({}, {}, {}, {})\n#{:02x}{:02x}{:02x}{:02x}",
+                x, y, r, g, b, a, r, g, b, a
+            ));
+        });
+    }
+}
+
+/// Shows an image viewer for the bytes of the attribute identified by `key`
+///
+/// The viewer is kept between frames so that zoom and pan are not lost, `key` must be unique to the attribute
+pub(super) fn image_ui(key: &str, bytes: &[u8], ui: &Ui) {
+    WidgetCache::<ImageViewer>::lock(ui)
+        .get_or_insert_with(key, ImageViewer::default)
+        .ui(key, bytes, ui);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageFormat;
+    use super::ImageState;
+    use super::Images;
+    use image::Rgba;
+    use image::RgbaImage;
+    use imgui::TextureId;
+    use std::io::Cursor;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn encode(image: &RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        image
+            .write_to(&mut bytes, format)
+            .expect("should encode the image");
+        bytes.into_inner()
+    }
+
+    /// looks up `bytes` once per frame, starting at `frame`, until the decoder is done
+    fn decoded(
+        images: &mut Images,
+        key: &str,
+        bytes: &[u8],
+        frame: &mut i32,
+    ) -> Result<RgbaImage, String> {
+        let format = ImageFormat::detect(bytes).expect("should be an image");
+        let started = Instant::now();
+        loop {
+            *frame += 1;
+            if let Some((image, _)) = images.lookup(key, format, bytes, *frame)? {
+                return Ok(image.as_ref().clone());
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "image should be decoded"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn formats_are_detected_by_signature() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        assert_eq!(
+            ImageFormat::detect(&encode(&image, image::ImageFormat::Png)),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::detect(&encode(&image, image::ImageFormat::Bmp)),
+            Some(ImageFormat::Bmp)
+        );
+        assert_eq!(
+            ImageFormat::detect(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(ImageFormat::Jpeg)
+        );
+
+        assert_eq!(
+            ImageFormat::detect(b"BM"),
+            None,
+            "too short for a bmp header"
+        );
+        assert_eq!(ImageFormat::detect(b"\x89PNG"), None);
+        assert_eq!(ImageFormat::detect(b"hello world"), None);
+        assert_eq!(ImageFormat::detect(&[]), None);
+    }
+
+    #[test]
+    fn images_are_decoded_again_when_their_bytes_change() {
+        let mut images = Images::default();
+        let mut frame = 0;
+
+        let red = RgbaImage::from_pixel(3, 2, Rgba([255, 0, 0, 255]));
+        let bytes = encode(&red, image::ImageFormat::Png);
+        assert_eq!(decoded(&mut images, "image", &bytes, &mut frame), Ok(red));
+
+        // pretend the renderer uploaded the texture, it must be removed once the image changes
+        let texture = TextureId::new(7);
+        if let Some(ImageState::Decoded {
+            texture: uploaded, ..
+        }) = images
+            .entries
+            .get_mut("image")
+            .map(|cached| &mut cached.state)
+        {
+            *uploaded = Some(texture);
+        }
+
+        let blue = RgbaImage::from_pixel(2, 3, Rgba([0, 0, 255, 128]));
+        let bytes = encode(&blue, image::ImageFormat::Bmp);
+        assert_eq!(decoded(&mut images, "image", &bytes, &mut frame), Ok(blue));
+        assert_eq!(images.retired, vec![texture]);
+        assert_eq!(images.entries.len(), 1);
+
+        let mut broken = bytes.clone();
+        broken.truncate(30);
+        assert!(decoded(&mut images, "broken", &broken, &mut frame).is_err());
+    }
+}
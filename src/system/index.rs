@@ -0,0 +1,320 @@
+use specs::storage::MaskedStorage;
+use specs::world::EntitiesRes;
+use specs::Entity;
+use specs::Join;
+use specs::Storage;
+use specs::World;
+use specs::WorldExt;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ops::Deref;
+
+use super::Attribute;
+use super::AttributeEvent;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Extension;
+use super::Subscription;
+use super::ValueKind;
+
+/// Indexed part of an attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    entity: Entity,
+    id: u32,
+    name: String,
+    kind: ValueKind,
+}
+
+/// Resource that indexes the attributes in a world by name, by `Attribute::id`, and by value kind
+///
+/// The index is kept up to date by the `Indexing` extension, see `AttributeQuery` to search it
+#[derive(Debug, Default)]
+pub struct AttributeIndex {
+    entries: BTreeMap<u32, Entry>,
+    names: BTreeMap<String, BTreeSet<u32>>,
+    ids: BTreeMap<u32, BTreeSet<u32>>,
+    kinds: BTreeMap<ValueKind, BTreeSet<u32>>,
+}
+
+impl AttributeIndex {
+    /// returns an index of every attribute in `world`
+    pub fn new(world: &World) -> Self {
+        let mut index = Self::default();
+        index.rebuild(&world.entities(), &world.read_component::<Attribute>());
+        index
+    }
+
+    /// clears the index and indexes every attribute in `attributes`
+    pub fn rebuild<D>(&mut self, entities: &EntitiesRes, attributes: &Storage<Attribute, D>)
+    where
+        D: Deref<Target = MaskedStorage<Attribute>>,
+    {
+        *self = Self::default();
+        for (entity, attribute) in (entities, attributes).join() {
+            self.insert(entity, attribute);
+        }
+    }
+
+    /// re-indexes the attributes that `events` refer to
+    pub fn update<D>(
+        &mut self,
+        entities: &EntitiesRes,
+        attributes: &Storage<Attribute, D>,
+        events: &[AttributeEvent],
+    ) where
+        D: Deref<Target = MaskedStorage<Attribute>>,
+    {
+        for index in events.iter().map(|e| e.entity).collect::<BTreeSet<_>>() {
+            self.remove(index);
+
+            let entity = entities.entity(index);
+            if entities.is_alive(entity) {
+                if let Some(attribute) = attributes.get(entity) {
+                    self.insert(entity, attribute);
+                }
+            }
+        }
+    }
+
+    /// entities with an attribute called `name`
+    pub fn with_name(&self, name: &str) -> Vec<Entity> {
+        self.resolve(self.names.get(name))
+    }
+
+    /// entities with an attribute that has `Attribute::id` equal to `id`
+    pub fn with_id(&self, id: u32) -> Vec<Entity> {
+        self.resolve(self.ids.get(&id))
+    }
+
+    /// entities with an attribute that has a value of `kind`
+    pub fn with_kind(&self, kind: ValueKind) -> Vec<Entity> {
+        self.resolve(self.kinds.get(&kind))
+    }
+
+    /// names of the attributes that have `Attribute::id` equal to `id`
+    pub fn names_of(&self, id: u32) -> Vec<&str> {
+        self.ids
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.entries.get(i))
+            .map(|e| e.name.as_str())
+            .collect()
+    }
+
+    /// name of the attribute stored on `entity`
+    pub fn name_of(&self, entity: Entity) -> Option<&str> {
+        self.entry(entity).map(|e| e.name.as_str())
+    }
+
+    /// kind of the value of the attribute stored on `entity`
+    pub fn kind_of(&self, entity: Entity) -> Option<ValueKind> {
+        self.entry(entity).map(|e| e.kind)
+    }
+
+    /// every distinct attribute name, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(|n| n.as_str())
+    }
+
+    /// number of indexed attributes
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// returns true if no attributes are indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(super) fn names_matching<'a>(
+        &'a self,
+        matches: impl Fn(&str) -> bool + 'a,
+    ) -> impl Iterator<Item = u32> + 'a {
+        self.names
+            .iter()
+            .filter(move |(name, _)| matches(name))
+            .flat_map(|(_, indices)| indices.iter().copied())
+    }
+
+    pub(super) fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entries.keys().copied()
+    }
+
+    pub(super) fn id_indices(&self, id: u32) -> impl Iterator<Item = u32> + '_ {
+        self.ids.get(&id).into_iter().flatten().copied()
+    }
+
+    pub(super) fn kind_indices(&self, kind: ValueKind) -> impl Iterator<Item = u32> + '_ {
+        self.kinds.get(&kind).into_iter().flatten().copied()
+    }
+
+    /// entity, id, name, and kind of the attribute at `index`
+    pub(super) fn get(&self, index: u32) -> Option<(Entity, u32, &str, ValueKind)> {
+        self.entries
+            .get(&index)
+            .map(|e| (e.entity, e.id, e.name.as_str(), e.kind))
+    }
+
+    fn entry(&self, entity: Entity) -> Option<&Entry> {
+        self.entries
+            .get(&entity.id())
+            .filter(|e| e.entity == entity)
+    }
+
+    fn resolve(&self, indices: Option<&BTreeSet<u32>>) -> Vec<Entity> {
+        indices
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.entries.get(i))
+            .map(|e| e.entity)
+            .collect()
+    }
+
+    fn insert(&mut self, entity: Entity, attribute: &Attribute) {
+        let entry = Entry {
+            entity,
+            id: attribute.id(),
+            name: attribute.name().to_string(),
+            kind: attribute.value().kind(),
+        };
+
+        let index = entity.id();
+        self.names
+            .entry(entry.name.clone())
+            .or_default()
+            .insert(index);
+        self.ids.entry(entry.id).or_default().insert(index);
+        self.kinds.entry(entry.kind).or_default().insert(index);
+        self.entries.insert(index, entry);
+    }
+
+    fn remove(&mut self, index: u32) {
+        let entry = match self.entries.remove(&index) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        fn unlink<K: Ord>(map: &mut BTreeMap<K, BTreeSet<u32>>, key: &K, index: u32) {
+            if let Some(set) = map.get_mut(key) {
+                set.remove(&index);
+                if set.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+        unlink(&mut self.names, &entry.name, index);
+        unlink(&mut self.ids, &entry.id, index);
+        unlink(&mut self.kinds, &entry.kind, index);
+    }
+}
+
+/// Extension that adds an `AttributeIndex` resource to the app world, and updates it after the world is maintained
+#[derive(Default)]
+pub struct Indexing {
+    subscription: Option<Subscription>,
+}
+
+impl Extension for Indexing {
+    fn configure_app_world(world: &mut World) {
+        ChangeDetection::configure_app_world(world);
+        if !world.has_value::<AttributeIndex>() {
+            let index = AttributeIndex::new(world);
+            world.insert(index);
+        }
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        let entities = app_world.entities();
+        let attributes = app_world.read_storage::<Attribute>();
+        let mut index = app_world.write_resource::<AttributeIndex>();
+        let mut events = app_world.write_resource::<AttributeEvents>();
+        events.update(&entities, &attributes);
+
+        match self.subscription {
+            Some(subscription) => {
+                let events = events.drain(subscription);
+                if !events.is_empty() {
+                    index.update(&entities, &attributes, &events);
+                }
+            }
+            None => {
+                self.subscription = Some(events.subscribe(AttributeFilter::Any));
+                index.rebuild(&entities, &attributes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    use super::AttributeIndex;
+    use crate::system::Attribute;
+    use crate::system::AttributeEvents;
+    use crate::system::AttributeFilter;
+    use crate::system::Extension;
+    use crate::system::Indexing;
+    use crate::system::Value;
+    use crate::system::ValueKind;
+
+    #[test]
+    fn renamed_and_removed_attributes_are_reindexed() {
+        let mut world = World::new();
+        Indexing::configure_app_world(&mut world);
+        let subscription = world
+            .write_resource::<AttributeEvents>()
+            .subscribe(AttributeFilter::Any);
+        let a = world
+            .create_entity()
+            .with(Attribute::new(1, "a", Value::Int(1)))
+            .build();
+        let b = world
+            .create_entity()
+            .with(Attribute::new(1, "b", Value::Int(2)))
+            .build();
+
+        let update = |world: &World| {
+            let entities = world.entities();
+            let attributes = world.read_storage::<Attribute>();
+            let mut events = world.write_resource::<AttributeEvents>();
+            events.update(&entities, &attributes);
+            let events = events.drain(subscription);
+            world
+                .write_resource::<AttributeIndex>()
+                .update(&entities, &attributes, &events);
+        };
+        update(&world);
+        assert_eq!(world.read_resource::<AttributeIndex>().with_id(1), [a, b]);
+
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(a).unwrap();
+            attribute.name = "c".to_string();
+            attribute.value = Value::Float(1.0);
+        }
+        update(&world);
+        {
+            let index = world.read_resource::<AttributeIndex>();
+            assert!(index.with_name("a").is_empty());
+            assert_eq!(index.with_name("c"), [a]);
+            assert_eq!(index.with_kind(ValueKind::Float), [a]);
+            assert_eq!(index.with_kind(ValueKind::Int), [b]);
+            assert_eq!(index.names().collect::<Vec<_>>(), ["b", "c"]);
+        }
+
+        world.delete_entity(b).unwrap();
+        world.maintain();
+        update(&world);
+        let index = world.read_resource::<AttributeIndex>();
+        assert!(index.with_name("b").is_empty());
+        assert_eq!(index.with_id(1), [a]);
+        assert_eq!(index.names_of(1), ["c"]);
+        assert_eq!(index.name_of(b), None);
+    }
+}
@@ -0,0 +1,446 @@
+use serde_json::json;
+use serde_json::Map;
+use std::fmt::Display;
+
+use super::Attribute;
+use super::Metadata;
+use super::Pattern;
+use super::Schema;
+use super::Value;
+use super::ValueKind;
+
+/// Draft of JSON Schema used when exporting
+pub const JSON_SCHEMA_DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// Error returned when a JSON Schema can't be exported or imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonSchemaError {
+    /// more than one of the exported attributes has this name
+    DuplicateName(String),
+    /// the root of the schema must be an object with `properties`
+    NotAnObject,
+    /// a property uses a type that has no matching `Value` variant
+    UnsupportedProperty { name: String, reason: String },
+}
+
+impl Display for JsonSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonSchemaError::DuplicateName(name) => {
+                write!(f, "more than one attribute is named '{}'", name)
+            }
+            JsonSchemaError::NotAnObject => {
+                write!(f, "schema must be an object with properties")
+            }
+            JsonSchemaError::UnsupportedProperty { name, reason } => {
+                write!(f, "property '{}' is not supported, {}", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JsonSchemaError {}
+
+impl Schema {
+    /// Exports a set of attributes as a JSON Schema object
+    ///
+    /// Each attribute becomes a property, its current value is used as the default,
+    /// and metadata registered in this schema is mapped to the matching keywords.
+    /// The kind of each value is kept in `x-atlier-kind` so that the schema can be imported again,
+    /// floats that are NaN or infinite are written as the strings "NaN", "Infinity" and "-Infinity".
+    ///
+    /// Attribute names must be unique, since each one becomes a property
+    pub fn export_json_schema(
+        &self,
+        attributes: &[Attribute],
+    ) -> Result<serde_json::Value, JsonSchemaError> {
+        let mut properties = Map::new();
+        let mut required = vec![];
+
+        for attribute in attributes {
+            let mut property = kind_to_json(attribute.value().kind());
+            let property_map = property
+                .as_object_mut()
+                .expect("kind_to_json always returns an object");
+
+            property_map.insert("default".to_string(), value_to_json(attribute.value()));
+
+            if let Some(metadata) = self.get(attribute.name()) {
+                if metadata.required {
+                    required.push(attribute.name().to_string());
+                }
+                metadata_to_json(metadata, property_map);
+            }
+
+            if properties
+                .insert(attribute.name().to_string(), property)
+                .is_some()
+            {
+                return Err(JsonSchemaError::DuplicateName(attribute.name().to_string()));
+            }
+        }
+
+        Ok(json!({
+            "$schema": JSON_SCHEMA_DRAFT,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        }))
+    }
+
+    /// Imports a JSON Schema object, returning the schema with metadata for each property and
+    /// a set of attributes initialized to each property's default, or an empty value of its kind
+    ///
+    /// The returned attributes have an id of 0, use `Attribute::set_id` to assign them to an entity
+    pub fn import_json_schema(
+        json: &serde_json::Value,
+    ) -> Result<(Schema, Vec<Attribute>), JsonSchemaError> {
+        let properties = json
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .ok_or(JsonSchemaError::NotAnObject)?;
+
+        let required = json
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|n| n.as_str()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut schema = Schema::default();
+        let mut attributes = vec![];
+        for (name, property) in properties {
+            let unsupported = |reason: &str| JsonSchemaError::UnsupportedProperty {
+                name: name.to_string(),
+                reason: reason.to_string(),
+            };
+
+            let kind = json_to_kind(property).map_err(unsupported)?;
+            let value = match property.get("default") {
+                Some(default) => json_to_value(kind, default)
+                    .ok_or_else(|| unsupported("default does not match the property type"))?,
+                None => empty_value(kind),
+            };
+
+            let mut metadata = json_to_metadata(property).map_err(|err| unsupported(&err))?;
+            metadata.required = required.contains(&name.as_str());
+
+            schema.insert(name, metadata);
+            attributes.push(Attribute::new(0, name, value));
+        }
+
+        Ok((schema, attributes))
+    }
+}
+
+fn kind_to_json(kind: ValueKind) -> serde_json::Value {
+    let array = |items: &str, len: usize| {
+        json!({
+            "type": "array",
+            "items": { "type": items },
+            "minItems": len,
+            "maxItems": len,
+        })
+    };
+
+    let mut property = match kind {
+        ValueKind::Empty => json!({ "type": "null" }),
+        ValueKind::Bool => json!({ "type": "boolean" }),
+        ValueKind::TextBuffer | ValueKind::Symbol => json!({ "type": "string" }),
+        ValueKind::Int | ValueKind::Reference => json!({ "type": "integer" }),
+        ValueKind::IntPair => array("integer", 2),
+        ValueKind::IntRange => array("integer", 3),
+        ValueKind::Float => json!({ "type": "number" }),
+        ValueKind::FloatPair => array("number", 2),
+        ValueKind::FloatRange => array("number", 3),
+        ValueKind::BinaryVector => json!({ "type": "string", "contentEncoding": "base64" }),
+    };
+
+    property["x-atlier-kind"] = json!(kind.to_string());
+    property
+}
+
+fn json_to_kind(property: &serde_json::Value) -> Result<ValueKind, &'static str> {
+    if let Some(kind) = property
+        .get("x-atlier-kind")
+        .and_then(|k| serde_json::from_value(k.clone()).ok())
+    {
+        return Ok(kind);
+    }
+
+    let items_len = || {
+        let items = property
+            .get("items")
+            .and_then(|i| i.get("type"))
+            .and_then(|t| t.as_str());
+        let len = property.get("maxItems").and_then(|l| l.as_u64());
+        (items, len)
+    };
+
+    match property.get("type").and_then(|t| t.as_str()) {
+        Some("null") => Ok(ValueKind::Empty),
+        Some("boolean") => Ok(ValueKind::Bool),
+        Some("string") if property.get("contentEncoding") == Some(&json!("base64")) => {
+            Ok(ValueKind::BinaryVector)
+        }
+        Some("string") => Ok(ValueKind::TextBuffer),
+        Some("integer") => Ok(ValueKind::Int),
+        Some("number") => Ok(ValueKind::Float),
+        Some("array") => match items_len() {
+            (Some("integer"), Some(2)) => Ok(ValueKind::IntPair),
+            (Some("integer"), Some(3)) => Ok(ValueKind::IntRange),
+            (Some("number"), Some(2)) => Ok(ValueKind::FloatPair),
+            (Some("number"), Some(3)) => Ok(ValueKind::FloatRange),
+            _ => Err("only arrays of 2 or 3 integers or numbers are supported"),
+        },
+        Some(_) => Err("type has no matching value"),
+        None => Err("type is required"),
+    }
+}
+
+fn metadata_to_json(metadata: &Metadata, property: &mut Map<String, serde_json::Value>) {
+    if let Some(description) = &metadata.description {
+        property.insert("description".to_string(), json!(description));
+    }
+    if let Some(units) = &metadata.units {
+        property.insert("x-units".to_string(), json!(units));
+    }
+    if let Some(min) = metadata.min {
+        property.insert("minimum".to_string(), json!(min));
+    }
+    if let Some(max) = metadata.max {
+        property.insert("maximum".to_string(), json!(max));
+    }
+    if let Some(step) = metadata.step {
+        property.insert("multipleOf".to_string(), json!(step));
+    }
+    if let Some(pattern) = &metadata.pattern {
+        property.insert("pattern".to_string(), json!(pattern.as_str()));
+    }
+    if !metadata.choices.is_empty() {
+        property.insert("enum".to_string(), json!(metadata.choices));
+    }
+    if metadata.read_only {
+        property.insert("readOnly".to_string(), json!(true));
+    }
+}
+
+fn json_to_metadata(property: &serde_json::Value) -> Result<Metadata, String> {
+    let string = |key: &str| property.get(key).and_then(|v| v.as_str()).map(String::from);
+    let number = |key: &str| property.get(key).and_then(|v| v.as_f64()).map(|f| f as f32);
+
+    let pattern = match string("pattern") {
+        Some(pattern) => Some(
+            Pattern::new(&pattern)
+                .map_err(|err| format!("pattern is not a valid regex, {}", err))?,
+        ),
+        None => None,
+    };
+
+    Ok(Metadata {
+        description: string("description"),
+        units: string("x-units"),
+        min: number("minimum"),
+        max: number("maximum"),
+        step: number("multipleOf"),
+        pattern,
+        choices: property
+            .get("enum")
+            .and_then(|e| e.as_array())
+            .map(|e| {
+                e.iter()
+                    .filter_map(|c| c.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        required: false,
+        read_only: property.get("readOnly") == Some(&json!(true)),
+    })
+}
+
+/// JSON has no NaN or infinities, so they are written as strings
+fn float_to_json(f: f32) -> serde_json::Value {
+    match f {
+        f if f.is_nan() => json!("NaN"),
+        f32::INFINITY => json!("Infinity"),
+        f32::NEG_INFINITY => json!("-Infinity"),
+        f => json!(f),
+    }
+}
+
+fn json_to_float(json: &serde_json::Value) -> Option<f32> {
+    match json.as_str() {
+        Some("NaN") => Some(f32::NAN),
+        Some("Infinity") => Some(f32::INFINITY),
+        Some("-Infinity") => Some(f32::NEG_INFINITY),
+        Some(_) => None,
+        None => json.as_f64().map(|f| f as f32),
+    }
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    let float = |f: &f32| float_to_json(*f);
+    match value {
+        Value::Empty => serde_json::Value::Null,
+        Value::Bool(b) => json!(b),
+        Value::TextBuffer(text) | Value::Symbol(text) => json!(text),
+        Value::Int(i) => json!(i),
+        Value::IntPair(i1, i2) => json!([i1, i2]),
+        Value::IntRange(i1, i2, i3) => json!([i1, i2, i3]),
+        Value::Float(f) => float(f),
+        Value::FloatPair(f1, f2) => json!([float(f1), float(f2)]),
+        Value::FloatRange(f1, f2, f3) => json!([float(f1), float(f2), float(f3)]),
+        Value::BinaryVector(bytes) => json!(base64::encode(bytes)),
+        Value::Reference(r) => json!(r),
+    }
+}
+
+fn json_to_value(kind: ValueKind, json: &serde_json::Value) -> Option<Value> {
+    let int = |j: &serde_json::Value| j.as_i64().and_then(|i| i32::try_from(i).ok());
+    let float = json_to_float;
+    let array = |len: usize| json.as_array().filter(|a| a.len() == len);
+
+    match kind {
+        ValueKind::Empty => json.is_null().then_some(Value::Empty),
+        ValueKind::Bool => json.as_bool().map(Value::Bool),
+        ValueKind::TextBuffer => json.as_str().map(|s| Value::TextBuffer(s.to_string())),
+        ValueKind::Symbol => json.as_str().map(|s| Value::Symbol(s.to_string())),
+        ValueKind::Int => int(json).map(Value::Int),
+        ValueKind::IntPair => array(2).and_then(|a| Some(Value::IntPair(int(&a[0])?, int(&a[1])?))),
+        ValueKind::IntRange => {
+            array(3).and_then(|a| Some(Value::IntRange(int(&a[0])?, int(&a[1])?, int(&a[2])?)))
+        }
+        ValueKind::Float => float(json).map(Value::Float),
+        ValueKind::FloatPair => {
+            array(2).and_then(|a| Some(Value::FloatPair(float(&a[0])?, float(&a[1])?)))
+        }
+        ValueKind::FloatRange => array(3).and_then(|a| {
+            Some(Value::FloatRange(
+                float(&a[0])?,
+                float(&a[1])?,
+                float(&a[2])?,
+            ))
+        }),
+        ValueKind::BinaryVector => json
+            .as_str()
+            .and_then(|s| base64::decode(s).ok())
+            .map(Value::BinaryVector),
+        ValueKind::Reference => json.as_u64().map(Value::Reference),
+    }
+}
+
+fn empty_value(kind: ValueKind) -> Value {
+    match kind {
+        ValueKind::Empty => Value::Empty,
+        ValueKind::Bool => Value::Bool(false),
+        ValueKind::TextBuffer => Value::TextBuffer(String::default()),
+        ValueKind::Int => Value::Int(0),
+        ValueKind::IntPair => Value::IntPair(0, 0),
+        ValueKind::IntRange => Value::IntRange(0, 0, 0),
+        ValueKind::Float => Value::Float(0.0),
+        ValueKind::FloatPair => Value::FloatPair(0.0, 0.0),
+        ValueKind::FloatRange => Value::FloatRange(0.0, 0.0, 0.0),
+        ValueKind::BinaryVector => Value::BinaryVector(vec![]),
+        ValueKind::Reference => Value::Reference(0),
+        ValueKind::Symbol => Value::Symbol(String::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonSchemaError;
+    use crate::system::Attribute;
+    use crate::system::Metadata;
+    use crate::system::Pattern;
+    use crate::system::Schema;
+    use crate::system::Value;
+
+    fn attributes() -> Vec<Attribute> {
+        vec![
+            Attribute::new(0, "enabled", Value::Bool(true)),
+            Attribute::new(0, "code", Value::TextBuffer("ABC".to_string())),
+            Attribute::new(0, "count", Value::Int(3)),
+            Attribute::new(0, "size", Value::IntPair(1, 2)),
+            Attribute::new(0, "slider", Value::IntRange(1, 0, 5)),
+            Attribute::new(0, "scale", Value::Float(0.5)),
+            Attribute::new(0, "offset", Value::FloatPair(-1.0, 1.0)),
+            Attribute::new(0, "zoom", Value::FloatRange(1.0, 0.5, 2.0)),
+            Attribute::new(0, "data", Value::BinaryVector(vec![0, 1, 255])),
+            Attribute::new(0, "target", Value::Reference(u64::MAX)),
+            Attribute::new(0, "mode", Value::Symbol("fast".to_string())),
+            Attribute::new(0, "nothing", Value::Empty),
+        ]
+    }
+
+    #[test]
+    fn exported_schemas_import_again() {
+        let mut schema = Schema::default();
+        schema.insert(
+            "code",
+            Metadata {
+                description: Some("three capital letters".to_string()),
+                pattern: Some(Pattern::new("^[A-Z]{3}$").unwrap()),
+                required: true,
+                ..Default::default()
+            },
+        );
+        schema.insert(
+            "count",
+            Metadata {
+                units: Some("items".to_string()),
+                min: Some(0.0),
+                max: Some(10.0),
+                step: Some(1.0),
+                read_only: true,
+                ..Default::default()
+            },
+        );
+        schema.insert(
+            "mode",
+            Metadata {
+                choices: vec!["fast".to_string(), "slow".to_string()],
+                ..Default::default()
+            },
+        );
+
+        let json = schema.export_json_schema(&attributes()).unwrap();
+        let (imported, mut imported_attributes) = Schema::import_json_schema(&json).unwrap();
+
+        let mut expected = attributes();
+        expected.sort_by(|a, b| a.name().cmp(b.name()));
+        imported_attributes.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(imported_attributes, expected);
+
+        for (name, metadata) in schema.iter() {
+            assert_eq!(imported.get(name), Some(metadata), "metadata of {}", name);
+        }
+        assert_eq!(imported.get("scale"), Some(&Metadata::default()));
+    }
+
+    #[test]
+    fn floats_that_are_not_numbers_are_kept() {
+        let attributes = vec![
+            Attribute::new(0, "nan", Value::Float(f32::NAN)),
+            Attribute::new(0, "pair", Value::FloatPair(f32::INFINITY, 1.0)),
+            Attribute::new(0, "range", Value::FloatRange(0.0, f32::NEG_INFINITY, 1.0)),
+        ];
+
+        let json = Schema::default().export_json_schema(&attributes).unwrap();
+        assert_eq!(json["properties"]["nan"]["default"], "NaN");
+        let (_, mut imported) = Schema::import_json_schema(&json).unwrap();
+        imported.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(imported, attributes);
+    }
+
+    #[test]
+    fn duplicate_names_are_not_exported() {
+        let attributes = vec![
+            Attribute::new(1, "name", Value::Int(1)),
+            Attribute::new(2, "name", Value::Int(2)),
+        ];
+        assert_eq!(
+            Schema::default().export_json_schema(&attributes),
+            Err(JsonSchemaError::DuplicateName("name".to_string()))
+        );
+    }
+}
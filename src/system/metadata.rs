@@ -0,0 +1,348 @@
+use imgui::Ui;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::Attribute;
+use super::Value;
+
+/// Metadata that describes and constrains attributes with the same name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// shown as a tooltip when editing the attribute
+    pub description: Option<String>,
+    /// shown next to the value when editing the attribute
+    pub units: Option<String>,
+    /// lower bound for numeric values
+    pub min: Option<f32>,
+    /// upper bound for numeric values
+    pub max: Option<f32>,
+    /// numeric values must be a multiple of step, starting from min (or 0)
+    pub step: Option<f32>,
+    /// text values must match this regex
+    pub pattern: Option<Pattern>,
+    /// text values must be one of these choices
+    pub choices: Vec<String>,
+    /// the value cannot be empty
+    pub required: bool,
+    /// the attribute cannot be edited
+    pub read_only: bool,
+}
+
+/// Error returned when a value does not satisfy its metadata
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// the attribute is read-only
+    ReadOnly,
+    /// the attribute is required but the value is empty
+    Required,
+    /// a numeric value is outside of [min, max]
+    OutOfRange {
+        value: f32,
+        min: Option<f32>,
+        max: Option<f32>,
+    },
+    /// a numeric value is not a multiple of step
+    NotAStep { value: f32, step: f32 },
+    /// a text value does not match the pattern
+    PatternMismatch { pattern: String },
+    /// a text value is not one of the choices
+    NotAChoice { choices: Vec<String> },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::ReadOnly => write!(f, "attribute is read-only"),
+            ValidationError::Required => write!(f, "a value is required"),
+            ValidationError::OutOfRange { value, min, max } => {
+                write!(f, "{} is out of range", value)?;
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(f, ", expected {} to {}", min, max),
+                    (Some(min), None) => write!(f, ", expected at least {}", min),
+                    (None, Some(max)) => write!(f, ", expected at most {}", max),
+                    (None, None) => Ok(()),
+                }
+            }
+            ValidationError::NotAStep { value, step } => {
+                write!(f, "{} is not a multiple of {}", value, step)
+            }
+            ValidationError::PatternMismatch { pattern } => {
+                write!(f, "value does not match '{}'", pattern)
+            }
+            ValidationError::NotAChoice { choices } => {
+                write!(f, "value must be one of {}", choices.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Metadata {
+    /// Validates `value` against the constraints of this metadata
+    ///
+    /// Numeric constraints apply to each component of `Int`/`Float` values and pairs,
+    /// for `IntRange`/`FloatRange` only the current value (the first component) is checked.
+    /// Text constraints apply to `TextBuffer` and `Symbol` values
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationError> {
+        let numbers = match value {
+            Value::Int(i) | Value::IntRange(i, ..) => vec![*i as f32],
+            Value::IntPair(i1, i2) => vec![*i1 as f32, *i2 as f32],
+            Value::Float(f) | Value::FloatRange(f, ..) => vec![*f],
+            Value::FloatPair(f1, f2) => vec![*f1, *f2],
+            _ => vec![],
+        };
+
+        for number in numbers {
+            self.validate_number(number)?;
+        }
+
+        match value {
+            Value::Empty if self.required => Err(ValidationError::Required),
+            Value::TextBuffer(text) | Value::Symbol(text) => self.validate_text(text),
+            Value::BinaryVector(bytes) if self.required && bytes.is_empty() => {
+                Err(ValidationError::Required)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// rounds `value` to the nearest multiple of step, starting from min (or 0), if there is a step
+    pub(super) fn snap(&self, value: f32) -> f32 {
+        match self.step.filter(|s| *s > 0.0) {
+            Some(step) => {
+                let origin = self.min.unwrap_or(0.0);
+                origin + ((value - origin) / step).round() * step
+            }
+            None => value,
+        }
+    }
+
+    fn validate_number(&self, value: f32) -> Result<(), ValidationError> {
+        let below = self.min.is_some_and(|min| value < min);
+        let above = self.max.is_some_and(|max| value > max);
+        if below || above || value.is_nan() {
+            return Err(ValidationError::OutOfRange {
+                value,
+                min: self.min,
+                max: self.max,
+            });
+        }
+
+        if let Some(step) = self.step.filter(|s| *s > 0.0) {
+            let steps = (value - self.min.unwrap_or(0.0)) / step;
+            if (steps - steps.round()).abs() > 1e-4 {
+                return Err(ValidationError::NotAStep { value, step });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_text(&self, text: &str) -> Result<(), ValidationError> {
+        if self.required && text.is_empty() {
+            return Err(ValidationError::Required);
+        }
+
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == text) {
+            return Err(ValidationError::NotAChoice {
+                choices: self.choices.clone(),
+            });
+        }
+
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(text) {
+                return Err(ValidationError::PatternMismatch {
+                    pattern: pattern.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Regex that text values must match, compiled once when it is parsed
+///
+/// Serialized as the source of the regex
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Pattern(Regex::new(pattern)?))
+    }
+
+    /// returns true if `text` matches this pattern
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// returns the source of this pattern
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Pattern::new(pattern)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Pattern::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Resource that maps attribute names to their metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schema {
+    metadata: BTreeMap<String, Metadata>,
+}
+
+impl Schema {
+    /// sets the metadata for attributes named `name`
+    pub fn insert(&mut self, name: impl AsRef<str>, metadata: Metadata) {
+        self.metadata.insert(name.as_ref().to_string(), metadata);
+    }
+
+    /// returns the metadata for attributes named `name`
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&Metadata> {
+        self.metadata.get(name.as_ref())
+    }
+
+    /// removes the metadata for attributes named `name`
+    pub fn remove(&mut self, name: impl AsRef<str>) -> Option<Metadata> {
+        self.metadata.remove(name.as_ref())
+    }
+
+    /// iterates over all attribute names and their metadata
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Metadata)> {
+        self.metadata.iter()
+    }
+
+    /// Validates the pending changes of `attribute` against the metadata for its name,
+    /// attributes without metadata are always valid
+    pub fn validate(&self, attribute: &Attribute) -> Result<(), ValidationError> {
+        match self.get(attribute.name()) {
+            Some(metadata) => attribute.validate(metadata),
+            None => Ok(()),
+        }
+    }
+
+    /// Commits the pending changes of `attribute` if they are valid, see `Attribute::try_commit`
+    ///
+    /// returns the previous name and value if there were pending changes
+    pub fn commit(
+        &self,
+        attribute: &mut Attribute,
+    ) -> Result<Option<(String, Value)>, ValidationError> {
+        self.validate(attribute)?;
+        Ok(attribute.commit_unchecked())
+    }
+
+    /// Shows an editor for `attribute` with `Attribute::edit_attr_with` and the metadata for its name
+    ///
+    /// returns the previous name and value if changes were saved
+    pub fn edit_attr(&self, attribute: &mut Attribute, ui: &Ui) -> Option<(String, Value)> {
+        let metadata = self.get(attribute.name());
+        attribute.edit_attr_with(ui, metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+    use super::Pattern;
+    use super::Schema;
+    use super::ValidationError;
+    use crate::system::Attribute;
+    use crate::system::Value;
+
+    fn schema() -> Schema {
+        let mut schema = Schema::default();
+        schema.insert(
+            "code",
+            Metadata {
+                pattern: Some(Pattern::new("^[A-Z]{3}$").unwrap()),
+                ..Default::default()
+            },
+        );
+        schema
+    }
+
+    #[test]
+    fn invalid_changes_are_not_committed() {
+        let mut attribute = Attribute::new(0, "code", Value::TextBuffer("ABC".to_string()));
+        attribute.edit_as(Value::TextBuffer("abc".to_string()));
+
+        assert_eq!(
+            schema().commit(&mut attribute),
+            Err(ValidationError::PatternMismatch {
+                pattern: "^[A-Z]{3}$".to_string()
+            })
+        );
+        assert_eq!(attribute.value(), &Value::TextBuffer("ABC".to_string()));
+        assert!(!attribute.is_stable(), "pending changes are kept");
+
+        attribute.edit_as(Value::TextBuffer("XYZ".to_string()));
+        assert!(schema().commit(&mut attribute).unwrap().is_some());
+        assert_eq!(attribute.value(), &Value::TextBuffer("XYZ".to_string()));
+    }
+
+    #[test]
+    fn patterns_are_serialized_as_their_source() {
+        let json = serde_json::to_string(&Pattern::new("a+").unwrap()).unwrap();
+        assert_eq!(json, r#""a+""#);
+        assert_eq!(
+            serde_json::from_str::<Pattern>(&json).unwrap(),
+            Pattern::new("a+").unwrap()
+        );
+        assert!(serde_json::from_str::<Pattern>(r#""(""#).is_err());
+    }
+
+    #[test]
+    fn numbers_snap_to_steps_from_min() {
+        let metadata = Metadata {
+            min: Some(1.0),
+            step: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(metadata.snap(2.2), 2.0);
+        assert_eq!(metadata.snap(2.3), 2.5);
+        assert_eq!(
+            metadata.validate(&Value::Float(metadata.snap(7.77))),
+            Ok(())
+        );
+        assert_eq!(Metadata::default().snap(2.2), 2.2);
+    }
+}
@@ -0,0 +1,477 @@
+use imgui::Ui;
+use serde::Deserialize;
+use serde::Serialize;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use super::conflict::as_text;
+use super::conflict::ADDED;
+use super::conflict::REMOVED;
+use super::diff_lines;
+use super::Attribute;
+use super::Change;
+use super::Value;
+
+/// Returns the stable state of every attribute in `world`, sorted by (id, name, value)
+pub fn snapshot(world: &World) -> Vec<Attribute> {
+    let mut attributes = world
+        .read_component::<Attribute>()
+        .join()
+        .map(|attribute| {
+            Attribute::new(attribute.id(), attribute.name(), attribute.value().clone())
+        })
+        .collect::<Vec<_>>();
+    attributes.sort();
+    attributes
+}
+
+/// A change to a single attribute, attributes are identified by (`id`, `name`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AttributeChange {
+    Added(Attribute),
+    Removed(Attribute),
+    /// the name changed but the value did not
+    Renamed {
+        id: u32,
+        from: String,
+        to: String,
+    },
+    Changed {
+        id: u32,
+        name: String,
+        before: Value,
+        after: Value,
+    },
+}
+
+impl AttributeChange {
+    /// returns the (`id`, `name`) this change applies to, before the change
+    pub fn key(&self) -> (u32, &str) {
+        match self {
+            AttributeChange::Added(attribute) | AttributeChange::Removed(attribute) => {
+                (attribute.id(), attribute.name())
+            }
+            AttributeChange::Renamed { id, from, .. } => (*id, from),
+            AttributeChange::Changed { id, name, .. } => (*id, name),
+        }
+    }
+}
+
+/// Error returned when a patch can't be applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// there is no attribute with this id and name
+    Missing { id: u32, name: String },
+    /// an attribute with this id and name already exists
+    Exists { id: u32, name: String },
+    /// the current value of the attribute is not the value the change was based on
+    Conflict { id: u32, name: String },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Missing { id, name } => {
+                write!(f, "attribute {} {:#4x} does not exist", name, id)
+            }
+            PatchError::Exists { id, name } => {
+                write!(f, "attribute {} {:#4x} already exists", name, id)
+            }
+            PatchError::Conflict { id, name } => write!(
+                f,
+                "attribute {} {:#4x} was changed, and no longer matches the patch",
+                name, id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Target of a change while a patch is applied
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// an attribute that exists in the world
+    Existing(Entity),
+    /// an attribute added by the change at this position of the patch
+    Added(usize),
+}
+
+/// A structured diff between two sets of attributes, which can be serialized and applied to another world
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Patch {
+    pub changes: Vec<AttributeChange>,
+}
+
+impl Patch {
+    /// Returns the changes that turn `before` into `after`
+    ///
+    /// Attributes are matched by (`id`, `name`). A removed and an added attribute with the same id and value
+    /// are reported as a rename. If a set has more than one attribute with the same id and name, the last one is used
+    pub fn diff(before: &[Attribute], after: &[Attribute]) -> Self {
+        let key = |a: &Attribute| ((a.id(), a.name().to_string()), a.value().clone());
+        let before = before.iter().map(key).collect::<BTreeMap<_, _>>();
+        let after = after.iter().map(key).collect::<BTreeMap<_, _>>();
+
+        let mut changed = vec![];
+        let mut removed = vec![];
+        for ((id, name), value) in before.iter() {
+            match after.get(&(*id, name.clone())) {
+                Some(after) if after != value => changed.push(AttributeChange::Changed {
+                    id: *id,
+                    name: name.clone(),
+                    before: value.clone(),
+                    after: after.clone(),
+                }),
+                Some(_) => {}
+                None => removed.push(Attribute::new(*id, name, value.clone())),
+            }
+        }
+
+        let mut added = after
+            .iter()
+            .filter(|(key, _)| !before.contains_key(key))
+            .map(|((id, name), value)| Some(Attribute::new(*id, name, value.clone())))
+            .collect::<Vec<_>>();
+
+        let mut changes = vec![];
+        for attribute in removed {
+            let renamed = added.iter_mut().find(|a| {
+                a.as_ref()
+                    .is_some_and(|a| a.id() == attribute.id() && a.value() == attribute.value())
+            });
+
+            match renamed.and_then(Option::take) {
+                Some(to) => changes.push(AttributeChange::Renamed {
+                    id: attribute.id(),
+                    from: attribute.name().to_string(),
+                    to: to.name().to_string(),
+                }),
+                None => changes.push(AttributeChange::Removed(attribute)),
+            }
+        }
+        changes.extend(changed);
+        changes.extend(added.into_iter().flatten().map(AttributeChange::Added));
+
+        Self { changes }
+    }
+
+    /// returns true if there are no changes
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies every change to the attributes in `world`, if any change can't be applied nothing is changed
+    ///
+    /// Added attributes are inserted on new entities, removed attributes are removed from their entity.
+    /// An added attribute whose owner (`Attribute::id`) has no attributes in `world` came from another world,
+    /// its owner is remapped to the entity of the first attribute added for that owner
+    pub fn apply(&self, world: &World) -> Result<(), Vec<PatchError>> {
+        let entities = world.entities();
+        let mut attributes = world.write_component::<Attribute>();
+
+        // target and value of each attribute as the changes are applied, by (id, name)
+        let mut index = (&entities, &attributes)
+            .join()
+            .map(|(e, a)| {
+                (
+                    (a.id(), a.name().to_string()),
+                    (Target::Existing(e), a.value().clone()),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let owners = index.keys().map(|(id, _)| *id).collect::<BTreeSet<_>>();
+
+        let mut errors = vec![];
+        let mut targets = vec![];
+        for (position, change) in self.changes.iter().enumerate() {
+            let (id, name) = change.key();
+            let key = (id, name.to_string());
+            let found = index.get(&key);
+            let error = match (change, found) {
+                (AttributeChange::Added(_), None) => None,
+                (AttributeChange::Added(_), Some(_)) => Some(PatchError::Exists {
+                    id,
+                    name: key.1.clone(),
+                }),
+                (_, None) => Some(PatchError::Missing {
+                    id,
+                    name: key.1.clone(),
+                }),
+                (AttributeChange::Changed { before, .. }, Some((_, value))) if value != before => {
+                    Some(PatchError::Conflict {
+                        id,
+                        name: key.1.clone(),
+                    })
+                }
+                (AttributeChange::Renamed { to, .. }, Some(_))
+                    if index.contains_key(&(id, to.to_string())) =>
+                {
+                    Some(PatchError::Exists {
+                        id,
+                        name: to.to_string(),
+                    })
+                }
+                _ => None,
+            };
+            targets.push(found.map(|(target, _)| *target));
+
+            match error {
+                Some(error) => errors.push(error),
+                None => {
+                    // later changes in this patch see the result of earlier ones
+                    match change {
+                        AttributeChange::Added(attribute) => {
+                            index.insert(key, (Target::Added(position), attribute.value().clone()));
+                        }
+                        AttributeChange::Removed(_) => {
+                            index.remove(&key);
+                        }
+                        AttributeChange::Renamed { to, .. } => {
+                            if let Some(state) = index.remove(&key) {
+                                index.insert((id, to.to_string()), state);
+                            }
+                        }
+                        AttributeChange::Changed { after, .. } => {
+                            if let Some((_, value)) = index.get_mut(&key) {
+                                *value = after.clone();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // entities of added attributes, by position in this patch
+        let mut added = BTreeMap::new();
+        // owners from another world, and the id they are remapped to
+        let mut remapped = BTreeMap::new();
+        for (position, (change, target)) in self.changes.iter().zip(targets).enumerate() {
+            let target = match target {
+                Some(Target::Existing(entity)) => Some(entity),
+                Some(Target::Added(position)) => added.get(&position).copied(),
+                None => None,
+            };
+
+            match (change, target) {
+                (AttributeChange::Added(attribute), _) => {
+                    let entity = entities.create();
+                    let mut attribute = attribute.clone();
+                    if !owners.contains(&attribute.id()) {
+                        let owner = *remapped.entry(attribute.id()).or_insert(entity.id());
+                        attribute.set_id(owner);
+                    }
+
+                    if let Err(err) = attributes.insert(entity, attribute) {
+                        eprintln!("Could not add attribute {}, {}", change.key().1, err);
+                    }
+                    added.insert(position, entity);
+                }
+                (AttributeChange::Removed(_), Some(entity)) => {
+                    attributes.remove(entity);
+                }
+                (AttributeChange::Renamed { to, .. }, Some(entity)) => {
+                    if let Some(attribute) = attributes.get_mut(entity) {
+                        attribute.name = to.to_string();
+                    }
+                }
+                (AttributeChange::Changed { after, .. }, Some(entity)) => {
+                    if let Some(attribute) = attributes.get_mut(entity) {
+                        attribute.value = after.clone();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shows each change with the state before on the left, and the state after on the right
+    pub fn side_by_side_ui(&self, ui: &Ui) {
+        if self.is_empty() {
+            ui.text_disabled("no changes");
+            return;
+        }
+
+        ui.columns(2, "patch", true);
+        ui.text("before");
+        ui.next_column();
+        ui.text("after");
+        ui.next_column();
+        ui.separator();
+
+        let row = |left: Option<String>, right: Option<String>| {
+            match left {
+                Some(left) => ui.text_colored(REMOVED, left),
+                None => ui.text(""),
+            }
+            ui.next_column();
+            match right {
+                Some(right) => ui.text_colored(ADDED, right),
+                None => ui.text(""),
+            }
+            ui.next_column();
+        };
+
+        for change in self.changes.iter() {
+            match change {
+                AttributeChange::Added(attribute) => row(
+                    None,
+                    Some(format!(
+                        "{} {:#4x} = {:?}",
+                        attribute.name(),
+                        attribute.id(),
+                        attribute.value()
+                    )),
+                ),
+                AttributeChange::Removed(attribute) => row(
+                    Some(format!(
+                        "{} {:#4x} = {:?}",
+                        attribute.name(),
+                        attribute.id(),
+                        attribute.value()
+                    )),
+                    None,
+                ),
+                AttributeChange::Renamed { id, from, to } => row(
+                    Some(format!("{} {:#4x}", from, id)),
+                    Some(format!("{} {:#4x}", to, id)),
+                ),
+                AttributeChange::Changed {
+                    id,
+                    name,
+                    before,
+                    after,
+                } => match (as_text(before), as_text(after)) {
+                    (Some(before), Some(after)) => {
+                        ui.text(format!("{} {:#4x}", name, id));
+                        ui.next_column();
+                        ui.text(format!("{} {:#4x}", name, id));
+                        ui.next_column();
+                        for (change, line) in diff_lines(before, after) {
+                            match change {
+                                Change::Unchanged => {
+                                    ui.text(format!("  {}", line));
+                                    ui.next_column();
+                                    ui.text(format!("  {}", line));
+                                    ui.next_column();
+                                }
+                                Change::Removed => row(Some(format!("- {}", line)), None),
+                                Change::Added => row(None, Some(format!("+ {}", line))),
+                            }
+                        }
+                    }
+                    _ => row(
+                        Some(format!("{} {:#4x} = {:?}", name, id, before)),
+                        Some(format!("{} {:#4x} = {:?}", name, id, after)),
+                    ),
+                },
+            }
+            ui.separator();
+        }
+
+        ui.columns(1, "patch", false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::snapshot;
+    use super::AttributeChange;
+    use super::Patch;
+    use super::PatchError;
+    use crate::system::Attribute;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        world
+    }
+
+    #[test]
+    fn adding_the_same_attribute_twice_is_an_error() {
+        let world = world();
+        let added = AttributeChange::Added(Attribute::new(1, "a", Value::Int(1)));
+        let patch = Patch {
+            changes: vec![added.clone(), added],
+        };
+
+        assert_eq!(
+            patch.apply(&world),
+            Err(vec![PatchError::Exists {
+                id: 1,
+                name: "a".to_string()
+            }])
+        );
+        assert!(snapshot(&world).is_empty(), "nothing is changed");
+    }
+
+    #[test]
+    fn changes_can_follow_an_added_attribute() {
+        let world = world();
+        let patch = Patch {
+            changes: vec![
+                AttributeChange::Added(Attribute::new(1, "a", Value::Int(1))),
+                AttributeChange::Changed {
+                    id: 1,
+                    name: "a".to_string(),
+                    before: Value::Int(1),
+                    after: Value::Int(2),
+                },
+                AttributeChange::Renamed {
+                    id: 1,
+                    from: "a".to_string(),
+                    to: "b".to_string(),
+                },
+            ],
+        };
+
+        assert_eq!(patch.apply(&world), Ok(()));
+        let attributes = snapshot(&world);
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name(), "b");
+        assert_eq!(attributes[0].value(), &Value::Int(2));
+    }
+
+    #[test]
+    fn foreign_owners_are_remapped() {
+        let mut world = world();
+        let local = world.create_entity().build();
+        world
+            .create_entity()
+            .with(Attribute::new(local.id(), "local", Value::Empty))
+            .build();
+
+        let foreign = 1000;
+        let patch = Patch {
+            changes: vec![
+                AttributeChange::Added(Attribute::new(foreign, "a", Value::Int(1))),
+                AttributeChange::Added(Attribute::new(foreign, "b", Value::Int(2))),
+                AttributeChange::Added(Attribute::new(local.id(), "c", Value::Int(3))),
+            ],
+        };
+        assert_eq!(patch.apply(&world), Ok(()));
+        world.maintain();
+
+        let attributes = snapshot(&world);
+        let owner = |name: &str| attributes.iter().find(|a| a.name() == name).unwrap().id();
+        assert_ne!(owner("a"), foreign);
+        assert_eq!(owner("a"), owner("b"));
+        assert!(world.entities().entity(owner("a")).gen().is_alive());
+        assert_eq!(owner("c"), local.id());
+    }
+}
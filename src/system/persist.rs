@@ -0,0 +1,364 @@
+use serde::Deserialize;
+use serde::Serialize;
+use specs::saveload::MarkerAllocator;
+use specs::saveload::SimpleMarker;
+use specs::saveload::SimpleMarkerAllocator;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use super::Attribute;
+use super::Extension;
+
+/// Current version of the project format, written to the header of every project file
+pub const PROJECT_FORMAT_VERSION: u32 = 1;
+
+/// Name of the project format, written to the header of JSON project files
+pub const PROJECT_FORMAT: &str = "atlier-project";
+
+/// Magic bytes at the start of binary project files
+pub const PROJECT_MAGIC: &[u8; 4] = b"ATLR";
+
+/// Tag type for the markers of entities that are saved to a project file
+pub struct Persisted;
+
+/// Marker component for entities that are saved to a project file
+pub type PersistedMarker = SimpleMarker<Persisted>;
+
+/// Allocator resource for `PersistedMarker`
+pub type PersistedMarkerAllocator = SimpleMarkerAllocator<Persisted>;
+
+/// Encoding of a project file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// human readable, the header is part of the root object
+    Json,
+    /// compact, the header is `PROJECT_MAGIC` followed by the version as a little endian u32
+    Binary,
+}
+
+/// Error returned when a project can't be saved or loaded
+#[derive(Debug)]
+pub enum PersistError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// the file is not a project file
+    InvalidHeader,
+    /// the file was written by a newer version of the project format
+    UnsupportedVersion(u32),
+}
+
+impl Display for PersistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistError::Io(err) => write!(f, "io error, {}", err),
+            PersistError::Json(err) => write!(f, "json error, {}", err),
+            PersistError::Binary(err) => write!(f, "binary encoding error, {}", err),
+            PersistError::InvalidHeader => write!(f, "not an atlier project"),
+            PersistError::UnsupportedVersion(version) => write!(
+                f,
+                "project format version {} is newer than the supported version {}",
+                version, PROJECT_FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+impl From<std::io::Error> for PersistError {
+    fn from(err: std::io::Error) -> Self {
+        PersistError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(err: serde_json::Error) -> Self {
+        PersistError::Json(err)
+    }
+}
+
+impl From<bincode::Error> for PersistError {
+    fn from(err: bincode::Error) -> Self {
+        PersistError::Binary(err)
+    }
+}
+
+/// An attribute, and the markers of the entity it's stored on and the entity that owns it (`Attribute::id`)
+#[derive(Serialize, Deserialize)]
+struct Record {
+    marker: PersistedMarker,
+    owner: Option<PersistedMarker>,
+    attribute: Attribute,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonProject {
+    format: String,
+    version: u32,
+    records: Vec<Record>,
+}
+
+/// Serializes every entity with an `Attribute` component
+///
+/// Entities are marked with a `PersistedMarker` so that entity ids can be remapped when loading,
+/// the owner of each attribute (`Attribute::id`) is also marked if it's alive
+pub fn save_world(world: &World, encoding: Encoding) -> Result<Vec<u8>, PersistError> {
+    let entities = world.entities();
+    let attributes = world.read_component::<Attribute>();
+    let mut markers = world.write_component::<PersistedMarker>();
+    let mut allocator = world.write_resource::<PersistedMarkerAllocator>();
+
+    // `is_alive` is true for ids that were never allocated, so owners are looked up among live entities
+    let alive = (&entities)
+        .join()
+        .map(|entity| (entity.id(), entity))
+        .collect::<HashMap<_, _>>();
+
+    let mut records = vec![];
+    for (entity, attribute) in (&entities, &attributes).join() {
+        let marker = *allocator
+            .mark(entity, &mut markers)
+            .expect("entity is alive")
+            .0;
+
+        let owner = alive
+            .get(&attribute.id())
+            .and_then(|owner| allocator.mark(*owner, &mut markers).map(|(m, _)| *m));
+
+        records.push(Record {
+            marker,
+            owner,
+            attribute: attribute.clone(),
+        });
+    }
+
+    match encoding {
+        Encoding::Json => Ok(serde_json::to_vec_pretty(&JsonProject {
+            format: PROJECT_FORMAT.to_string(),
+            version: PROJECT_FORMAT_VERSION,
+            records,
+        })?),
+        Encoding::Binary => {
+            let mut bytes = PROJECT_MAGIC.to_vec();
+            bytes.extend(PROJECT_FORMAT_VERSION.to_le_bytes());
+            bincode::serialize_into(&mut bytes, &records)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Deserializes a project that was saved with `save_world`, the encoding is detected from the header
+///
+/// Entities are matched by their `PersistedMarker`, entities that don't exist yet are created.
+/// `Attribute::id` is remapped to the id of the loaded owner entity. Returns the entities that were loaded
+pub fn load_world(world: &World, bytes: &[u8]) -> Result<Vec<Entity>, PersistError> {
+    let records = if let Some(rest) = bytes.strip_prefix(PROJECT_MAGIC) {
+        if rest.len() < 4 {
+            return Err(PersistError::InvalidHeader);
+        }
+        let (version, rest) = rest.split_at(4);
+        check_version(u32::from_le_bytes([
+            version[0], version[1], version[2], version[3],
+        ]))?;
+        bincode::deserialize::<Vec<Record>>(rest)?
+    } else {
+        let project = serde_json::from_slice::<JsonProject>(bytes)?;
+        if project.format != PROJECT_FORMAT {
+            return Err(PersistError::InvalidHeader);
+        }
+        check_version(project.version)?;
+        project.records
+    };
+
+    let entities = world.entities();
+    let mut attributes = world.write_component::<Attribute>();
+    let mut markers = world.write_component::<PersistedMarker>();
+    let mut allocator = world.write_resource::<PersistedMarkerAllocator>();
+
+    let mut loaded = vec![];
+    for Record {
+        marker,
+        owner,
+        mut attribute,
+    } in records
+    {
+        let entity = allocator.retrieve_entity(marker, &mut markers, &entities);
+        if let Some(owner) = owner {
+            let owner = allocator.retrieve_entity(owner, &mut markers, &entities);
+            attribute.set_id(owner.id());
+        }
+
+        if let Err(err) = attributes.insert(entity, attribute) {
+            eprintln!(
+                "Could not load attribute for entity {}, {}",
+                entity.id(),
+                err
+            );
+            continue;
+        }
+        loaded.push(entity);
+    }
+
+    Ok(loaded)
+}
+
+/// Saves every entity with an `Attribute` component to the file at `path`
+pub fn save_to_file(
+    world: &World,
+    path: impl AsRef<Path>,
+    encoding: Encoding,
+) -> Result<(), PersistError> {
+    std::fs::write(path, save_world(world, encoding)?)?;
+    Ok(())
+}
+
+/// Loads a project file that was saved with `save_to_file`
+pub fn load_from_file(world: &World, path: impl AsRef<Path>) -> Result<Vec<Entity>, PersistError> {
+    load_world(world, &std::fs::read(path)?)
+}
+
+fn check_version(version: u32) -> Result<(), PersistError> {
+    if version > PROJECT_FORMAT_VERSION {
+        Err(PersistError::UnsupportedVersion(version))
+    } else {
+        Ok(())
+    }
+}
+
+/// Extension that registers the components and resources used by `save_world`/`load_world`
+#[derive(Default)]
+pub struct Persistence;
+
+impl Extension for Persistence {
+    fn configure_app_world(world: &mut World) {
+        world.register::<Attribute>();
+        world.register::<PersistedMarker>();
+        world.insert(PersistedMarkerAllocator::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::Join;
+    use specs::World;
+    use specs::WorldExt;
+
+    use super::load_world;
+    use super::save_world;
+    use super::Encoding;
+    use super::PersistError;
+    use super::Persistence;
+    use super::PROJECT_MAGIC;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::Value;
+
+    fn world() -> World {
+        let mut world = World::new();
+        Persistence::configure_app_world(&mut world);
+        world
+    }
+
+    fn attributes(world: &World) -> Vec<Attribute> {
+        let mut attributes = world
+            .read_component::<Attribute>()
+            .join()
+            .cloned()
+            .collect::<Vec<_>>();
+        attributes.sort();
+        attributes
+    }
+
+    #[test]
+    fn projects_round_trip_in_both_encodings() {
+        for encoding in [Encoding::Json, Encoding::Binary] {
+            let saved = world();
+            for (i, value) in [
+                Value::Int(1),
+                Value::TextBuffer("text".to_string()),
+                Value::BinaryVector(vec![0, 1, 2]),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                saved
+                    .create_entity_unchecked()
+                    .with(Attribute::new(100, format!("a{}", i), value))
+                    .build();
+            }
+
+            let bytes = save_world(&saved, encoding).unwrap();
+            assert_eq!(
+                bytes.starts_with(PROJECT_MAGIC),
+                encoding == Encoding::Binary
+            );
+
+            let loaded = world();
+            assert_eq!(load_world(&loaded, &bytes).unwrap().len(), 3);
+            assert_eq!(attributes(&loaded), attributes(&saved));
+        }
+    }
+
+    #[test]
+    fn owners_are_remapped_to_the_loaded_entities() {
+        let saved = world();
+        let owner = saved.create_entity_unchecked().build();
+        saved
+            .create_entity_unchecked()
+            .with(Attribute::new(owner.id(), "a", Value::Int(1)))
+            .build();
+        let bytes = save_world(&saved, Encoding::Json).unwrap();
+
+        let loaded = world();
+        for _ in 0..5 {
+            loaded.create_entity_unchecked().build();
+        }
+        let entities = load_world(&loaded, &bytes).unwrap();
+        assert_eq!(entities.len(), 1);
+
+        let attribute = loaded
+            .read_component::<Attribute>()
+            .get(entities[0])
+            .cloned()
+            .unwrap();
+        assert_ne!(attribute.id(), owner.id());
+        assert!(entities[0].id() >= 5);
+        assert!(attribute.id() >= 5);
+        assert_ne!(attribute.id(), entities[0].id());
+
+        // loading again matches the entities by their markers instead of creating new ones
+        assert_eq!(load_world(&loaded, &bytes).unwrap(), entities);
+        assert_eq!(
+            loaded
+                .read_component::<Attribute>()
+                .get(entities[0])
+                .unwrap()
+                .id(),
+            attribute.id()
+        );
+    }
+
+    #[test]
+    fn newer_or_foreign_files_are_rejected() {
+        let mut bytes = PROJECT_MAGIC.to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        assert!(matches!(
+            load_world(&world(), &bytes),
+            Err(PersistError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            load_world(
+                &world(),
+                br#"{"format": "other", "version": 1, "records": []}"#
+            ),
+            Err(PersistError::InvalidHeader)
+        ));
+    }
+}
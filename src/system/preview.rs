@@ -0,0 +1,218 @@
+use imgui::ChildWindow;
+use imgui::Key;
+use imgui::ListClipper;
+use imgui::MouseButton;
+use imgui::Ui;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops::Range;
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use super::widget_cache::WidgetCache;
+
+/// Lines longer than this are cut when they are previewed
+const MAX_LINE_LEN: usize = 4096;
+
+/// Line ranges of a vector, `None` if the vector is not utf-8
+type Lines = Option<Arc<Vec<Range<usize>>>>;
+
+/// Number of bytes hashed at each sampled position of a vector
+const SAMPLE_LEN: usize = 64;
+
+/// Number of positions sampled after the start of a vector, the last one is at the end
+const SAMPLES: usize = 32;
+
+/// Cheap check of whether a vector changed, its length and a hash of evenly spaced samples of its content
+///
+/// Editing a vector in place between the samples doesn't change its fingerprint,
+/// widgets that edit vectors in place call `invalidate` instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Fingerprint {
+    len: usize,
+    sample: u64,
+}
+
+impl Fingerprint {
+    pub(super) fn of(bytes: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        if bytes.len() <= SAMPLE_LEN * (SAMPLES + 1) {
+            bytes.hash(&mut hasher);
+        } else {
+            let last = bytes.len() - SAMPLE_LEN;
+            for i in 0..=SAMPLES {
+                let start = i * last / SAMPLES;
+                bytes[start..start + SAMPLE_LEN].hash(&mut hasher);
+            }
+        }
+
+        Self {
+            len: bytes.len(),
+            sample: hasher.finish(),
+        }
+    }
+}
+
+/// Lines of the vector of an attribute, and the fingerprint of the content they were decoded from
+struct Decoded {
+    fingerprint: Fingerprint,
+    lines: Lines,
+}
+
+/// returns the byte ranges of the lines of `bytes`, which are the value of the attribute identified by `key`,
+/// the bytes are only decoded again when their fingerprint changes
+fn lines(key: &str, bytes: &[u8], ui: &Ui) -> Lines {
+    let fingerprint = Fingerprint::of(bytes);
+    let mut cache = WidgetCache::<Decoded>::lock(ui);
+    let decoded = cache.get_or_insert_with(key, || Decoded {
+        fingerprint,
+        lines: decode(bytes),
+    });
+    if decoded.fingerprint != fingerprint {
+        *decoded = Decoded {
+            fingerprint,
+            lines: decode(bytes),
+        };
+    }
+    decoded.lines.clone()
+}
+
+/// drops the decoded lines of the attribute identified by `key`, e.g. after its vector was edited in place
+pub(super) fn invalidate(key: &str, ui: &Ui) {
+    WidgetCache::<Decoded>::lock(ui).remove(key);
+}
+
+fn decode(bytes: &[u8]) -> Lines {
+    let text = from_utf8(bytes).ok()?;
+    let mut start = 0;
+    let mut lines = text
+        .match_indices('\n')
+        .map(|(end, _)| {
+            let line = start..end;
+            start = end + 1;
+            line
+        })
+        .collect::<Vec<_>>();
+    lines.push(start..text.len());
+    Some(Arc::new(lines))
+}
+
+/// returns true if `bytes`, which are the value of the attribute identified by `key`, are utf-8
+pub(super) fn is_text(key: &str, bytes: &[u8], ui: &Ui) -> bool {
+    lines(key, bytes, ui).is_some()
+}
+
+/// Shows the text preview of a binary value, with a tooltip while V or the middle mouse button are held,
+/// and a popup that is pinned with a right click
+///
+/// Must be called right after the item the preview belongs to, `key` must be unique to the attribute
+pub(super) fn preview_ui(key: &str, bytes: &[u8], ui: &Ui) {
+    if bytes.is_empty() {
+        return;
+    }
+    let lines = match lines(key, bytes, ui) {
+        Some(lines) => lines,
+        None => return,
+    };
+
+    let hovered = ui.is_item_hovered();
+    let clicked = ui.is_item_clicked_with_button(MouseButton::Right);
+    let peeking = ui.is_key_down(Key::V) || ui.is_mouse_down(MouseButton::Middle);
+
+    let _id = ui.push_id(key);
+    if hovered && peeking {
+        let width = lines
+            .first()
+            .map(|l| l.len() as f32 * 16.0 + 400.0)
+            .map(|w| w.min(1360.0))
+            .unwrap_or(800.0);
+        ui.tooltip(|| {
+            ui.text("Preview - Right+Click to pin/expand");
+            lines_ui("preview-tooltip", bytes, &lines, [width, 35.0 * 16.0], ui);
+        });
+    } else if hovered {
+        ui.tooltip_text("Hold+V or Middle+Mouse to peek at content");
+    }
+
+    ui.popup("preview-popup", || {
+        ui.text(format!(
+            "Preview - {} lines, {} bytes",
+            lines.len(),
+            bytes.len()
+        ));
+        lines_ui("preview", bytes, &lines, [1360.0, 35.0 * 16.0], ui);
+    });
+    if clicked {
+        ui.open_popup("preview-popup");
+    }
+}
+
+/// shows the visible lines of `bytes`
+fn lines_ui(id: &str, bytes: &[u8], lines: &[Range<usize>], size: [f32; 2], ui: &Ui) {
+    ChildWindow::new(id)
+        .size(size)
+        .border(true)
+        .horizontal_scrollbar(true)
+        .build(ui, || {
+            let mut clipper = ListClipper::new(lines.len() as i32).begin(ui);
+            while clipper.step() {
+                for line in clipper.display_start()..clipper.display_end() {
+                    let range = lines[line as usize].clone();
+                    let end = range.end.min(range.start + MAX_LINE_LEN);
+                    // a cut line can end in the middle of a character
+                    let line = match from_utf8(&bytes[range.start..end]) {
+                        Ok(line) => line,
+                        Err(err) => from_utf8(&bytes[range.start..range.start + err.valid_up_to()])
+                            .unwrap_or_default(),
+                    };
+                    if end < range.end {
+                        ui.text(format!("{}...", line));
+                    } else {
+                        ui.text(line);
+                    }
+                }
+            }
+            clipper.end();
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use super::Fingerprint;
+    use super::SAMPLES;
+    use super::SAMPLE_LEN;
+
+    #[test]
+    fn small_vectors_are_fingerprinted_whole() {
+        let mut bytes = vec![b'a'; SAMPLE_LEN * (SAMPLES + 1)];
+        let before = Fingerprint::of(&bytes);
+        bytes[SAMPLE_LEN * 3 / 2] = b'\n';
+        assert_ne!(Fingerprint::of(&bytes), before);
+        assert_eq!(decode(&bytes).map(|lines| lines.len()), Some(2));
+
+        bytes[SAMPLE_LEN * 3 / 2] = 0xFF;
+        assert_eq!(decode(&bytes), None);
+    }
+
+    #[test]
+    fn large_vectors_are_sampled_at_the_start_and_end() {
+        let mut bytes = vec![b'a'; 1 << 20];
+        let before = Fingerprint::of(&bytes);
+
+        bytes.push(b'a');
+        assert_ne!(Fingerprint::of(&bytes), before, "length changes are seen");
+        bytes.pop();
+
+        bytes[0] = b'b';
+        assert_ne!(Fingerprint::of(&bytes), before, "the start is sampled");
+        bytes[0] = b'a';
+
+        *bytes.last_mut().unwrap() = b'b';
+        assert_ne!(Fingerprint::of(&bytes), before, "the end is sampled");
+        *bytes.last_mut().unwrap() = b'a';
+
+        assert_eq!(Fingerprint::of(&bytes), before);
+    }
+}
@@ -0,0 +1,305 @@
+use imgui::Ui;
+use specs::world::EntitiesRes;
+use specs::Join;
+use specs::ReadStorage;
+use specs::World;
+use specs::WorldExt;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use winit::event::VirtualKeyCode;
+use winit::event_loop::ControlFlow;
+
+use super::save_to_file;
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::CloseResponse;
+use super::ControlState;
+use super::Encoding;
+use super::Extension;
+use super::PersistError;
+use super::Persistence;
+use super::Subscription;
+
+/// Resource that tracks whether attributes were committed after the last save
+///
+/// Attribute events are read when the world is maintained, and the committed name/value
+/// of each changed attribute is compared with its state at the last save. The state when tracking starts
+/// is considered saved
+#[derive(Debug)]
+pub struct UnsavedChanges {
+    subscription: Subscription,
+    /// hash of the committed name/value of each attribute when it was last saved, by entity id
+    saved: HashMap<u32, u64>,
+    /// hash of the committed name/value of each attribute, by entity id
+    current: HashMap<u32, u64>,
+    /// entity ids of attributes that are different from their saved state
+    changed: HashSet<u32>,
+}
+
+impl UnsavedChanges {
+    /// subscribes to the `AttributeEvents` of `world`, which are added if there are none,
+    /// the current attributes are considered saved
+    pub fn new(world: &mut World) -> Self {
+        ChangeDetection::configure_app_world(world);
+        let subscription = world
+            .write_resource::<AttributeEvents>()
+            .subscribe(AttributeFilter::Any);
+        let current = (&world.entities(), &world.read_storage::<Attribute>())
+            .join()
+            .map(|(entity, attribute)| (entity.id(), attribute.stable_hash()))
+            .collect::<HashMap<_, _>>();
+
+        Self {
+            subscription,
+            saved: current.clone(),
+            current,
+            changed: HashSet::new(),
+        }
+    }
+
+    /// returns true if attributes were created, removed, or committed after the last save
+    pub fn is_dirty(&self) -> bool {
+        !self.changed.is_empty()
+    }
+
+    /// marks the current committed state as saved
+    pub fn mark_saved(&mut self) {
+        self.saved = self.current.clone();
+        self.changed.clear();
+    }
+
+    /// reads the attribute events since the last update,
+    /// and hashes the committed name/value of the attributes that changed
+    pub fn update(
+        &mut self,
+        events: &mut AttributeEvents,
+        entities: &EntitiesRes,
+        attributes: &ReadStorage<Attribute>,
+    ) {
+        events.update(entities, attributes);
+        for event in events.drain(self.subscription) {
+            let index = event.entity;
+
+            let current = attributes
+                .get(entities.entity(index))
+                .map(|attribute| attribute.stable_hash());
+            match current {
+                Some(hash) => self.current.insert(index, hash),
+                None => self.current.remove(&index),
+            };
+
+            if current == self.saved.get(&index).copied() {
+                self.changed.remove(&index);
+            } else {
+                self.changed.insert(index);
+            }
+        }
+    }
+}
+
+/// Extension that saves the app world to a project file with Ctrl+S,
+/// and asks to save, discard, or cancel when the window is closed with unsaved changes
+pub struct ProjectFile {
+    /// path of the project file
+    pub path: PathBuf,
+    /// encoding used when saving
+    pub encoding: Encoding,
+    confirm_close: bool,
+}
+
+impl Default for ProjectFile {
+    fn default() -> Self {
+        Self::new("project.json", Encoding::Json)
+    }
+}
+
+impl ProjectFile {
+    /// returns a project file extension that saves to `path`
+    pub fn new(path: impl Into<PathBuf>, encoding: Encoding) -> Self {
+        Self {
+            path: path.into(),
+            encoding,
+            confirm_close: false,
+        }
+    }
+
+    /// saves every attribute in the app world to the project file, and marks the changes as saved
+    pub fn save(&self, app_world: &World) -> Result<(), PersistError> {
+        save_to_file(app_world, &self.path, self.encoding)?;
+
+        let mut unsaved = app_world.write_resource::<UnsavedChanges>();
+        unsaved.update(
+            &mut app_world.write_resource::<AttributeEvents>(),
+            &app_world.entities(),
+            &app_world.read_storage::<Attribute>(),
+        );
+        unsaved.mark_saved();
+        Ok(())
+    }
+
+    fn confirm_close_ui(&mut self, app_world: &World, ui: &Ui) {
+        if !self.confirm_close {
+            return;
+        }
+
+        ui.open_popup("Unsaved changes");
+        let mut close = false;
+        ui.popup_modal("Unsaved changes")
+            .always_auto_resize(true)
+            .build(ui, || {
+                ui.text(format!("Save changes to {:?} before closing?", self.path));
+
+                if ui.button("save") {
+                    match self.save(app_world) {
+                        Ok(_) => {
+                            app_world.write_resource::<ControlState>().control_flow =
+                                Some(ControlFlow::Exit);
+                        }
+                        Err(err) => eprintln!("Could not save {:?}, {}", self.path, err),
+                    }
+                    close = true;
+                }
+
+                ui.same_line();
+                if ui.button("discard") {
+                    app_world.write_resource::<ControlState>().control_flow =
+                        Some(ControlFlow::Exit);
+                    close = true;
+                }
+
+                ui.same_line();
+                if ui.button("cancel") {
+                    close = true;
+                }
+
+                if close {
+                    ui.close_current_popup();
+                }
+            });
+
+        if close {
+            self.confirm_close = false;
+        }
+    }
+}
+
+impl Extension for ProjectFile {
+    fn configure_app_world(world: &mut World) {
+        Persistence::configure_app_world(world);
+        if !world.has_value::<UnsavedChanges>() {
+            let unsaved = UnsavedChanges::new(world);
+            world.insert(unsaved);
+        }
+        world
+            .entry::<ControlState>()
+            .or_insert_with(ControlState::default);
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        // imgui::Key has no S, winit-support indexes keys_down by VirtualKeyCode
+        let io = ui.io();
+        if (io.key_ctrl || io.key_super)
+            && !io.want_text_input
+            && ui.is_key_index_pressed(VirtualKeyCode::S as i32)
+        {
+            if let Err(err) = self.save(app_world) {
+                eprintln!("Could not save {:?}, {}", self.path, err);
+            }
+        }
+
+        self.confirm_close_ui(app_world, ui);
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        app_world.write_resource::<UnsavedChanges>().update(
+            &mut app_world.write_resource::<AttributeEvents>(),
+            &app_world.entities(),
+            &app_world.read_storage::<Attribute>(),
+        );
+    }
+
+    fn on_close_requested(&'_ mut self, app_world: &World) -> CloseResponse {
+        if app_world.read_resource::<UnsavedChanges>().is_dirty() {
+            self.confirm_close = true;
+            CloseResponse::Deny
+        } else {
+            CloseResponse::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsavedChanges;
+    use crate::system::Attribute;
+    use crate::system::AttributeEvents;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    fn is_dirty(world: &World) -> bool {
+        let mut unsaved = world.write_resource::<UnsavedChanges>();
+        unsaved.update(
+            &mut world.write_resource::<AttributeEvents>(),
+            &world.entities(),
+            &world.read_storage::<Attribute>(),
+        );
+        unsaved.is_dirty()
+    }
+
+    #[test]
+    fn commits_after_the_last_save_are_unsaved() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        let unsaved = UnsavedChanges::new(&mut world);
+        world.insert(unsaved);
+        assert!(!is_dirty(&world));
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .edit_as(Value::Int(2));
+        assert!(!is_dirty(&world), "pending changes aren't unsaved");
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .commit_unchecked();
+        assert!(is_dirty(&world));
+
+        world.write_resource::<UnsavedChanges>().mark_saved();
+        assert!(!is_dirty(&world));
+
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(entity).unwrap();
+            attribute.edit_as(Value::Int(1));
+            attribute.commit_unchecked();
+        }
+        assert!(is_dirty(&world));
+        {
+            let mut attributes = world.write_storage::<Attribute>();
+            let attribute = attributes.get_mut(entity).unwrap();
+            attribute.edit_as(Value::Int(2));
+            attribute.commit_unchecked();
+        }
+        assert!(
+            !is_dirty(&world),
+            "reverting a commit restores the saved state"
+        );
+
+        world.delete_entity(entity).unwrap();
+        world.maintain();
+        assert!(is_dirty(&world));
+    }
+}
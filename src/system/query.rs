@@ -0,0 +1,563 @@
+use imgui::Ui;
+use regex::Regex;
+use specs::storage::MaskedStorage;
+use specs::Entity;
+use specs::Storage;
+use specs::World;
+use specs::WorldExt;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::ops::Bound;
+use std::ops::Deref;
+use std::ops::RangeBounds;
+
+use super::Attribute;
+use super::AttributeIndex;
+use super::Extension;
+use super::Indexing;
+use super::Value;
+use super::ValueKind;
+
+/// Error returned when a query can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// the term is not valid
+    Term { term: String, reason: String },
+    /// the same filter was given more than once
+    Duplicate(String),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Term { term, reason } => write!(f, "invalid term '{}', {}", term, reason),
+            QueryError::Duplicate(filter) => {
+                write!(f, "{} filter was given more than once", filter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Matches attribute names
+#[derive(Debug, Clone)]
+pub enum NameFilter {
+    Exact(String),
+    /// `*` matches any run of characters, `?` matches one character
+    Glob(String, Regex),
+    Regex(Regex),
+}
+
+impl NameFilter {
+    /// returns a glob filter for `pattern`, or an exact filter if the pattern has no wildcards
+    pub fn glob(pattern: impl AsRef<str>) -> Self {
+        let pattern = pattern.as_ref();
+        if !pattern.contains(['*', '?']) {
+            return NameFilter::Exact(pattern.to_string());
+        }
+
+        let mut regex = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        NameFilter::Glob(
+            pattern.to_string(),
+            Regex::new(&regex).expect("escaped glob is a valid regex"),
+        )
+    }
+
+    /// returns true if `name` passes this filter
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameFilter::Exact(exact) => exact == name,
+            NameFilter::Glob(_, regex) | NameFilter::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// A query over the attributes in an `AttributeIndex`, every filter that is set must match
+///
+/// Queries can be built with the builder methods, or parsed from a string of whitespace separated terms:
+/// - `port`, `file::*` or `name:port` match names exactly, or with a glob when there is a `*` or `?`
+/// - `/^file::/` or `re:^file::` match names with a regex
+/// - `kind:int,float` matches value kinds, by name and case-insensitive
+/// - `id:7` or `id:0x7` matches `Attribute::id`, `entity:3` matches the entity the attribute is stored on
+/// - `value:1..10`, `value:1..=10`, `value:>=5`, `value:<5` or `value:=5` match int and float values
+#[derive(Debug, Clone, Default)]
+pub struct AttributeQuery {
+    pub name: Option<NameFilter>,
+    /// empty matches any kind
+    pub kinds: BTreeSet<ValueKind>,
+    /// empty matches any id
+    pub ids: BTreeSet<u32>,
+    /// entity ids, empty matches any entity
+    pub entities: BTreeSet<u32>,
+    pub range: Option<(Bound<f64>, Bound<f64>)>,
+}
+
+impl AttributeQuery {
+    /// returns a query that matches every attribute
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// matches names with `pattern`, see `NameFilter::glob`
+    pub fn name(mut self, pattern: impl AsRef<str>) -> Self {
+        self.name = Some(NameFilter::glob(pattern));
+        self
+    }
+
+    /// matches names with `regex`
+    pub fn name_regex(mut self, regex: Regex) -> Self {
+        self.name = Some(NameFilter::Regex(regex));
+        self
+    }
+
+    /// also matches values of `kind`
+    pub fn kind(mut self, kind: ValueKind) -> Self {
+        self.kinds.insert(kind);
+        self
+    }
+
+    /// also matches attributes with `Attribute::id` equal to `id`
+    pub fn id(mut self, id: u32) -> Self {
+        self.ids.insert(id);
+        self
+    }
+
+    /// also matches the attribute stored on `entity`
+    pub fn entity(mut self, entity: Entity) -> Self {
+        self.entities.insert(entity.id());
+        self
+    }
+
+    /// matches int and float values in `range`
+    pub fn range(mut self, range: impl RangeBounds<f64>) -> Self {
+        self.range = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+        self
+    }
+
+    /// parses a query, see `AttributeQuery` for the syntax
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut parsed = Self::default();
+        let term_error = |term: &str, reason: &str| QueryError::Term {
+            term: term.to_string(),
+            reason: reason.to_string(),
+        };
+
+        for term in query.split_whitespace() {
+            let (key, arg) = match term.split_once(':') {
+                Some((key, arg))
+                    if ["name", "re", "kind", "id", "entity", "value"].contains(&key) =>
+                {
+                    (key, arg)
+                }
+                _ => match term.strip_prefix('/').and_then(|t| t.strip_suffix('/')) {
+                    Some(regex) => ("re", regex),
+                    None => ("name", term),
+                },
+            };
+
+            if arg.is_empty() {
+                return Err(term_error(term, "expected a value after ':'"));
+            }
+
+            match key {
+                "name" | "re" => {
+                    if parsed.name.is_some() {
+                        return Err(QueryError::Duplicate("name".to_string()));
+                    }
+                    parsed.name = Some(if key == "re" {
+                        NameFilter::Regex(
+                            Regex::new(arg).map_err(|err| term_error(term, &err.to_string()))?,
+                        )
+                    } else {
+                        NameFilter::glob(arg)
+                    });
+                }
+                "kind" => {
+                    for kind in arg.split(',') {
+                        parsed.kinds.insert(
+                            parse_kind(kind).ok_or_else(|| term_error(term, "unknown kind"))?,
+                        );
+                    }
+                }
+                "id" | "entity" => {
+                    for id in arg.split(',') {
+                        let id = match id.strip_prefix("0x") {
+                            Some(hex) => u32::from_str_radix(hex, 16),
+                            None => id.parse::<u32>(),
+                        }
+                        .map_err(|err| term_error(term, &err.to_string()))?;
+                        if key == "id" {
+                            parsed.ids.insert(id);
+                        } else {
+                            parsed.entities.insert(id);
+                        }
+                    }
+                }
+                _ => {
+                    if parsed.range.is_some() {
+                        return Err(QueryError::Duplicate("value".to_string()));
+                    }
+                    parsed.range = Some(parse_range(arg).map_err(|r| term_error(term, r))?);
+                }
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Returns the entities that match this query, in order
+    ///
+    /// The index narrows down the candidates, `attributes` is only read to filter on value ranges
+    pub fn run<D>(&self, index: &AttributeIndex, attributes: &Storage<Attribute, D>) -> Vec<Entity>
+    where
+        D: Deref<Target = MaskedStorage<Attribute>>,
+    {
+        let mut candidates: Option<BTreeSet<u32>> = None;
+        let mut narrow = |found: BTreeSet<u32>| {
+            candidates = Some(match candidates.take() {
+                Some(c) => c.intersection(&found).copied().collect(),
+                None => found,
+            });
+        };
+
+        if let Some(name) = &self.name {
+            narrow(index.names_matching(|n| name.matches(n)).collect());
+        }
+        if !self.ids.is_empty() {
+            narrow(
+                self.ids
+                    .iter()
+                    .flat_map(|id| index.id_indices(*id))
+                    .collect(),
+            );
+        }
+        if !self.kinds.is_empty() {
+            narrow(
+                self.kinds
+                    .iter()
+                    .flat_map(|kind| index.kind_indices(*kind))
+                    .collect(),
+            );
+        }
+        if !self.entities.is_empty() {
+            narrow(self.entities.clone());
+        }
+
+        let candidates = candidates.unwrap_or_else(|| index.indices().collect());
+        candidates
+            .into_iter()
+            .filter_map(|i| index.get(i))
+            .map(|(entity, ..)| entity)
+            .filter(|entity| match &self.range {
+                Some(range) => attributes
+                    .get(*entity)
+                    .and_then(|a| match a.value() {
+                        Value::Int(i) => Some(*i as f64),
+                        Value::Float(f) => Some(*f as f64),
+                        _ => None,
+                    })
+                    .is_some_and(|v| range.contains(&v)),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// runs this query against the `AttributeIndex` resource of `world`
+    pub fn find(&self, world: &World) -> Vec<Entity> {
+        self.run(
+            &world.read_resource::<AttributeIndex>(),
+            &world.read_component::<Attribute>(),
+        )
+    }
+}
+
+fn parse_kind(kind: &str) -> Option<ValueKind> {
+    [
+        ValueKind::Empty,
+        ValueKind::Bool,
+        ValueKind::TextBuffer,
+        ValueKind::Int,
+        ValueKind::IntPair,
+        ValueKind::IntRange,
+        ValueKind::Float,
+        ValueKind::FloatPair,
+        ValueKind::FloatRange,
+        ValueKind::BinaryVector,
+        ValueKind::Reference,
+        ValueKind::Symbol,
+    ]
+    .into_iter()
+    .find(|k| k.to_string().eq_ignore_ascii_case(kind))
+}
+
+fn parse_range(range: &str) -> Result<(Bound<f64>, Bound<f64>), &'static str> {
+    let number = |n: &str| n.parse::<f64>().map_err(|_| "expected a number");
+
+    let bounds = if let Some(n) = range.strip_prefix(">=") {
+        (Bound::Included(number(n)?), Bound::Unbounded)
+    } else if let Some(n) = range.strip_prefix("<=") {
+        (Bound::Unbounded, Bound::Included(number(n)?))
+    } else if let Some(n) = range.strip_prefix('>') {
+        (Bound::Excluded(number(n)?), Bound::Unbounded)
+    } else if let Some(n) = range.strip_prefix('<') {
+        (Bound::Unbounded, Bound::Excluded(number(n)?))
+    } else if let Some(n) = range.strip_prefix('=') {
+        (Bound::Included(number(n)?), Bound::Included(number(n)?))
+    } else if let Some((start, end)) = range.split_once("..") {
+        let start = match start {
+            "" => Bound::Unbounded,
+            start => Bound::Included(number(start)?),
+        };
+        let end = match end.strip_prefix('=') {
+            Some(end) => Bound::Included(number(end)?),
+            None if end.is_empty() => Bound::Unbounded,
+            None => Bound::Excluded(number(end)?),
+        };
+        (start, end)
+    } else {
+        let n = number(range)?;
+        (Bound::Included(n), Bound::Included(n))
+    };
+
+    Ok(bounds)
+}
+
+/// Short description of `value` for the results table, text and binary values are truncated
+fn summary(value: &Value) -> String {
+    const PREVIEW_CHARS: usize = 32;
+
+    match value {
+        Value::TextBuffer(text) | Value::Symbol(text) => {
+            let preview = text.chars().take(PREVIEW_CHARS).collect::<String>();
+            if preview.len() == text.len() {
+                format!("{:?}", value)
+            } else {
+                format!("{} ({} bytes) {:?}...", value.kind(), text.len(), preview)
+            }
+        }
+        Value::BinaryVector(bytes) => format!("{} ({} bytes)", value.kind(), bytes.len()),
+        value => format!("{:?}", value),
+    }
+}
+
+/// Extension that shows a panel to search the attributes of the app world with `AttributeQuery`
+#[derive(Default)]
+pub struct SearchPanel {
+    /// when true, the search window is shown
+    pub opened: bool,
+    /// the result that was last clicked
+    pub selected: Option<Entity>,
+    input: String,
+    indexing: Indexing,
+}
+
+impl SearchPanel {
+    fn search_ui(&mut self, app_world: &World, ui: &Ui) {
+        ui.input_text("query", &mut self.input).build();
+        if ui.is_item_hovered() {
+            ui.tooltip_text(
+                "name, file::*, /regex/, kind:int,float, id:7, entity:3, value:1..10, value:>=5",
+            );
+        }
+
+        let query = match AttributeQuery::parse(&self.input) {
+            Ok(query) => query,
+            Err(err) => {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], err.to_string());
+                return;
+            }
+        };
+
+        let attributes = app_world.read_component::<Attribute>();
+        let results = query.run(&app_world.read_resource::<AttributeIndex>(), &attributes);
+        ui.text(format!("{} results", results.len()));
+        ui.separator();
+
+        ui.columns(4, "search results", true);
+        for header in ["entity", "id", "name", "value"] {
+            ui.text_disabled(header);
+            ui.next_column();
+        }
+        for entity in results {
+            let attribute = match attributes.get(entity) {
+                Some(attribute) => attribute,
+                None => continue,
+            };
+
+            if imgui::Selectable::new(format!("{}", entity.id()))
+                .selected(self.selected == Some(entity))
+                .span_all_columns(true)
+                .build(ui)
+            {
+                self.selected = Some(entity);
+            }
+            ui.next_column();
+            ui.text(format!("{:#4x}", attribute.id()));
+            ui.next_column();
+            ui.text(attribute.name());
+            ui.next_column();
+            ui.text(summary(attribute.value()));
+            ui.next_column();
+        }
+        ui.columns(1, "search results", false);
+    }
+}
+
+impl Extension for SearchPanel {
+    fn configure_app_world(world: &mut World) {
+        Indexing::configure_app_world(world);
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        self.indexing.on_maintain(app_world);
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        if !self.opened {
+            return;
+        }
+
+        let mut opened = self.opened;
+        imgui::Window::new("Search")
+            .size([600.0, 400.0], imgui::Condition::FirstUseEver)
+            .opened(&mut opened)
+            .build(ui, || self.search_ui(app_world, ui));
+        self.opened = opened;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+    use std::ops::Bound;
+
+    use super::parse_range;
+    use super::summary;
+    use super::AttributeQuery;
+    use super::NameFilter;
+    use super::QueryError;
+    use crate::system::Attribute;
+    use crate::system::AttributeIndex;
+    use crate::system::Value;
+    use crate::system::ValueKind;
+
+    #[test]
+    fn globs_escape_regex_characters() {
+        assert!(matches!(NameFilter::glob("a.b"), NameFilter::Exact(_)));
+
+        let glob = NameFilter::glob("file::(*).?s");
+        assert!(glob.matches("file::(main).rs"));
+        assert!(glob.matches("file::().ts"));
+        assert!(!glob.matches("file::main.rs"));
+        assert!(!glob.matches("file::(main)xrs"));
+        assert!(!glob.matches("xfile::(main).rs"));
+    }
+
+    #[test]
+    fn ranges_include_or_exclude_their_bounds() {
+        use Bound::*;
+        assert_eq!(parse_range("1..10"), Ok((Included(1.0), Excluded(10.0))));
+        assert_eq!(parse_range("1..=10"), Ok((Included(1.0), Included(10.0))));
+        assert_eq!(parse_range("..10"), Ok((Unbounded, Excluded(10.0))));
+        assert_eq!(parse_range("1.5.."), Ok((Included(1.5), Unbounded)));
+        assert_eq!(parse_range(">=5"), Ok((Included(5.0), Unbounded)));
+        assert_eq!(parse_range(">5"), Ok((Excluded(5.0), Unbounded)));
+        assert_eq!(parse_range("<=5"), Ok((Unbounded, Included(5.0))));
+        assert_eq!(parse_range("<-5"), Ok((Unbounded, Excluded(-5.0))));
+        assert_eq!(parse_range("=5"), Ok((Included(5.0), Included(5.0))));
+        assert_eq!(parse_range("5"), Ok((Included(5.0), Included(5.0))));
+        assert!(parse_range("a..b").is_err());
+        assert!(parse_range(">").is_err());
+    }
+
+    #[test]
+    fn queries_are_parsed_from_terms() {
+        let query = AttributeQuery::parse("file::* kind:int,FLOAT id:0x10,3 entity:2 value:>=1")
+            .expect("query is valid");
+        assert!(matches!(query.name, Some(NameFilter::Glob(..))));
+        assert_eq!(
+            query.kinds.into_iter().collect::<Vec<_>>(),
+            [ValueKind::Int, ValueKind::Float]
+        );
+        assert_eq!(query.ids.into_iter().collect::<Vec<_>>(), [3, 16]);
+        assert_eq!(query.entities.into_iter().collect::<Vec<_>>(), [2]);
+        assert_eq!(query.range, Some((Bound::Included(1.0), Bound::Unbounded)));
+
+        let query = AttributeQuery::parse("/^a+$/").expect("query is valid");
+        assert!(matches!(query.name, Some(NameFilter::Regex(_))));
+
+        assert_eq!(
+            AttributeQuery::parse("a name:b").unwrap_err(),
+            QueryError::Duplicate("name".to_string())
+        );
+        assert_eq!(
+            AttributeQuery::parse("a /b/").unwrap_err(),
+            QueryError::Duplicate("name".to_string())
+        );
+        assert_eq!(
+            AttributeQuery::parse("value:1 value:2").unwrap_err(),
+            QueryError::Duplicate("value".to_string())
+        );
+        assert!(matches!(
+            AttributeQuery::parse("kind:number"),
+            Err(QueryError::Term { .. })
+        ));
+        assert!(matches!(
+            AttributeQuery::parse("id:"),
+            Err(QueryError::Term { .. })
+        ));
+    }
+
+    #[test]
+    fn queries_filter_indexed_attributes() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let a = world
+            .create_entity()
+            .with(Attribute::new(1, "file::a", Value::Int(5)))
+            .build();
+        world
+            .create_entity()
+            .with(Attribute::new(2, "file::b", Value::Float(50.0)))
+            .build();
+        world
+            .create_entity()
+            .with(Attribute::new(1, "other", Value::Int(5)))
+            .build();
+        world.insert(AttributeIndex::new(&world));
+
+        let find = |query: &str| AttributeQuery::parse(query).unwrap().find(&world);
+        assert_eq!(find("file::* value:..10"), [a]);
+        assert_eq!(find("id:1 kind:int").len(), 2);
+        assert_eq!(find("file::* kind:bool"), []);
+    }
+
+    #[test]
+    fn long_values_are_summarized() {
+        assert_eq!(summary(&Value::Int(1)), "Int(1)");
+        assert_eq!(
+            summary(&Value::TextBuffer("short".to_string())),
+            "TextBuffer(\"short\")"
+        );
+        assert_eq!(
+            summary(&Value::TextBuffer("é".repeat(40))),
+            format!("TextBuffer (80 bytes) {:?}...", "é".repeat(32))
+        );
+        assert_eq!(
+            summary(&Value::BinaryVector(vec![0; 1000])),
+            "BinaryVector (1000 bytes)"
+        );
+    }
+}
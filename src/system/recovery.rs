@@ -0,0 +1,466 @@
+use imgui::Ui;
+use serde::Deserialize;
+use serde::Serialize;
+use specs::saveload::Marker;
+use specs::saveload::MarkerAllocator;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use winit::event_loop::ControlFlow;
+
+use super::load_world;
+use super::save_world;
+use super::Attribute;
+use super::CloseResponse;
+use super::ControlState;
+use super::Encoding;
+use super::Extension;
+use super::PersistError;
+use super::PersistedMarker;
+use super::PersistedMarkerAllocator;
+use super::Persistence;
+use super::UnsavedChanges;
+use super::Value;
+
+/// Committed and uncommitted state of every attribute, written periodically by `Autosave`
+#[derive(Serialize, Deserialize)]
+pub struct Recovery {
+    /// seconds since the unix epoch when this recovery was captured
+    pub saved_at: u64,
+    /// committed state, in the binary project format
+    project: Vec<u8>,
+    /// pending changes of each attribute that was being edited
+    transient: Vec<(PersistedMarker, (String, Value))>,
+}
+
+impl Recovery {
+    /// Captures every attribute in `world`, including pending changes that haven't been committed
+    pub fn capture(world: &World) -> Result<Self, PersistError> {
+        let project = save_world(world, Encoding::Binary)?;
+
+        let markers = world.read_component::<PersistedMarker>();
+        let attributes = world.read_component::<Attribute>();
+        let transient = (&markers, &attributes)
+            .join()
+            .filter_map(|(marker, attribute)| attribute.transient().map(|t| (*marker, t.clone())))
+            .collect();
+
+        let saved_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Ok(Self {
+            saved_at,
+            project,
+            transient,
+        })
+    }
+
+    /// Replaces the attributes in `world` with the captured attributes and restores their pending changes,
+    /// returns the entities that were loaded
+    ///
+    /// Entities are matched by their `PersistedMarker`, attributes that aren't part of this recovery are removed,
+    /// so restoring more than once doesn't duplicate attributes, other components of those entities are kept
+    pub fn restore(&self, world: &World) -> Result<Vec<Entity>, PersistError> {
+        let loaded = load_world(world, &self.project)?;
+
+        let entities = world.entities();
+        let mut attributes = world.write_component::<Attribute>();
+        let keep = loaded.iter().collect::<HashSet<_>>();
+        let replaced = (&entities, &attributes)
+            .join()
+            .map(|(entity, _)| entity)
+            .filter(|entity| !keep.contains(entity))
+            .collect::<Vec<_>>();
+        for entity in replaced {
+            attributes.remove(entity);
+        }
+
+        let mut markers = world.write_component::<PersistedMarker>();
+        let mut allocator = world.write_resource::<PersistedMarkerAllocator>();
+        for (marker, edit) in self.transient.iter() {
+            let entity = allocator.retrieve_entity(*marker, &mut markers, &entities);
+            if let Some(attribute) = attributes.get_mut(entity) {
+                attribute.edit(edit.clone());
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// number of attributes with pending changes
+    pub fn edits(&self) -> usize {
+        self.transient.len()
+    }
+
+    /// reads a recovery file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, PersistError> {
+        Ok(bincode::deserialize(&std::fs::read(path)?)?)
+    }
+
+    /// writes this recovery to a file, the file is replaced atomically
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), PersistError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bincode::serialize(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn hash_state(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.project.hash(&mut hasher);
+        for (marker, edit) in self.transient.iter() {
+            marker.id().hash(&mut hasher);
+            edit.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Extension that periodically saves every attribute, including pending changes, to a recovery file
+///
+/// On start up, if a recovery file was left behind by a crash, a dialog offers to restore it.
+/// When the window is closed while there are pending changes, a dialog asks before exiting.
+/// The recovery file is removed when the app exits normally
+///
+/// When `ProjectFile` is also installed, it owns the close prompt, pending changes are then
+/// kept in the recovery file for the next launch instead of asking a second time
+pub struct Autosave {
+    /// path of the recovery file
+    pub path: PathBuf,
+    /// time between autosaves
+    pub interval: Duration,
+    /// set when a recovery file was found on start up, until the user restores or discards it
+    found: Option<Recovery>,
+    checked: bool,
+    confirm_close: bool,
+    last_save: Option<Instant>,
+    last_hash: Option<u64>,
+}
+
+impl Default for Autosave {
+    /// writes every 30 seconds to a recovery file in the temp dir, named after the executable
+    /// and the working directory, so that apps and projects don't share a recovery file
+    fn default() -> Self {
+        let app = std::env::current_exe()
+            .ok()
+            .and_then(|exe| {
+                exe.file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "atlier".to_string());
+        let project = std::env::current_dir().unwrap_or_default();
+
+        Self::new(recovery_path(&app, &project), Duration::from_secs(30))
+    }
+}
+
+/// Returns the path of the recovery file of `app` when it's working on `project`, in the temp dir
+pub fn recovery_path(app: &str, project: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    project.hash(&mut hasher);
+    std::env::temp_dir().join(format!("{}-{:016x}.recovery", app, hasher.finish()))
+}
+
+impl Autosave {
+    /// returns an autosave extension that writes to `path` every `interval`
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            found: None,
+            checked: false,
+            confirm_close: false,
+            last_save: None,
+            last_hash: None,
+        }
+    }
+
+    /// returns true if any attribute has pending changes
+    pub fn is_dirty(app_world: &World) -> bool {
+        app_world
+            .read_component::<Attribute>()
+            .join()
+            .any(|attribute| !attribute.is_stable())
+    }
+
+    /// captures the app world and writes it to the recovery file, unless nothing changed since the last save
+    pub fn save(&mut self, app_world: &World) -> Result<(), PersistError> {
+        self.last_save = Some(Instant::now());
+
+        let recovery = Recovery::capture(app_world)?;
+        let hash = recovery.hash_state();
+        if self.last_hash != Some(hash) {
+            recovery.write(&self.path)?;
+            self.last_hash = Some(hash);
+        }
+        Ok(())
+    }
+
+    /// removes the recovery file
+    pub fn discard(&mut self) {
+        self.found = None;
+        self.last_hash = None;
+        if self.path.exists() {
+            if let Err(err) = std::fs::remove_file(&self.path) {
+                eprintln!("Could not remove recovery file {:?}, {}", self.path, err);
+            }
+        }
+    }
+
+    fn exit(app_world: &World) {
+        app_world.write_resource::<ControlState>().control_flow = Some(ControlFlow::Exit);
+    }
+
+    fn recovery_ui(&mut self, app_world: &World, ui: &Ui) {
+        let found = match &self.found {
+            Some(found) => found,
+            None => return,
+        };
+
+        ui.open_popup("Recover unsaved changes");
+        let mut resolved = false;
+        ui.popup_modal("Recover unsaved changes")
+            .always_auto_resize(true)
+            .build(ui, || {
+                ui.text(format!(
+                    "The app did not exit normally, a recovery file from {} seconds ago was found",
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs().saturating_sub(found.saved_at))
+                        .unwrap_or_default()
+                ));
+                ui.text(format!(
+                    "{} attributes had uncommitted edits",
+                    found.edits()
+                ));
+                ui.text_disabled(format!("{:?}", self.path));
+
+                if ui.button("restore") {
+                    if let Err(err) = found.restore(app_world) {
+                        eprintln!("Could not restore {:?}, {}", self.path, err);
+                    }
+                    resolved = true;
+                }
+
+                ui.same_line();
+                if ui.button("discard") {
+                    resolved = true;
+                }
+
+                if resolved {
+                    ui.close_current_popup();
+                }
+            });
+
+        if resolved {
+            self.discard();
+        }
+    }
+
+    fn confirm_close_ui(&mut self, app_world: &World, ui: &Ui) {
+        if !self.confirm_close {
+            return;
+        }
+
+        ui.open_popup("Uncommitted edits");
+        let mut close = false;
+        ui.popup_modal("Uncommitted edits")
+            .always_auto_resize(true)
+            .build(ui, || {
+                ui.text("Some attributes have edits that haven't been saved");
+
+                if ui.button("exit, keep for next launch") {
+                    if let Err(err) = self.save(app_world) {
+                        eprintln!("Could not write recovery file {:?}, {}", self.path, err);
+                    }
+                    Self::exit(app_world);
+                    close = true;
+                }
+
+                ui.same_line();
+                if ui.button("exit, discard") {
+                    self.discard();
+                    Self::exit(app_world);
+                    close = true;
+                }
+
+                ui.same_line();
+                if ui.button("cancel") {
+                    close = true;
+                }
+
+                if close {
+                    ui.close_current_popup();
+                }
+            });
+
+        if close {
+            self.confirm_close = false;
+        }
+    }
+}
+
+impl Extension for Autosave {
+    fn configure_app_world(world: &mut World) {
+        Persistence::configure_app_world(world);
+        world
+            .entry::<ControlState>()
+            .or_insert_with(ControlState::default);
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        if !self.checked {
+            self.checked = true;
+            if self.path.exists() {
+                match Recovery::read(&self.path) {
+                    Ok(recovery) => self.found = Some(recovery),
+                    Err(err) => eprintln!("Could not read recovery file {:?}, {}", self.path, err),
+                }
+            }
+        }
+
+        self.recovery_ui(app_world, ui);
+        self.confirm_close_ui(app_world, ui);
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        // the recovery file isn't overwritten until the user decides what to do with it
+        if !self.checked || self.found.is_some() {
+            return;
+        }
+
+        if self
+            .last_save
+            .is_some_and(|last_save| last_save.elapsed() < self.interval)
+        {
+            return;
+        }
+
+        if let Err(err) = self.save(app_world) {
+            eprintln!("Could not write recovery file {:?}, {}", self.path, err);
+        }
+    }
+
+    fn on_close_requested(&'_ mut self, app_world: &World) -> CloseResponse {
+        if self.found.is_none() && app_world.has_value::<UnsavedChanges>() {
+            if Self::is_dirty(app_world) {
+                if let Err(err) = self.save(app_world) {
+                    eprintln!("Could not write recovery file {:?}, {}", self.path, err);
+                }
+            } else {
+                self.discard();
+            }
+            CloseResponse::Allow
+        } else if self.found.is_none() && Self::is_dirty(app_world) {
+            self.confirm_close = true;
+            CloseResponse::Deny
+        } else {
+            if self.found.is_none() {
+                self.discard();
+            }
+            CloseResponse::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recovery_path;
+    use super::Recovery;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::Persistence;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::Component;
+    use specs::Join;
+    use specs::NullStorage;
+    use specs::World;
+    use specs::WorldExt;
+    use std::path::Path;
+
+    #[derive(Component, Default)]
+    #[storage(NullStorage)]
+    struct Marker;
+
+    fn attributes(world: &World) -> Vec<Attribute> {
+        let mut attributes = world
+            .read_component::<Attribute>()
+            .join()
+            .cloned()
+            .collect::<Vec<_>>();
+        attributes.sort_by(|a, b| a.name().cmp(b.name()));
+        attributes
+    }
+
+    #[test]
+    fn restoring_replaces_attributes() {
+        let mut world = World::new();
+        Persistence::configure_app_world(&mut world);
+        world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        let mut edited = Attribute::new(0, "b", Value::Int(2));
+        edited.edit_as(Value::Int(3));
+        world.create_entity().with(edited).build();
+
+        let recovery = Recovery::capture(&world).expect("can capture");
+        let captured = attributes(&world);
+
+        world
+            .create_entity()
+            .with(Attribute::new(0, "c", Value::Int(4)))
+            .build();
+        for _ in 0..2 {
+            recovery.restore(&world).expect("can restore");
+            world.maintain();
+            assert_eq!(attributes(&world), captured);
+        }
+    }
+
+    #[test]
+    fn restoring_keeps_other_components() {
+        let mut world = World::new();
+        Persistence::configure_app_world(&mut world);
+        world.register::<Marker>();
+        world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        let recovery = Recovery::capture(&world).expect("can capture");
+
+        let other = world
+            .create_entity()
+            .with(Attribute::new(0, "b", Value::Int(2)))
+            .with(Marker)
+            .build();
+        recovery.restore(&world).expect("can restore");
+        world.maintain();
+
+        assert!(world.is_alive(other));
+        assert!(world.read_component::<Marker>().contains(other));
+        assert!(!world.read_component::<Attribute>().contains(other));
+    }
+
+    #[test]
+    fn recovery_paths_are_per_app_and_project() {
+        let a = recovery_path("a", Path::new("/projects/one"));
+        assert_eq!(a, recovery_path("a", Path::new("/projects/one")));
+        assert_ne!(a, recovery_path("b", Path::new("/projects/one")));
+        assert_ne!(a, recovery_path("a", Path::new("/projects/two")));
+    }
+}
@@ -0,0 +1,623 @@
+use imgui::Ui;
+use specs::World;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+
+use super::grammars;
+use super::hex_editor::binary_ui;
+use super::image_viewer::image_ui;
+use super::widget_cache::WidgetCache;
+use super::Extension;
+use super::ImageFormat;
+use super::TextEditor;
+use super::Value;
+use super::ValueKind;
+
+/// Error returned by a scheme handler
+#[derive(Debug)]
+pub enum SchemeError {
+    Io(std::io::Error),
+    /// the part of the name after the scheme is not valid for this scheme
+    InvalidPath(String),
+    /// the value is not of a kind this scheme can save
+    Kind {
+        scheme: String,
+        found: ValueKind,
+    },
+    /// the scheme doesn't support this operation
+    Unsupported {
+        scheme: String,
+        operation: &'static str,
+    },
+    /// a command exited with an error
+    Command(String),
+    /// the file was changed by another program since it was loaded
+    ChangedOnDisk(String),
+}
+
+impl Display for SchemeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemeError::Io(err) => write!(f, "io error, {}", err),
+            SchemeError::InvalidPath(reason) => write!(f, "invalid path, {}", reason),
+            SchemeError::Kind { scheme, found } => {
+                write!(f, "{} can't save a value of kind {}", scheme, found)
+            }
+            SchemeError::Unsupported { scheme, operation } => {
+                write!(f, "{} does not support {}", scheme, operation)
+            }
+            SchemeError::Command(err) => write!(f, "command failed, {}", err),
+            SchemeError::ChangedOnDisk(path) => {
+                write!(f, "{} was changed on disk since it was loaded", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemeError {}
+
+impl From<std::io::Error> for SchemeError {
+    fn from(err: std::io::Error) -> Self {
+        SchemeError::Io(err)
+    }
+}
+
+/// Handles attributes with names that start with a scheme, e.g. `file::` in `file::notes.txt`
+///
+/// The part of the name after the scheme is passed to the handler as `path`.
+/// Loading and saving only happen when the user asks for it, either with the buttons of the editor or
+/// through `Schemes::load` and `Schemes::save`
+pub trait SchemeHandler: Send + Sync {
+    /// the scheme this handler is registered for, including the trailing `::`
+    fn scheme(&self) -> &str;
+
+    /// checks that `path` and `value` can be loaded and saved by this handler
+    fn validate(&self, path: &str, _value: &Value) -> Result<(), SchemeError> {
+        if path.is_empty() {
+            Err(SchemeError::InvalidPath("path is empty".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// reads the value that `path` is bound to
+    fn load(&self, path: &str) -> Result<Value, SchemeError>;
+
+    /// writes `value` to what `path` is bound to
+    fn save(&self, _path: &str, _value: &Value) -> Result<(), SchemeError> {
+        Err(SchemeError::Unsupported {
+            scheme: self.scheme().to_string(),
+            operation: "save",
+        })
+    }
+
+    /// Shows the editor for an attribute bound with this scheme, after the editor for its value
+    ///
+    /// The default shows the validation error, if any, and load and save buttons
+    fn edit_ui(&self, path: &str, label: &str, value: &mut Value, ui: &Ui) {
+        if let Err(err) = self.validate(path, value) {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], err.to_string());
+            return;
+        }
+
+        if ui.button(format!("load {}", label)) {
+            match self.load(path) {
+                Ok(loaded) => *value = loaded,
+                Err(err) => eprintln!("Could not load '{}{}', {}", self.scheme(), path, err),
+            }
+        }
+        ui.same_line();
+        if ui.button(format!("save {}", label)) {
+            if let Err(err) = self.save(path, value) {
+                eprintln!("Could not save '{}{}', {}", self.scheme(), path, err);
+            }
+        }
+    }
+}
+
+/// Registry of scheme handlers, by scheme
+///
+/// The default registry has handlers for `file::`, `env::`, `cmd::` and `dir::`.
+/// `Attribute::edit_ui` uses the process-wide registry, see `register_scheme`
+#[derive(Clone)]
+pub struct Schemes {
+    handlers: BTreeMap<String, Arc<dyn SchemeHandler>>,
+}
+
+impl Default for Schemes {
+    fn default() -> Self {
+        let mut schemes = Self::empty();
+        schemes.register(FileScheme);
+        let env = EnvOverrides::default();
+        schemes.register(EnvScheme::new(env.clone()));
+        schemes.register(CommandScheme::new(env));
+        schemes.register(DirScheme);
+        schemes
+    }
+}
+
+impl Schemes {
+    /// returns a registry without any handlers
+    pub fn empty() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+        }
+    }
+
+    /// adds a handler, replacing the handler that was registered for the same scheme
+    pub fn register(&mut self, handler: impl SchemeHandler + 'static) {
+        self.handlers
+            .insert(handler.scheme().to_string(), Arc::new(handler));
+    }
+
+    /// removes the handler for `scheme`
+    pub fn unregister(&mut self, scheme: &str) {
+        self.handlers.remove(scheme);
+    }
+
+    /// registered schemes, in order
+    pub fn schemes(&self) -> impl Iterator<Item = &str> {
+        self.handlers.keys().map(|s| s.as_str())
+    }
+
+    /// returns the handler for the scheme of `name`, and the rest of the name
+    pub fn find<'a>(&self, name: &'a str) -> Option<(Arc<dyn SchemeHandler>, &'a str)> {
+        self.handlers
+            .iter()
+            .filter(|(scheme, _)| name.starts_with(scheme.as_str()))
+            .max_by_key(|(scheme, _)| scheme.len())
+            .map(|(scheme, handler)| (handler.clone(), &name[scheme.len()..]))
+    }
+
+    /// loads the value bound to `name`, returns None if no handler is registered for the scheme of `name`
+    pub fn load(&self, name: &str) -> Option<Result<Value, SchemeError>> {
+        self.find(name).map(|(handler, path)| {
+            handler.validate(path, &Value::Empty)?;
+            handler.load(path)
+        })
+    }
+
+    /// saves `value` to what `name` is bound to, returns None if no handler is registered for the scheme of `name`
+    pub fn save(&self, name: &str, value: &Value) -> Option<Result<(), SchemeError>> {
+        self.find(name).map(|(handler, path)| {
+            handler.validate(path, value)?;
+            handler.save(path, value)
+        })
+    }
+
+    /// validates `name` and `value`, returns None if no handler is registered for the scheme of `name`
+    pub fn validate(&self, name: &str, value: &Value) -> Option<Result<(), SchemeError>> {
+        self.find(name)
+            .map(|(handler, path)| handler.validate(path, value))
+    }
+}
+
+fn global() -> &'static RwLock<Schemes> {
+    static SCHEMES: OnceLock<RwLock<Schemes>> = OnceLock::new();
+    SCHEMES.get_or_init(|| RwLock::new(Schemes::default()))
+}
+
+/// adds a handler to the process-wide registry that `Attribute::edit_ui` uses
+pub fn register_scheme(handler: impl SchemeHandler + 'static) {
+    global()
+        .write()
+        .expect("scheme registry is not poisoned")
+        .register(handler);
+}
+
+/// Returns the process-wide registry, `register_scheme` waits until the returned guard is dropped
+///
+/// Handlers returned by `Schemes::find` can be used after the guard is dropped
+pub fn schemes() -> RwLockReadGuard<'static, Schemes> {
+    global().read().expect("scheme registry is not poisoned")
+}
+
+pub(super) fn to_bytes(scheme: &str, value: &Value) -> Result<Vec<u8>, SchemeError> {
+    as_bytes(value)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| SchemeError::Kind {
+            scheme: scheme.to_string(),
+            found: value.kind(),
+        })
+}
+
+/// returns the bytes of binary and text values
+pub(super) fn as_bytes(value: &Value) -> Option<&[u8]> {
+    match value {
+        Value::BinaryVector(bytes) => Some(bytes),
+        Value::TextBuffer(text) | Value::Symbol(text) => Some(text.as_bytes()),
+        _ => None,
+    }
+}
+
+/// `file::<path>`, binds the content of a file to a binary vector
+pub struct FileScheme;
+
+impl SchemeHandler for FileScheme {
+    fn scheme(&self) -> &str {
+        "file::"
+    }
+
+    fn load(&self, path: &str) -> Result<Value, SchemeError> {
+        Ok(Value::BinaryVector(fs::read(path)?))
+    }
+
+    fn save(&self, path: &str, value: &Value) -> Result<(), SchemeError> {
+        fs::write(path, to_bytes(self.scheme(), value)?)?;
+        Ok(())
+    }
+
+    fn edit_ui(&self, path: &str, label: &str, value: &mut Value, ui: &Ui) {
+        file_edit_ui(self, path, label, value, ui);
+    }
+}
+
+/// Decoded content of a `file::` attribute, kept between frames so that edits and undo history are not lost
+struct CachedEditor {
+    /// bytes the editor was decoded from, or last wrote back
+    source: Vec<u8>,
+    editor: Option<TextEditor>,
+    /// generation of the editor when it last wrote back
+    written: u64,
+}
+
+impl CachedEditor {
+    /// decodes the content of a file, highlighted with the grammar for its extension
+    fn decode(path: &str, bytes: &[u8]) -> Self {
+        let editor = TextEditor::decode(bytes)
+            .map(|editor| editor.with_grammar(grammars().for_path(path).cloned()));
+        Self {
+            source: bytes.to_vec(),
+            written: editor
+                .as_ref()
+                .map(TextEditor::generation)
+                .unwrap_or_default(),
+            editor,
+        }
+    }
+}
+
+/// Shows the content of a `file::` attribute in a text editor, with buttons to reload it and to write it with `handler`
+pub(super) fn file_edit_ui(
+    handler: &dyn SchemeHandler,
+    path: &str,
+    label: &str,
+    value: &mut Value,
+    ui: &Ui,
+) {
+    let key = format!("{}{}{}", label, handler.scheme(), path);
+    let mut editors = WidgetCache::<CachedEditor>::lock(ui);
+
+    let cached = as_bytes(value).map(|bytes| {
+        let cached = editors.get_or_insert_with(&key, || CachedEditor::decode(path, bytes));
+        // the value was changed by something other than the editor, e.g. the file watcher
+        if cached.source != bytes {
+            *cached = CachedEditor::decode(path, bytes);
+        }
+        cached
+    });
+
+    let mut dirty = false;
+    match cached {
+        Some(CachedEditor {
+            source,
+            editor: Some(editor),
+            written,
+        }) => {
+            editor.ui(&key, ui);
+            if editor.generation() != *written {
+                *written = editor.generation();
+                let encoded = editor.encode();
+                source.clone_from(&encoded);
+                *value = match value {
+                    Value::TextBuffer(_) => match String::from_utf8(encoded) {
+                        Ok(text) => Value::TextBuffer(text),
+                        Err(err) => Value::BinaryVector(err.into_bytes()),
+                    },
+                    _ => Value::BinaryVector(encoded),
+                };
+            }
+            dirty = editor.is_dirty();
+        }
+        Some(CachedEditor {
+            source,
+            editor: None,
+            ..
+        }) => {
+            if let Value::BinaryVector(bytes) = value {
+                if ImageFormat::detect(bytes).is_some() {
+                    image_ui(&key, bytes, ui);
+                } else if binary_ui(&key, bytes, ui) {
+                    source.clone_from(bytes);
+                }
+            }
+        }
+        None => {}
+    }
+
+    if ui.button(format!("reload {}", label)) {
+        match handler.load(path) {
+            // text values stay text if the file is valid utf-8
+            Ok(loaded) if value.kind() == ValueKind::TextBuffer => {
+                *value = loaded.coerce(ValueKind::TextBuffer).unwrap_or(loaded);
+                editors.remove(&key);
+            }
+            Ok(loaded) => {
+                *value = loaded;
+                editors.remove(&key);
+            }
+            Err(err) => {
+                eprintln!(
+                    "Could not load file '{}', for attribute labeled '{}'. Error: {}",
+                    path, label, err
+                );
+            }
+        }
+    }
+
+    ui.same_line();
+    if ui.button(format!(
+        "write to disk {}{}",
+        label,
+        if dirty { " *" } else { "" }
+    )) {
+        match handler.save(path, value) {
+            Ok(_) => {
+                println!("Saved to {}", path);
+                if let Some(editor) = editors.get_mut(&key).and_then(|c| c.editor.as_mut()) {
+                    editor.mark_saved();
+                }
+            }
+            Err(err) => {
+                eprintln!(
+                    "Could not write file '{}', for attribute labeled '{}'. Error: {}",
+                    path, label, err
+                );
+            }
+        }
+    }
+}
+
+/// Resource with the environment variables that were saved through `env::` attributes
+///
+/// Variables are kept here instead of changing the environment of the process, which isn't safe while
+/// other threads are running. A variable set to `None` was removed. Commands run by `cmd::` see these variables.
+/// This is shared with the `env::` and `cmd::` handlers that `Environment` registers
+#[derive(Debug, Clone, Default)]
+pub struct EnvOverrides(Arc<Mutex<BTreeMap<String, Option<String>>>>);
+
+impl EnvOverrides {
+    /// returns the value of the variable `name`, from the overrides or else from the environment of the process
+    pub fn var(&self, name: &str) -> Option<String> {
+        match self.lock().get(name) {
+            Some(value) => value.clone(),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    /// overrides the variable `name`
+    pub fn set_var(&self, name: &str, value: impl Into<String>) {
+        self.lock().insert(name.to_string(), Some(value.into()));
+    }
+
+    /// overrides the variable `name` as removed
+    pub fn remove_var(&self, name: &str) {
+        self.lock().insert(name.to_string(), None);
+    }
+
+    /// applies the overrides to the environment of `command`
+    pub fn apply(&self, command: &mut Command) {
+        for (name, value) in self.lock().iter() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Option<String>>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Extension that adds an `EnvOverrides` resource to the app world,
+/// and registers `env::` and `cmd::` handlers that share it
+#[derive(Default)]
+pub struct Environment;
+
+impl Extension for Environment {
+    fn configure_app_world(world: &mut World) {
+        if !world.has_value::<EnvOverrides>() {
+            let overrides = EnvOverrides::default();
+            register_scheme(EnvScheme::new(overrides.clone()));
+            register_scheme(CommandScheme::new(overrides.clone()));
+            world.insert(overrides);
+        }
+    }
+}
+
+/// `env::<variable>`, binds an environment variable to text
+///
+/// Saving doesn't change the environment of the process, the value is kept in `EnvOverrides`
+#[derive(Default)]
+pub struct EnvScheme {
+    overrides: EnvOverrides,
+}
+
+impl EnvScheme {
+    /// returns a handler that keeps saved variables in `overrides`
+    pub fn new(overrides: EnvOverrides) -> Self {
+        Self { overrides }
+    }
+}
+
+impl SchemeHandler for EnvScheme {
+    fn scheme(&self) -> &str {
+        "env::"
+    }
+
+    fn validate(&self, path: &str, _value: &Value) -> Result<(), SchemeError> {
+        if path.is_empty() || path.contains(['=', '\0']) {
+            Err(SchemeError::InvalidPath(format!(
+                "'{}' is not a valid variable name",
+                path
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn load(&self, path: &str) -> Result<Value, SchemeError> {
+        Ok(self
+            .overrides
+            .var(path)
+            .map(Value::TextBuffer)
+            .unwrap_or(Value::Empty))
+    }
+
+    fn save(&self, path: &str, value: &Value) -> Result<(), SchemeError> {
+        match value {
+            Value::Empty => self.overrides.remove_var(path),
+            value => self.overrides.set_var(
+                path,
+                String::from_utf8_lossy(&to_bytes(self.scheme(), value)?),
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// `cmd::<program> <args>`, binds the output of a command to text, arguments are separated by whitespace
+///
+/// Commands are only run when the value is loaded, and can't be saved
+#[derive(Default)]
+pub struct CommandScheme {
+    overrides: EnvOverrides,
+}
+
+impl CommandScheme {
+    /// returns a handler that runs commands with the variables in `overrides`
+    pub fn new(overrides: EnvOverrides) -> Self {
+        Self { overrides }
+    }
+}
+
+impl SchemeHandler for CommandScheme {
+    fn scheme(&self) -> &str {
+        "cmd::"
+    }
+
+    fn validate(&self, path: &str, _value: &Value) -> Result<(), SchemeError> {
+        if path.trim().is_empty() {
+            Err(SchemeError::InvalidPath("command is empty".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn load(&self, path: &str) -> Result<Value, SchemeError> {
+        let mut args = path.split_whitespace();
+        let program = args
+            .next()
+            .ok_or_else(|| SchemeError::InvalidPath("command is empty".to_string()))?;
+
+        let mut command = Command::new(program);
+        command.args(args);
+        self.overrides.apply(&mut command);
+        let output = command.output()?;
+        if output.status.success() {
+            Ok(Value::TextBuffer(
+                String::from_utf8_lossy(&output.stdout).to_string(),
+            ))
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(SchemeError::Command(match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => format!("{}, {}", output.status, stderr),
+            }))
+        }
+    }
+
+    fn edit_ui(&self, path: &str, label: &str, value: &mut Value, ui: &Ui) {
+        if let Err(err) = self.validate(path, value) {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], err.to_string());
+            return;
+        }
+
+        if ui.button(format!("run {}", label)) {
+            match self.load(path) {
+                Ok(loaded) => *value = loaded,
+                Err(err) => eprintln!("Could not run '{}', {}", path, err),
+            }
+        }
+    }
+}
+
+/// `dir::<path>`, binds the names of the entries of a directory to text, one per line
+///
+/// Saving creates the directory
+pub struct DirScheme;
+
+impl SchemeHandler for DirScheme {
+    fn scheme(&self) -> &str {
+        "dir::"
+    }
+
+    fn load(&self, path: &str) -> Result<Value, SchemeError> {
+        let mut names = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+
+        Ok(Value::TextBuffer(names.join("\n")))
+    }
+
+    fn save(&self, path: &str, _value: &Value) -> Result<(), SchemeError> {
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandScheme;
+    use super::EnvOverrides;
+    use super::EnvScheme;
+    use super::SchemeHandler;
+    use crate::system::Value;
+
+    #[test]
+    fn saved_variables_do_not_change_the_process_environment() {
+        let overrides = EnvOverrides::default();
+        let env = EnvScheme::new(overrides.clone());
+        let name = "ATLIER_ENV_SCHEME_TEST";
+
+        env.save(name, &Value::TextBuffer("saved".to_string()))
+            .expect("can save");
+        assert_eq!(
+            env.load(name).ok(),
+            Some(Value::TextBuffer("saved".to_string()))
+        );
+        assert_eq!(std::env::var(name).ok(), None);
+
+        #[cfg(unix)]
+        assert_eq!(
+            CommandScheme::new(overrides.clone())
+                .load(&format!("printenv {}", name))
+                .ok(),
+            Some(Value::TextBuffer("saved\n".to_string()))
+        );
+
+        env.save(name, &Value::Empty).expect("can remove");
+        assert_eq!(env.load(name).ok(), Some(Value::Empty));
+    }
+}
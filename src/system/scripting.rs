@@ -0,0 +1,678 @@
+use regex::Regex;
+use rhai::Array;
+use rhai::Blob;
+use rhai::Dynamic;
+use rhai::Engine;
+use rhai::EvalAltResult;
+use rhai::Module;
+use rhai::Scope;
+use rhai::FLOAT;
+use rhai::INT;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use super::Attribute;
+use super::Extension;
+use super::History;
+use super::Operation;
+use super::Schema;
+use super::Value;
+use super::ValueKind;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Copy of an attribute that a script has read from the world
+#[derive(Debug, Clone)]
+struct ScriptAttribute {
+    entity: u32,
+    attribute: Attribute,
+}
+
+/// View of the attributes in the app world that is shared with a script
+///
+/// Scripts edit a copy of the attributes, changes are written back to the world after the script finishes
+#[derive(Debug, Default)]
+struct WorldView {
+    attributes: BTreeMap<u32, Attribute>,
+    /// copy of the `Schema` resource, commits are validated against it
+    schema: Rc<Schema>,
+    /// entity and previous name/value of each attribute that was committed
+    commits: Vec<(u32, (String, Value))>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ScriptWorld(Rc<RefCell<WorldView>>);
+
+impl ScriptWorld {
+    fn query(&mut self, pattern: Option<&str>) -> ScriptResult<Array> {
+        let pattern = match pattern {
+            Some(pattern) => Some(Regex::new(pattern).map_err(|err| err.to_string())?),
+            None => None,
+        };
+
+        Ok(self
+            .0
+            .borrow()
+            .attributes
+            .iter()
+            .filter(|(_, a)| pattern.as_ref().is_none_or(|p| p.is_match(a.name())))
+            .map(|(entity, attribute)| {
+                Dynamic::from(ScriptAttribute {
+                    entity: *entity,
+                    attribute: attribute.clone(),
+                })
+            })
+            .collect())
+    }
+
+    fn get(&mut self, entity: INT) -> Dynamic {
+        let view = self.0.borrow();
+        match u32::try_from(entity)
+            .ok()
+            .and_then(|e| view.attributes.get_key_value(&e))
+        {
+            Some((entity, attribute)) => Dynamic::from(ScriptAttribute {
+                entity: *entity,
+                attribute: attribute.clone(),
+            }),
+            None => Dynamic::UNIT,
+        }
+    }
+
+    fn find(&mut self, name: &str) -> Dynamic {
+        let view = self.0.borrow();
+        match view.attributes.iter().find(|(_, a)| a.name() == name) {
+            Some((entity, attribute)) => Dynamic::from(ScriptAttribute {
+                entity: *entity,
+                attribute: attribute.clone(),
+            }),
+            None => Dynamic::UNIT,
+        }
+    }
+
+    fn with_attribute<T>(
+        &mut self,
+        entity: INT,
+        f: impl FnOnce(&mut Attribute) -> ScriptResult<T>,
+    ) -> ScriptResult<T> {
+        let mut view = self.0.borrow_mut();
+        match u32::try_from(entity)
+            .ok()
+            .and_then(|e| view.attributes.get_mut(&e))
+        {
+            Some(attribute) => f(attribute),
+            None => Err(format!("no attribute on entity {}", entity).into()),
+        }
+    }
+
+    fn edit(&mut self, entity: INT, value: Dynamic) -> ScriptResult<()> {
+        self.with_attribute(entity, |attribute| {
+            let kind = match attribute.transient() {
+                Some((_, value)) => value.kind(),
+                None => attribute.value().kind(),
+            };
+            attribute.edit_as(to_value(value, Some(kind))?);
+            Ok(())
+        })
+    }
+
+    fn rename(&mut self, entity: INT, name: &str) -> ScriptResult<()> {
+        self.with_attribute(entity, |attribute| {
+            let value = match attribute.transient() {
+                Some((_, value)) => value.clone(),
+                None => attribute.value().clone(),
+            };
+            attribute.edit((name.to_string(), value));
+            Ok(())
+        })
+    }
+
+    fn commit(&mut self, entity: INT) -> ScriptResult<bool> {
+        let schema = self.0.borrow().schema.clone();
+        let before = self.with_attribute(entity, |attribute| {
+            schema
+                .commit(attribute)
+                .map_err(|err| format!("could not commit {}, {}", attribute.name(), err).into())
+        })?;
+        match before {
+            Some(before) => {
+                self.0.borrow_mut().commits.push((entity as u32, before));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Converts a value to the closest native script type
+///
+/// Pairs and ranges become arrays, binary vectors become blobs, and references become ints
+fn to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Empty => Dynamic::UNIT,
+        Value::Bool(b) => Dynamic::from(*b),
+        Value::TextBuffer(text) | Value::Symbol(text) => Dynamic::from(text.clone()),
+        Value::Int(i) => Dynamic::from(*i as INT),
+        Value::IntPair(a, b) => Dynamic::from_array(vec![(*a as INT).into(), (*b as INT).into()]),
+        Value::IntRange(a, b, c) => Dynamic::from_array(vec![
+            (*a as INT).into(),
+            (*b as INT).into(),
+            (*c as INT).into(),
+        ]),
+        Value::Float(f) => Dynamic::from(*f as FLOAT),
+        Value::FloatPair(a, b) => {
+            Dynamic::from_array(vec![(*a as FLOAT).into(), (*b as FLOAT).into()])
+        }
+        Value::FloatRange(a, b, c) => Dynamic::from_array(vec![
+            (*a as FLOAT).into(),
+            (*b as FLOAT).into(),
+            (*c as FLOAT).into(),
+        ]),
+        Value::BinaryVector(bytes) => Dynamic::from_blob(bytes.clone()),
+        Value::Reference(r) => Dynamic::from(*r as INT),
+    }
+}
+
+/// Converts a script value to a value, and coerces it to `kind` when that is possible
+fn to_value(dynamic: Dynamic, kind: Option<ValueKind>) -> ScriptResult<Value> {
+    let value = if dynamic.is::<Value>() {
+        dynamic.cast::<Value>()
+    } else if dynamic.is_unit() {
+        Value::Empty
+    } else if let Ok(b) = dynamic.as_bool() {
+        Value::Bool(b)
+    } else if let Ok(i) = dynamic.as_int() {
+        Value::Int(to_i32(i)?)
+    } else if let Ok(f) = dynamic.as_float() {
+        Value::Float(f as f32)
+    } else if dynamic.is_string() {
+        Value::TextBuffer(dynamic.into_string()?)
+    } else if dynamic.is_blob() {
+        Value::BinaryVector(dynamic.cast::<Blob>())
+    } else if dynamic.is_array() {
+        let items = dynamic.into_array()?;
+        let ints = items
+            .iter()
+            .map(|i| {
+                i.as_int()
+                    .map_err(|_| ())
+                    .and_then(|i| to_i32(i).map_err(|_| ()))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let floats = items
+            .iter()
+            .map(|i| {
+                i.as_float()
+                    .or_else(|_| i.as_int().map(|i| i as FLOAT))
+                    .map(|f| f as f32)
+            })
+            .collect::<Result<Vec<_>, _>>();
+
+        match (ints, floats) {
+            (Ok(i), _) if i.len() == 2 => Value::IntPair(i[0], i[1]),
+            (Ok(i), _) if i.len() == 3 => Value::IntRange(i[0], i[1], i[2]),
+            (_, Ok(f)) if f.len() == 2 => Value::FloatPair(f[0], f[1]),
+            (_, Ok(f)) if f.len() == 3 => Value::FloatRange(f[0], f[1], f[2]),
+            _ => return Err("only arrays of 2 or 3 numbers can be converted to a value".into()),
+        }
+    } else {
+        return Err(format!("can't convert {} to a value", dynamic.type_name()).into());
+    };
+
+    Ok(match kind {
+        Some(kind) => value.coerce(kind).unwrap_or(value),
+        None => value,
+    })
+}
+
+fn to_i32(i: INT) -> ScriptResult<i32> {
+    i32::try_from(i).map_err(|_| format!("{} is out of range for an int", i).into())
+}
+
+/// Registers `Value`, `Attribute` and `World` with `engine`
+fn register_types(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Value>("Value")
+        .register_get("kind", |v: &mut Value| v.kind().to_string())
+        .register_get("native", |v: &mut Value| to_dynamic(v))
+        .register_fn("to_string", |v: &mut Value| v.to_string())
+        .register_fn("to_debug", |v: &mut Value| format!("{:?}", v))
+        .register_fn("==", |a: Value, b: Value| a == b)
+        .register_fn("!=", |a: Value, b: Value| a != b);
+
+    let mut module = Module::new();
+    module.set_native_fn("empty", || Ok(Value::Empty));
+    module.set_native_fn("bool", |b: bool| Ok(Value::Bool(b)));
+    module.set_native_fn("text", |text: &str| Ok(Value::TextBuffer(text.to_string())));
+    module.set_native_fn("symbol", |text: &str| Ok(Value::Symbol(text.to_string())));
+    module.set_native_fn("int", |i: INT| Ok(Value::Int(to_i32(i)?)));
+    module.set_native_fn("int_pair", |a: INT, b: INT| {
+        Ok(Value::IntPair(to_i32(a)?, to_i32(b)?))
+    });
+    module.set_native_fn("int_range", |a: INT, b: INT, c: INT| {
+        Ok(Value::IntRange(to_i32(a)?, to_i32(b)?, to_i32(c)?))
+    });
+    module.set_native_fn("float", |f: FLOAT| Ok(Value::Float(f as f32)));
+    module.set_native_fn("float_pair", |a: FLOAT, b: FLOAT| {
+        Ok(Value::FloatPair(a as f32, b as f32))
+    });
+    module.set_native_fn("float_range", |a: FLOAT, b: FLOAT, c: FLOAT| {
+        Ok(Value::FloatRange(a as f32, b as f32, c as f32))
+    });
+    module.set_native_fn("binary", |bytes: Blob| Ok(Value::BinaryVector(bytes)));
+    module.set_native_fn("reference", |r: INT| Ok(Value::Reference(r as u64)));
+    module.set_native_fn("from", |d: Dynamic| to_value(d, None));
+    engine.register_static_module("Value", module.into());
+
+    engine
+        .register_type_with_name::<ScriptAttribute>("Attribute")
+        .register_get("entity", |a: &mut ScriptAttribute| a.entity as INT)
+        .register_get("id", |a: &mut ScriptAttribute| a.attribute.id() as INT)
+        .register_get("name", |a: &mut ScriptAttribute| {
+            a.attribute.name().to_string()
+        })
+        .register_get("value", |a: &mut ScriptAttribute| {
+            a.attribute.value().clone()
+        })
+        .register_get("is_stable", |a: &mut ScriptAttribute| {
+            a.attribute.is_stable()
+        })
+        .register_get("transient", |a: &mut ScriptAttribute| {
+            match a.attribute.transient() {
+                Some((name, value)) => {
+                    Dynamic::from_array(vec![name.clone().into(), Dynamic::from(value.clone())])
+                }
+                None => Dynamic::UNIT,
+            }
+        })
+        .register_fn("to_string", |a: &mut ScriptAttribute| {
+            format!(
+                "{} {} = {:?}",
+                a.entity,
+                a.attribute.name(),
+                a.attribute.value()
+            )
+        });
+
+    engine
+        .register_type_with_name::<ScriptWorld>("World")
+        .register_fn("query", |w: &mut ScriptWorld| w.query(None))
+        .register_fn("query", |w: &mut ScriptWorld, pattern: &str| {
+            w.query(Some(pattern))
+        })
+        .register_fn("get", ScriptWorld::get)
+        .register_fn("find", ScriptWorld::find)
+        .register_fn("edit", ScriptWorld::edit)
+        .register_fn("rename", ScriptWorld::rename)
+        .register_fn("commit", ScriptWorld::commit);
+}
+
+#[derive(Debug, Default)]
+struct ScriptState {
+    log: Vec<String>,
+    commands: BTreeMap<String, String>,
+}
+
+/// Extension that embeds a rhai scripting engine, and shows a console window to run scripts
+/// and script-defined commands against the app world
+///
+/// Scripts can use:
+/// - `world.query()`, `world.query(pattern)`, `world.get(entity)` and `world.find(name)` to read attributes,
+/// - `world.edit(entity, value)` and `world.rename(entity, name)` to make pending changes,
+/// - `world.commit(entity)` to commit the pending changes of an attribute, validated against the `Schema` resource,
+/// - `Value::int(1)`, `Value::text("a")`, ... to construct values of a specific kind,
+/// - `command(name, source)` to add a command, which is shown as a button in the console
+///
+/// Native script values passed to `world.edit` are coerced to the kind of the attribute when possible.
+/// Changes are written back to the world after a script finishes, commits are recorded in `History` if it exists
+pub struct Scripting {
+    /// when true, the console window is shown
+    pub opened: bool,
+    engine: Engine,
+    scope: Scope<'static>,
+    state: Rc<RefCell<ScriptState>>,
+    input: String,
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        let state = Rc::new(RefCell::new(ScriptState::default()));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(1_000_000);
+        register_types(&mut engine);
+
+        let log = state.clone();
+        engine.on_print(move |text| log.borrow_mut().log.push(text.to_string()));
+        let log = state.clone();
+        engine.on_debug(move |text, _, position| {
+            log.borrow_mut().log.push(format!("{} {}", position, text))
+        });
+        let commands = state.clone();
+        engine.register_fn("command", move |name: &str, source: &str| {
+            commands
+                .borrow_mut()
+                .commands
+                .insert(name.to_string(), source.to_string());
+        });
+
+        Self {
+            opened: true,
+            engine,
+            scope: Scope::new(),
+            state,
+            input: String::new(),
+        }
+    }
+}
+
+impl Scripting {
+    /// adds a command that runs `source` when its button is pressed
+    pub fn with_command(mut self, name: impl AsRef<str>, source: impl AsRef<str>) -> Self {
+        self.add_command(name, source);
+        self
+    }
+
+    /// adds or replaces a command
+    pub fn add_command(&mut self, name: impl AsRef<str>, source: impl AsRef<str>) {
+        self.state
+            .borrow_mut()
+            .commands
+            .insert(name.as_ref().to_string(), source.as_ref().to_string());
+    }
+
+    /// names of the commands, in order
+    pub fn commands(&self) -> Vec<String> {
+        self.state.borrow().commands.keys().cloned().collect()
+    }
+
+    /// the engine, to register additional types and functions
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// lines printed by scripts, and the results of scripts run from the console
+    pub fn log(&self) -> Vec<String> {
+        self.state.borrow().log.clone()
+    }
+
+    /// Runs `source` against `app_world`, and writes changes to attributes back to the world
+    ///
+    /// Variables declared at the top level of a script are kept for the next script,
+    /// changes are only written back if the script succeeds
+    pub fn run(&mut self, app_world: &World, source: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        self.run_as("script", app_world, source)
+    }
+
+    /// runs the command with `name`, returns an error if there is no such command
+    pub fn run_command(
+        &mut self,
+        app_world: &World,
+        name: &str,
+    ) -> Result<Dynamic, Box<EvalAltResult>> {
+        let source = self.state.borrow().commands.get(name).cloned();
+        match source {
+            Some(source) => self.run_as(name, app_world, &source),
+            None => Err(format!("unknown command '{}'", name).into()),
+        }
+    }
+
+    fn run_as(
+        &mut self,
+        name: &str,
+        app_world: &World,
+        source: &str,
+    ) -> Result<Dynamic, Box<EvalAltResult>> {
+        let view = ScriptWorld::default();
+        {
+            let mut view = view.0.borrow_mut();
+            view.attributes = (
+                &app_world.entities(),
+                &app_world.read_component::<Attribute>(),
+            )
+                .join()
+                .map(|(e, a)| (e.id(), a.clone()))
+                .collect();
+            if let Some(schema) = app_world.try_fetch::<Schema>() {
+                view.schema = Rc::new(Schema::clone(&schema));
+            }
+        }
+        self.scope.set_or_push("world", view.clone());
+
+        let result = self
+            .engine
+            .eval_with_scope::<Dynamic>(&mut self.scope, source)?;
+
+        let view = view.0.take();
+        let entities = app_world.entities();
+        let mut attributes = app_world.write_component::<Attribute>();
+        for (id, attribute) in view.attributes {
+            let entity = entities.entity(id);
+            if !entities.is_alive(entity) {
+                continue;
+            }
+
+            if let Some(current) = attributes.get_mut(entity) {
+                if *current != attribute {
+                    *current = attribute;
+                }
+            }
+        }
+
+        if let Some(mut history) = app_world.try_fetch_mut::<History>() {
+            history.begin(name);
+            for (id, before) in view.commits {
+                let entity = entities.entity(id);
+                if let Some(attribute) = attributes.get(entity) {
+                    history.record(Operation::Commit {
+                        entity,
+                        before,
+                        after: (attribute.name().to_string(), attribute.value().clone()),
+                    });
+                }
+            }
+            history.end();
+        }
+
+        Ok(result)
+    }
+
+    fn log_result(&mut self, source: &str, result: Result<Dynamic, Box<EvalAltResult>>) {
+        let mut state = self.state.borrow_mut();
+        state.log.push(format!("> {}", source));
+        match result {
+            Ok(result) if result.is_unit() => {}
+            Ok(result) => state.log.push(format!("{}", result)),
+            Err(err) => state.log.push(format!("error: {}", err)),
+        }
+    }
+
+    fn console_ui(&mut self, app_world: &World, ui: &imgui::Ui) {
+        let mut run_command = None;
+        for (i, name) in self.commands().into_iter().enumerate() {
+            if i > 0 {
+                ui.same_line();
+            }
+            if ui.button(&name) {
+                run_command = Some(name);
+            }
+        }
+        if let Some(name) = run_command {
+            let result = self.run_command(app_world, &name);
+            self.log_result(&name, result);
+        }
+        ui.separator();
+
+        imgui::ChildWindow::new("script log")
+            .size([0.0, -110.0])
+            .build(ui, || {
+                for line in self.state.borrow().log.iter() {
+                    ui.text_wrapped(line);
+                }
+                if ui.scroll_y() >= ui.scroll_max_y() {
+                    ui.set_scroll_here_y_with_ratio(1.0);
+                }
+            });
+
+        ui.input_text_multiline("##script", &mut self.input, [-1.0, 80.0])
+            .build();
+        let run = ui.is_item_focused() && ui.io().key_ctrl && ui.is_key_pressed(imgui::Key::Enter);
+        let run = ui.button("run") || run;
+        ui.same_line();
+        if ui.button("clear") {
+            self.state.borrow_mut().log.clear();
+        }
+
+        if run && !self.input.trim().is_empty() {
+            let source = self.input.clone();
+            let result = self.run(app_world, &source);
+            self.log_result(&source, result);
+            self.input.clear();
+        }
+    }
+}
+
+impl Extension for Scripting {
+    fn configure_app_world(world: &mut World) {
+        world.register::<Attribute>();
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        if !self.opened {
+            return;
+        }
+
+        let mut opened = self.opened;
+        imgui::Window::new("Scripts")
+            .size([600.0, 400.0], imgui::Condition::FirstUseEver)
+            .opened(&mut opened)
+            .build(ui, || self.console_ui(app_world, ui));
+        self.opened = opened;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::Entity;
+    use specs::World;
+    use specs::WorldExt;
+    use std::rc::Rc;
+
+    use super::ScriptWorld;
+    use super::Scripting;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::History;
+    use crate::system::Metadata;
+    use crate::system::Schema;
+    use crate::system::Value;
+
+    fn setup() -> (World, Entity) {
+        let mut world = World::new();
+        Scripting::configure_app_world(&mut world);
+        let history = History::new(&mut world);
+        world.insert(history);
+        let mut schema = Schema::default();
+        schema.insert(
+            "a",
+            Metadata {
+                max: Some(10.0),
+                ..Default::default()
+            },
+        );
+        world.insert(schema);
+
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(1)))
+            .build();
+        (world, entity)
+    }
+
+    fn attribute(world: &World, entity: Entity) -> Attribute {
+        world
+            .read_component::<Attribute>()
+            .get(entity)
+            .cloned()
+            .expect("attribute exists")
+    }
+
+    #[test]
+    fn commits_are_written_back_and_recorded() {
+        let (world, entity) = setup();
+        let mut scripting = Scripting::default().with_command(
+            "double",
+            "let a = world.find(\"a\"); world.edit(a.entity, a.value.native * 2); world.commit(a.entity)",
+        );
+
+        let result = scripting
+            .run_command(&world, "double")
+            .expect("script runs");
+        assert!(result.as_bool().expect("commit returns a bool"));
+
+        let attribute = attribute(&world, entity);
+        assert_eq!(attribute.value(), &Value::Int(2));
+        assert!(attribute.is_stable());
+
+        let history = world.read_resource::<History>();
+        let transaction = history.undo_stack().last().expect("commit is recorded");
+        assert_eq!(transaction.name, "double");
+        assert_eq!(transaction.operations.len(), 1);
+    }
+
+    #[test]
+    fn changes_are_discarded_when_a_script_fails() {
+        let (world, entity) = setup();
+        let mut scripting = Scripting::default();
+
+        let result = scripting.run(
+            &world,
+            "let a = world.find(\"a\"); world.edit(a.entity, 5); world.commit(a.entity); throw \"stop\"",
+        );
+        assert!(result.is_err());
+
+        let attribute = attribute(&world, entity);
+        assert_eq!(attribute.value(), &Value::Int(1));
+        assert!(attribute.is_stable());
+        assert!(world.read_resource::<History>().undo_stack().is_empty());
+    }
+
+    #[test]
+    fn commits_are_validated_against_the_schema() {
+        let (world, entity) = setup();
+        let mut scripting = Scripting::default();
+
+        let err = scripting
+            .run(
+                &world,
+                "let a = world.find(\"a\"); world.edit(a.entity, 11); world.commit(a.entity)",
+            )
+            .expect_err("11 is out of range");
+        assert!(err.to_string().contains("could not commit a"), "{}", err);
+        assert_eq!(attribute(&world, entity).value(), &Value::Int(1));
+
+        // the pending value is kept in the view, so that a script can fix it
+        let mut view = ScriptWorld::default();
+        {
+            let mut state = view.0.borrow_mut();
+            state
+                .attributes
+                .insert(0, Attribute::new(0, "a", Value::Int(1)));
+            state.schema = Rc::new(Schema::clone(&world.read_resource::<Schema>()));
+        }
+        view.edit(0, 11.into()).unwrap();
+        assert!(view.commit(0).is_err());
+        assert_eq!(
+            view.0.borrow().attributes[&0].transient(),
+            Some(&("a".to_string(), Value::Int(11)))
+        );
+        view.edit(0, 10.into()).unwrap();
+        assert_eq!(view.commit(0).ok(), Some(true));
+        assert_eq!(view.0.borrow().commits.len(), 1);
+    }
+}
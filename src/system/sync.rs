@@ -0,0 +1,704 @@
+use serde::Deserialize;
+use serde::Serialize;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Extension;
+use super::Subscription;
+use super::Value;
+
+/// Largest message that is accepted from a peer
+const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+/// Largest amount of output that is buffered for a peer, peers that fall further behind are disconnected,
+/// and receive the full state when they reconnect
+const MAX_PENDING_WRITE: usize = 4 * MAX_MESSAGE_LEN;
+
+/// Orders writes to the same attribute, the highest stamp wins
+///
+/// `clock` is a lamport clock, ties between sites are broken by `site`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub clock: u64,
+    pub site: u64,
+}
+
+/// Last-writer-wins register for the value of the attribute with some (`id`, `name`),
+/// a value of `None` means the attribute was removed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncUpdate {
+    pub id: u32,
+    pub name: String,
+    pub stamp: Stamp,
+    pub value: Option<Value>,
+}
+
+trait SyncStream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> SyncStream for T {}
+
+enum Listener {
+    Tcp(TcpListener),
+    /// the socket file is removed when the listener is dropped
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, std::path::PathBuf),
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            if let Err(err) = std::fs::remove_file(&path) {
+                eprintln!("Could not remove sync socket {:?}, {}", path, err);
+            }
+        }
+    }
+}
+
+struct Peer {
+    stream: Box<dyn SyncStream>,
+    read: Vec<u8>,
+    write: Vec<u8>,
+    open: bool,
+}
+
+impl Peer {
+    fn new(stream: Box<dyn SyncStream>) -> Self {
+        Self {
+            stream,
+            read: vec![],
+            write: vec![],
+            open: true,
+        }
+    }
+
+    fn send(&mut self, update: &SyncUpdate) {
+        if !self.open {
+            return;
+        }
+
+        match bincode::serialize(update) {
+            Ok(bytes) if self.write.len() + 4 + bytes.len() > MAX_PENDING_WRITE => {
+                eprintln!(
+                    "Sync peer is not reading, disconnecting with {} bytes pending",
+                    self.write.len()
+                );
+                self.open = false;
+                self.write.clear();
+            }
+            Ok(bytes) => {
+                self.write.extend((bytes.len() as u32).to_le_bytes());
+                self.write.extend(bytes);
+            }
+            Err(err) => eprintln!("Could not encode sync update, {}", err),
+        }
+    }
+
+    /// writes as much of the pending output as the socket accepts without blocking
+    fn flush(&mut self) {
+        while self.open && !self.write.is_empty() {
+            match self.stream.write(&self.write) {
+                Ok(0) => self.open = false,
+                Ok(n) => {
+                    self.write.drain(..n);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    eprintln!("Sync peer disconnected, {}", err);
+                    self.open = false;
+                }
+            }
+        }
+    }
+
+    /// reads everything that is available without blocking, and returns the complete updates
+    fn receive(&mut self) -> Vec<SyncUpdate> {
+        let mut buf = [0; 4096];
+        while self.open {
+            match self.stream.read(&mut buf) {
+                Ok(0) => self.open = false,
+                Ok(n) => self.read.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    eprintln!("Sync peer disconnected, {}", err);
+                    self.open = false;
+                }
+            }
+        }
+
+        let mut updates = vec![];
+        while self.read.len() >= 4 {
+            let len = u32::from_le_bytes([self.read[0], self.read[1], self.read[2], self.read[3]])
+                as usize;
+            if len > MAX_MESSAGE_LEN {
+                eprintln!("Sync peer sent a message that is too large, {} bytes", len);
+                self.open = false;
+                break;
+            }
+            if self.read.len() < 4 + len {
+                break;
+            }
+
+            match bincode::deserialize::<SyncUpdate>(&self.read[4..4 + len]) {
+                Ok(update) => updates.push(update),
+                Err(err) => eprintln!("Could not decode sync update, {}", err),
+            }
+            self.read.drain(..4 + len);
+        }
+        updates
+    }
+}
+
+/// Extension that synchronizes the committed values of attributes with other processes over local sockets
+///
+/// Each attribute is identified by (`id`, `name`) and is a last-writer-wins register, so peers that
+/// receive the same updates converge on the same values regardless of the order they arrive in.
+/// Local changes are detected and exchanged when the app world is maintained, or when `sync` is called.
+/// Peers that connect receive the full state, and updates received from one peer are relayed to the others.
+/// If more than one entity has an attribute with the same id and name, only one of them is synchronized
+pub struct AttributeSync {
+    site: u64,
+    clock: u64,
+    registers: BTreeMap<(u32, String), (Stamp, Option<Value>)>,
+    /// (`id`, `name`) of the attribute on each entity, by entity id, as of the last local changes
+    keys: HashMap<u32, (u32, String)>,
+    subscription: Option<Subscription>,
+    listeners: Vec<Listener>,
+    peers: Vec<Peer>,
+}
+
+impl Default for AttributeSync {
+    fn default() -> Self {
+        static SITES: AtomicU64 = AtomicU64::new(0);
+
+        let mut hasher = DefaultHasher::new();
+        std::process::id().hash(&mut hasher);
+        SITES.fetch_add(1, Ordering::Relaxed).hash(&mut hasher);
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+            .hash(&mut hasher);
+
+        Self {
+            site: hasher.finish(),
+            clock: 0,
+            registers: BTreeMap::new(),
+            keys: HashMap::new(),
+            subscription: None,
+            listeners: vec![],
+            peers: vec![],
+        }
+    }
+}
+
+impl AttributeSync {
+    /// id of this site, used to break ties between concurrent writes
+    pub fn site(&self) -> u64 {
+        self.site
+    }
+
+    /// number of connected peers
+    pub fn peers(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// accepts peers on a tcp address, returns the bound address, which is useful when binding to port 0
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        self.listeners.push(Listener::Tcp(listener));
+        Ok(addr)
+    }
+
+    /// connects to a peer that is listening on a tcp address
+    pub fn connect(&mut self, addr: impl ToSocketAddrs) -> std::io::Result<()> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        self.add_peer(Box::new(stream));
+        Ok(())
+    }
+
+    /// Accepts peers on a unix socket, the socket file is removed when this extension is dropped
+    ///
+    /// A socket file left behind at `path` by a process that exited without cleaning up is replaced,
+    /// other kinds of files are not
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+
+        let path = path.as_ref();
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket())
+            && std::os::unix::net::UnixStream::connect(path).is_err()
+        {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        self.listeners
+            .push(Listener::Unix(listener, path.to_path_buf()));
+        Ok(())
+    }
+
+    /// connects to a peer that is listening on a unix socket
+    #[cfg(unix)]
+    pub fn connect_unix(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_nonblocking(true)?;
+        self.add_peer(Box::new(stream));
+        Ok(())
+    }
+
+    /// Exchanges changes with peers, local changes made since the last call are sent,
+    /// and updates from peers are applied to `world`
+    pub fn sync(&mut self, world: &World) {
+        self.accept();
+
+        for update in self.local_changes(world) {
+            self.broadcast(&update, None);
+        }
+
+        let mut index = (&world.entities(), &world.read_component::<Attribute>())
+            .join()
+            .map(|(e, a)| ((a.id(), a.name().to_string()), e))
+            .collect::<BTreeMap<_, _>>();
+
+        for i in 0..self.peers.len() {
+            for update in self.peers[i].receive() {
+                if self.merge(world, &mut index, &update) {
+                    self.broadcast(&update, Some(i));
+                }
+            }
+        }
+
+        for peer in self.peers.iter_mut() {
+            peer.flush();
+        }
+        self.peers.retain(|peer| peer.open);
+    }
+
+    fn add_peer(&mut self, stream: Box<dyn SyncStream>) {
+        let mut peer = Peer::new(stream);
+        for ((id, name), (stamp, value)) in self.registers.iter() {
+            peer.send(&SyncUpdate {
+                id: *id,
+                name: name.to_string(),
+                stamp: *stamp,
+                value: value.clone(),
+            });
+        }
+        self.peers.push(peer);
+    }
+
+    fn accept(&mut self) {
+        let mut accepted: Vec<Box<dyn SyncStream>> = vec![];
+        for listener in self.listeners.iter() {
+            loop {
+                let stream: std::io::Result<Box<dyn SyncStream>> = match listener {
+                    Listener::Tcp(listener) => listener.accept().and_then(|(stream, _)| {
+                        stream.set_nonblocking(true)?;
+                        stream.set_nodelay(true)?;
+                        Ok(Box::new(stream) as Box<dyn SyncStream>)
+                    }),
+                    #[cfg(unix)]
+                    Listener::Unix(listener, _) => listener.accept().and_then(|(stream, _)| {
+                        stream.set_nonblocking(true)?;
+                        Ok(Box::new(stream) as Box<dyn SyncStream>)
+                    }),
+                };
+
+                match stream {
+                    Ok(stream) => accepted.push(stream),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        eprintln!("Could not accept sync peer, {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for stream in accepted {
+            self.add_peer(stream);
+        }
+    }
+
+    /// Reads the attribute events since the last call, and stamps the committed values that differ from the registers
+    ///
+    /// On the first call every attribute in `world` is compared
+    fn local_changes(&mut self, world: &World) -> Vec<SyncUpdate> {
+        let entities = world.entities();
+        let attributes = world.read_component::<Attribute>();
+        let changed_entities = {
+            let mut events = world.write_resource::<AttributeEvents>();
+            events.update(&entities, &attributes);
+            match self.subscription {
+                Some(subscription) => events
+                    .drain(subscription)
+                    .into_iter()
+                    .map(|event| event.entity)
+                    .collect::<BTreeSet<_>>(),
+                None => {
+                    self.subscription = Some(events.subscribe(AttributeFilter::Any));
+                    (&entities, &attributes)
+                        .join()
+                        .map(|(e, _)| e.id())
+                        .collect()
+                }
+            }
+        };
+
+        let mut changed = vec![];
+        for index in changed_entities {
+            let current = attributes
+                .get(entities.entity(index))
+                .map(|a| ((a.id(), a.name().to_string()), a.value()));
+            let previous = match &current {
+                Some((key, _)) => self.keys.insert(index, key.clone()),
+                None => self.keys.remove(&index),
+            };
+
+            // the attribute was removed, renamed, or moved to another owner
+            if let Some(previous) =
+                previous.filter(|p| current.as_ref().is_none_or(|(key, _)| key != p))
+            {
+                if self
+                    .registers
+                    .get(&previous)
+                    .is_some_and(|(_, value)| value.is_some())
+                {
+                    changed.push((previous, None));
+                }
+            }
+
+            if let Some((key, value)) = current {
+                match self.registers.get(&key) {
+                    Some((_, Some(synced))) if synced == value => {}
+                    _ => changed.push((key, Some(value.clone()))),
+                }
+            }
+        }
+
+        changed
+            .into_iter()
+            .map(|((id, name), value)| {
+                self.clock += 1;
+                let stamp = Stamp {
+                    clock: self.clock,
+                    site: self.site,
+                };
+                self.registers
+                    .insert((id, name.clone()), (stamp, value.clone()));
+                SyncUpdate {
+                    id,
+                    name,
+                    stamp,
+                    value,
+                }
+            })
+            .collect()
+    }
+
+    /// applies `update` if it's newer than the register, returns true if it was applied
+    fn merge(
+        &mut self,
+        world: &World,
+        index: &mut BTreeMap<(u32, String), Entity>,
+        update: &SyncUpdate,
+    ) -> bool {
+        self.clock = self.clock.max(update.stamp.clock);
+
+        let key = (update.id, update.name.clone());
+        if self
+            .registers
+            .get(&key)
+            .is_some_and(|(stamp, _)| *stamp >= update.stamp)
+        {
+            return false;
+        }
+        self.registers
+            .insert(key.clone(), (update.stamp, update.value.clone()));
+
+        let mut attributes = world.write_component::<Attribute>();
+        match (index.get(&key).copied(), &update.value) {
+            (Some(entity), Some(value)) => {
+                if let Some(attribute) = attributes.get_mut(entity) {
+                    attribute.value = value.clone();
+                }
+            }
+            (Some(entity), None) => {
+                attributes.remove(entity);
+                index.remove(&key);
+            }
+            (None, Some(value)) => {
+                let entity = world.entities().create();
+                match attributes.insert(
+                    entity,
+                    Attribute::new(update.id, &update.name, value.clone()),
+                ) {
+                    Ok(_) => {
+                        index.insert(key, entity);
+                    }
+                    Err(err) => eprintln!("Could not add attribute {}, {}", update.name, err),
+                }
+            }
+            (None, None) => {}
+        }
+        true
+    }
+
+    fn broadcast(&mut self, update: &SyncUpdate, except: Option<usize>) {
+        for (i, peer) in self.peers.iter_mut().enumerate() {
+            if Some(i) != except {
+                peer.send(update);
+            }
+        }
+    }
+}
+
+impl Extension for AttributeSync {
+    fn configure_app_world(world: &mut World) {
+        ChangeDetection::configure_app_world(world);
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        self.sync(app_world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AttributeSync;
+    use super::Peer;
+    use super::SyncUpdate;
+    use super::MAX_PENDING_WRITE;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::Join;
+    use specs::World;
+    use specs::WorldExt;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    struct Site {
+        sync: AttributeSync,
+        world: World,
+    }
+
+    impl Site {
+        fn new() -> Self {
+            let mut world = World::new();
+            AttributeSync::configure_app_world(&mut world);
+            Self {
+                sync: AttributeSync::default(),
+                world,
+            }
+        }
+
+        fn value(&self, name: &str) -> Option<Value> {
+            self.world
+                .read_component::<Attribute>()
+                .join()
+                .find(|a| a.name() == name)
+                .map(|a| a.value().clone())
+        }
+
+        fn set(&mut self, name: &str, value: Value) {
+            let mut attributes = self.world.write_component::<Attribute>();
+            match (&mut attributes).join().find(|a| a.name() == name) {
+                Some(attribute) => attribute.value = value,
+                None => {
+                    drop(attributes);
+                    self.world
+                        .create_entity()
+                        .with(Attribute::new(0, name, value))
+                        .build();
+                }
+            }
+        }
+    }
+
+    /// syncs both sites until `done` returns true, panics if they don't converge within a few seconds
+    fn settle(a: &mut Site, b: &mut Site, done: impl Fn(&Site, &Site) -> bool) {
+        let start = Instant::now();
+        loop {
+            a.sync.sync(&a.world);
+            a.world.maintain();
+            b.sync.sync(&b.world);
+            b.world.maintain();
+            if done(a, b) {
+                return;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "sites did not converge, {:?} and {:?}",
+                a.sync.registers,
+                b.sync.registers
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn connected() -> (Site, Site) {
+        let mut a = Site::new();
+        let mut b = Site::new();
+        let addr = a
+            .sync
+            .listen("127.0.0.1:0")
+            .expect("can listen on loopback");
+        b.sync.connect(addr).expect("can connect on loopback");
+        settle(&mut a, &mut b, |a, b| {
+            a.sync.peers() == 1 && b.sync.peers() == 1
+        });
+        (a, b)
+    }
+
+    #[test]
+    fn the_last_writer_wins() {
+        let (mut a, mut b) = connected();
+
+        a.set("x", Value::Int(1));
+        settle(&mut a, &mut b, |_, b| b.value("x") == Some(Value::Int(1)));
+
+        b.set("x", Value::Int(2));
+        settle(&mut a, &mut b, |a, _| a.value("x") == Some(Value::Int(2)));
+        assert_eq!(b.value("x"), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn concurrent_writes_are_ordered_by_site() {
+        let (mut a, mut b) = connected();
+
+        a.set("x", Value::Int(1));
+        settle(&mut a, &mut b, |_, b| b.value("x") == Some(Value::Int(1)));
+        assert_eq!(a.sync.clock, b.sync.clock);
+
+        // both writes have the same lamport clock
+        a.set("x", Value::Int(10));
+        b.set("x", Value::Int(20));
+        let expected = if a.sync.site() > b.sync.site() {
+            Value::Int(10)
+        } else {
+            Value::Int(20)
+        };
+        settle(&mut a, &mut b, |a, b| {
+            a.value("x") == Some(expected.clone()) && b.value("x") == Some(expected.clone())
+        });
+    }
+
+    #[test]
+    fn peers_catch_up_after_reconnecting() {
+        let (mut a, mut b) = connected();
+        let addr = match &a.sync.listeners[..] {
+            [super::Listener::Tcp(listener)] => listener.local_addr().unwrap(),
+            _ => unreachable!("a listens on one tcp address"),
+        };
+
+        a.set("x", Value::Int(1));
+        settle(&mut a, &mut b, |_, b| b.value("x") == Some(Value::Int(1)));
+
+        // b restarts, and misses changes while it's disconnected
+        b.sync = AttributeSync::default();
+        settle(&mut a, &mut b, |a, _| a.sync.peers() == 0);
+        a.set("x", Value::Int(2));
+        a.set("y", Value::Int(3));
+        settle(&mut a, &mut b, |_, _| true);
+        assert_eq!(b.value("x"), Some(Value::Int(1)));
+
+        b.sync.connect(addr).expect("can reconnect on loopback");
+        settle(&mut a, &mut b, |_, b| {
+            b.value("x") == Some(Value::Int(2)) && b.value("y") == Some(Value::Int(3))
+        });
+        assert_eq!(a.value("x"), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn renamed_and_removed_attributes_are_synced() {
+        let (mut a, mut b) = connected();
+
+        a.set("x", Value::Int(1));
+        settle(&mut a, &mut b, |_, b| b.value("x") == Some(Value::Int(1)));
+
+        {
+            let mut attributes = a.world.write_component::<Attribute>();
+            let attribute = (&mut attributes).join().next().unwrap();
+            attribute.name = "y".to_string();
+        }
+        settle(&mut a, &mut b, |_, b| {
+            b.value("x").is_none() && b.value("y") == Some(Value::Int(1))
+        });
+
+        let entity = {
+            let entities = a.world.entities();
+            let attributes = a.world.read_component::<Attribute>();
+            (&entities, &attributes).join().next().unwrap().0
+        };
+        a.world.write_component::<Attribute>().remove(entity);
+        settle(&mut a, &mut b, |_, b| b.value("y").is_none());
+    }
+
+    #[test]
+    fn peers_that_stop_reading_are_disconnected() {
+        let mut peer = Peer::new(Box::new(std::io::Cursor::new(vec![])));
+        let update = SyncUpdate {
+            id: 0,
+            name: "x".to_string(),
+            stamp: super::Stamp { clock: 0, site: 0 },
+            value: Some(Value::TextBuffer("x".repeat(MAX_PENDING_WRITE / 16))),
+        };
+        while peer.open {
+            peer.send(&update);
+            assert!(peer.write.len() <= MAX_PENDING_WRITE);
+        }
+        assert!(peer.write.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_sockets_are_removed_when_dropped() {
+        let path = std::env::temp_dir().join(format!("atlier-sync-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // a socket file left behind by another process is replaced
+        let stale = std::os::unix::net::UnixListener::bind(&path).expect("can bind");
+        drop(stale);
+        assert!(path.exists());
+
+        let mut sync = AttributeSync::default();
+        sync.listen_unix(&path).expect("can replace a stale socket");
+        let mut other = AttributeSync::default();
+        assert!(other.listen_unix(&path).is_err());
+
+        drop(sync);
+        assert!(!path.exists());
+    }
+}
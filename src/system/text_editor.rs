@@ -0,0 +1,710 @@
+use imgui::Ui;
+use regex::NoExpand;
+use regex::Regex;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops::Range;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::CodeEditor;
+use super::Grammar;
+
+/// Largest number of groups of edits kept by the undo stack
+const MAX_UNDO: usize = 100;
+
+/// Edits that are closer together than this are undone together
+const UNDO_GROUP: Duration = Duration::from_secs(1);
+
+/// Line ending that is written when text is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl AsRef<str> for LineEnding {
+    fn as_ref(&self) -> &str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+impl Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+/// Encoding of text, byte order marks are kept when text is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl AsRef<str> for TextEncoding {
+    fn as_ref(&self) -> &str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf8Bom => "UTF-8 with BOM",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+        }
+    }
+}
+
+impl Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+const LINE_ENDINGS: [LineEnding; 2] = [LineEnding::Lf, LineEnding::CrLf];
+
+const ENCODINGS: [TextEncoding; 4] = [
+    TextEncoding::Utf8,
+    TextEncoding::Utf8Bom,
+    TextEncoding::Utf16Le,
+    TextEncoding::Utf16Be,
+];
+
+/// A change to the text, `removed` was replaced by `inserted` at the byte offset `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Edit {
+    start: usize,
+    removed: String,
+    inserted: String,
+}
+
+impl Edit {
+    /// returns the change from `before` to `after`, which only covers the bytes between their common prefix and suffix
+    fn diff(before: &str, after: &str) -> Option<Self> {
+        if before == after {
+            return None;
+        }
+
+        let mut start = before
+            .bytes()
+            .zip(after.bytes())
+            .take_while(|(b, a)| b == a)
+            .count();
+        while !before.is_char_boundary(start) {
+            start -= 1;
+        }
+
+        let max_suffix = before.len().min(after.len()) - start;
+        let mut suffix = before
+            .bytes()
+            .rev()
+            .zip(after.bytes().rev())
+            .take(max_suffix)
+            .take_while(|(b, a)| b == a)
+            .count();
+        while !before.is_char_boundary(before.len() - suffix) {
+            suffix -= 1;
+        }
+
+        Some(Self {
+            start,
+            removed: before[start..before.len() - suffix].to_string(),
+            inserted: after[start..after.len() - suffix].to_string(),
+        })
+    }
+
+    fn apply(&self, text: &mut String) {
+        text.replace_range(self.start..self.start + self.removed.len(), &self.inserted);
+    }
+
+    fn revert(&self, text: &mut String) {
+        text.replace_range(self.start..self.start + self.inserted.len(), &self.removed);
+    }
+}
+
+/// Text editor widget with find/replace and an undo stack, the text is shown with a `CodeEditor`
+///
+/// The text is decoded once, and kept with `\n` line endings, `encode` converts it back to bytes
+/// with the detected encoding and line ending
+#[derive(Debug, Clone)]
+pub struct TextEditor {
+    text: String,
+    pub line_ending: LineEnding,
+    pub encoding: TextEncoding,
+    /// true if the decoded bytes had both LF and CRLF line endings
+    pub mixed_line_endings: bool,
+    /// incremented when the text, line ending or encoding changes
+    generation: u64,
+    /// hash of the text when it was last saved, and the line ending and encoding it was saved with
+    saved: (u64, LineEnding, TextEncoding),
+    /// true if the text is different from when it was last saved
+    dirty: bool,
+    /// groups of edits, in the order they were made
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// text before the last edit made by typing, used to find what changed
+    previous: String,
+    last_edit: Option<Instant>,
+    find: String,
+    replace: String,
+    match_case: bool,
+    /// matches of the find string, with the generation, find string and case sensitivity they were found with
+    matches: Option<(u64, String, bool, Vec<Range<usize>>)>,
+    code: CodeEditor,
+}
+
+impl TextEditor {
+    /// Decodes `bytes`, returns None if they are not text
+    ///
+    /// UTF-16 is only detected with a byte order mark, text with NUL characters is treated as binary
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (encoding, text) = if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+            (
+                TextEncoding::Utf8Bom,
+                String::from_utf8(rest.to_vec()).ok()?,
+            )
+        } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+            (
+                TextEncoding::Utf16Le,
+                decode_utf16(rest, u16::from_le_bytes)?,
+            )
+        } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+            (
+                TextEncoding::Utf16Be,
+                decode_utf16(rest, u16::from_be_bytes)?,
+            )
+        } else {
+            (TextEncoding::Utf8, String::from_utf8(bytes.to_vec()).ok()?)
+        };
+
+        if text.contains('\0') {
+            return None;
+        }
+
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        let text = if crlf > 0 {
+            text.replace("\r\n", "\n")
+        } else {
+            text
+        };
+
+        let mut editor = Self {
+            previous: text.clone(),
+            text,
+            line_ending: if crlf > lf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            },
+            encoding,
+            mixed_line_endings: crlf > 0 && lf > 0,
+            generation: 0,
+            saved: (0, LineEnding::Lf, encoding),
+            dirty: false,
+            undo: vec![],
+            redo: vec![],
+            last_edit: None,
+            find: String::new(),
+            replace: String::new(),
+            match_case: false,
+            matches: None,
+            code: CodeEditor::default(),
+        };
+        editor.mark_saved();
+        Some(editor)
+    }
+
+    /// returns the text with the line ending and encoding of this editor
+    pub fn encode(&self) -> Vec<u8> {
+        let text = match self.line_ending {
+            LineEnding::Lf => self.text.clone(),
+            LineEnding::CrLf => self.text.replace('\n', "\r\n"),
+        };
+
+        match self.encoding {
+            TextEncoding::Utf8 => text.into_bytes(),
+            TextEncoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(),
+            TextEncoding::Utf16Le => [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+            TextEncoding::Utf16Be => [0xFE, 0xFF]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect(),
+        }
+    }
+
+    /// highlights the text with `grammar`
+    pub fn with_grammar(mut self, grammar: Option<Grammar>) -> Self {
+        self.code.set_grammar(grammar);
+        self
+    }
+
+    /// the code editor that shows the text
+    pub fn code_mut(&mut self) -> &mut CodeEditor {
+        &mut self.code
+    }
+
+    /// the text, with `\n` line endings
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// replaces the text, the previous text can be restored with `undo`
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if let Some(edit) = Edit::diff(&self.text, &text) {
+            self.text = text;
+            self.push_undo(vec![edit]);
+            self.redo.clear();
+            self.changed();
+        }
+    }
+
+    /// returns true if the encoded text is different from when it was last saved
+    pub fn is_dirty(&self) -> bool {
+        let (_, line_ending, encoding) = self.saved;
+        self.dirty || line_ending != self.line_ending || encoding != self.encoding
+    }
+
+    /// marks the current text as saved
+    pub fn mark_saved(&mut self) {
+        self.saved = (self.hash(), self.line_ending, self.encoding);
+        self.dirty = false;
+    }
+
+    /// incremented every time the text, line ending or encoding is changed by this editor
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// number of lines
+    pub fn lines(&self) -> usize {
+        self.text.matches('\n').count() + 1
+    }
+
+    /// restores the text from before the last group of edits, returns false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(edits) => {
+                for edit in edits.iter().rev() {
+                    edit.revert(&mut self.text);
+                }
+                self.redo.push(edits);
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// restores the text that was last undone, returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(edits) => {
+                for edit in edits.iter() {
+                    edit.apply(&mut self.text);
+                }
+                self.push_undo(edits);
+                self.changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// byte ranges of the text that match the find string
+    pub fn find_all(&self) -> Vec<Range<usize>> {
+        match self.find_regex() {
+            Some(regex) => regex.find_iter(&self.text).map(|m| m.range()).collect(),
+            None => vec![],
+        }
+    }
+
+    /// replaces every match of the find string, returns the number of replacements
+    pub fn replace_all(&mut self) -> usize {
+        let regex = match self.find_regex() {
+            Some(regex) => regex,
+            None => return 0,
+        };
+
+        let count = regex.find_iter(&self.text).count();
+        if count > 0 {
+            let replaced = regex
+                .replace_all(&self.text, NoExpand(&self.replace))
+                .to_string();
+            self.set_text(replaced);
+        }
+        count
+    }
+
+    fn find_regex(&self) -> Option<Regex> {
+        if self.find.is_empty() {
+            return None;
+        }
+
+        let flags = if self.match_case { "" } else { "(?i)" };
+        Regex::new(&format!("{}{}", flags, regex::escape(&self.find))).ok()
+    }
+
+    /// `find_all`, cached until the text or the find options change
+    fn matches(&mut self) -> &[Range<usize>] {
+        let cached = self
+            .matches
+            .as_ref()
+            .is_some_and(|(generation, find, match_case, _)| {
+                *generation == self.generation
+                    && *find == self.find
+                    && *match_case == self.match_case
+            });
+        if !cached {
+            self.matches = Some((
+                self.generation,
+                self.find.clone(),
+                self.match_case,
+                self.find_all(),
+            ));
+        }
+
+        self.matches
+            .as_ref()
+            .map(|(.., matches)| matches.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn find_next(&mut self, from: usize) -> Option<Range<usize>> {
+        let matches = self.matches();
+        matches
+            .iter()
+            .find(|m| m.start >= from)
+            .or_else(|| matches.first())
+            .cloned()
+    }
+
+    fn push_undo(&mut self, edits: Vec<Edit>) {
+        self.undo.push(edits);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.text.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// called after the text was changed
+    fn touched(&mut self) {
+        self.generation += 1;
+        self.dirty = self.hash() != self.saved.0;
+    }
+
+    /// called after the text was changed by anything other than typing
+    fn changed(&mut self) {
+        self.previous.clone_from(&self.text);
+        self.last_edit = None;
+        self.code.text_changed(&self.text);
+        self.touched();
+    }
+
+    /// Shows the editor, `id` must be unique in the current window
+    ///
+    /// Returns true if the text was changed
+    pub fn ui(&mut self, id: impl AsRef<str>, ui: &Ui) -> bool {
+        let _id = ui.push_id(id.as_ref());
+        let mut changed = false;
+
+        ui.disabled(self.undo.is_empty(), || {
+            if ui.button("undo") {
+                changed |= self.undo();
+            }
+        });
+        ui.same_line();
+        ui.disabled(self.redo.is_empty(), || {
+            if ui.button("redo") {
+                changed |= self.redo();
+            }
+        });
+
+        ui.same_line();
+        ui.set_next_item_width(150.0);
+        ui.input_text("##find", &mut self.find).hint("find").build();
+        ui.same_line();
+        ui.set_next_item_width(150.0);
+        ui.input_text("##replace", &mut self.replace)
+            .hint("replace")
+            .build();
+        ui.same_line();
+        ui.checkbox("match case", &mut self.match_case);
+
+        let selection = self.code.selection();
+        let (count, selected) = {
+            let matches = self.matches();
+            (matches.len(), matches.contains(&selection))
+        };
+        ui.same_line();
+        ui.disabled(count == 0, || {
+            let next = if ui.button("next") {
+                self.find_next(selection.end.max(self.code.cursor()))
+            } else {
+                None
+            };
+            ui.same_line();
+            let next = if ui.button("replace") {
+                if selected {
+                    let mut text = self.text.clone();
+                    text.replace_range(selection.clone(), &self.replace);
+                    self.set_text(text);
+                    changed = true;
+                    self.find_next(selection.start + self.replace.len())
+                } else {
+                    self.find_next(self.code.cursor())
+                }
+            } else {
+                next
+            };
+            if let Some(next) = next {
+                self.code.select(next, &self.text);
+            }
+            ui.same_line();
+            if ui.button("replace all") {
+                changed |= self.replace_all() > 0;
+            }
+        });
+        if !self.find.is_empty() {
+            ui.same_line();
+            ui.text_disabled(format!("{} matches", count));
+        }
+
+        let mut line_ending = LINE_ENDINGS
+            .iter()
+            .position(|l| *l == self.line_ending)
+            .unwrap_or_default();
+        ui.set_next_item_width(80.0);
+        if ui.combo_simple_string("##line ending", &mut line_ending, &LINE_ENDINGS) {
+            self.line_ending = LINE_ENDINGS[line_ending];
+            self.generation += 1;
+            changed = true;
+        }
+        ui.same_line();
+        let mut encoding = ENCODINGS
+            .iter()
+            .position(|e| *e == self.encoding)
+            .unwrap_or_default();
+        ui.set_next_item_width(150.0);
+        if ui.combo_simple_string("##encoding", &mut encoding, &ENCODINGS) {
+            self.encoding = ENCODINGS[encoding];
+            self.generation += 1;
+            changed = true;
+        }
+        ui.same_line();
+        let (line, column) = self.code.cursor_position(&self.text);
+        ui.text_disabled(format!(
+            "{} lines, Ln {}, Col {}{}",
+            self.lines(),
+            line,
+            column,
+            if self.mixed_line_endings {
+                ", mixed line endings"
+            } else {
+                ""
+            }
+        ));
+        if self.is_dirty() {
+            ui.same_line();
+            ui.text_colored([1.0, 0.7, 0.2, 1.0], "modified");
+        }
+
+        let typed = self
+            .code
+            .ui("code", &mut self.text, ui)
+            .then(|| Edit::diff(&self.previous, &self.text))
+            .flatten();
+        if let Some(edit) = typed {
+            // only the changed part of the previous text is updated
+            edit.apply(&mut self.previous);
+
+            // start a new undo group when typing resumes after a pause
+            match self.undo.last_mut() {
+                Some(group)
+                    if self
+                        .last_edit
+                        .is_some_and(|last| last.elapsed() <= UNDO_GROUP) =>
+                {
+                    group.push(edit)
+                }
+                _ => {
+                    self.push_undo(vec![edit]);
+                    self.redo.clear();
+                }
+            }
+            self.last_edit = Some(Instant::now());
+            self.touched();
+            changed = true;
+        }
+
+        changed
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&units).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Edit;
+    use super::LineEnding;
+    use super::TextEditor;
+    use super::TextEncoding;
+
+    #[test]
+    fn edits_only_cover_the_changed_text() {
+        let edit = Edit::diff("let a = 1;\nlet b = 2;\n", "let a = 1;\nlet c = 2;\n").unwrap();
+        assert_eq!(
+            edit,
+            Edit {
+                start: 15,
+                removed: "b".to_string(),
+                inserted: "c".to_string(),
+            }
+        );
+        assert_eq!(Edit::diff("same", "same"), None);
+
+        // common bytes inside a character are not split
+        let edit = Edit::diff("aé", "aè").unwrap();
+        assert_eq!((edit.removed.as_str(), edit.inserted.as_str()), ("é", "è"));
+
+        let mut text = "aaa".to_string();
+        let edit = Edit::diff(&text, "aaaa").unwrap();
+        edit.apply(&mut text);
+        assert_eq!(text, "aaaa");
+        edit.revert(&mut text);
+        assert_eq!(text, "aaa");
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_text() {
+        let mut editor = TextEditor::decode(b"one\ntwo\n").unwrap();
+        editor.set_text("one\n2\n");
+        editor.set_text("1\n2\n");
+
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "one\n2\n");
+        assert!(editor.undo());
+        assert_eq!(editor.text(), "one\ntwo\n");
+        assert!(!editor.undo());
+
+        assert!(editor.redo());
+        assert!(editor.redo());
+        assert_eq!(editor.text(), "1\n2\n");
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn the_dirty_flag_follows_the_saved_state() {
+        let mut editor = TextEditor::decode(b"text").unwrap();
+        let generation = editor.generation();
+        assert!(!editor.is_dirty());
+
+        editor.set_text("changed");
+        assert!(editor.is_dirty());
+        assert_ne!(editor.generation(), generation);
+
+        editor.undo();
+        assert!(
+            !editor.is_dirty(),
+            "undoing back to the saved text is clean"
+        );
+
+        editor.line_ending = LineEnding::CrLf;
+        assert!(editor.is_dirty());
+        editor.mark_saved();
+        assert!(!editor.is_dirty());
+        assert_eq!(editor.encode(), b"text");
+    }
+
+    #[test]
+    fn line_endings_and_encodings_round_trip() {
+        let text = "héllo\nwörld\n";
+        let utf16 = |to_bytes: fn(u16) -> [u8; 2], bom: [u8; 2], text: &str| {
+            bom.into_iter()
+                .chain(text.encode_utf16().flat_map(to_bytes))
+                .collect::<Vec<_>>()
+        };
+
+        let cases = [
+            (text.as_bytes().to_vec(), LineEnding::Lf, TextEncoding::Utf8),
+            (
+                text.replace('\n', "\r\n").into_bytes(),
+                LineEnding::CrLf,
+                TextEncoding::Utf8,
+            ),
+            (
+                [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(),
+                LineEnding::Lf,
+                TextEncoding::Utf8Bom,
+            ),
+            (
+                utf16(u16::to_le_bytes, [0xFF, 0xFE], &text.replace('\n', "\r\n")),
+                LineEnding::CrLf,
+                TextEncoding::Utf16Le,
+            ),
+            (
+                utf16(u16::to_be_bytes, [0xFE, 0xFF], text),
+                LineEnding::Lf,
+                TextEncoding::Utf16Be,
+            ),
+        ];
+
+        for (bytes, line_ending, encoding) in cases {
+            let editor = TextEditor::decode(&bytes).expect("bytes are text");
+            assert_eq!(editor.text(), text);
+            assert_eq!(editor.line_ending, line_ending);
+            assert_eq!(editor.encoding, encoding);
+            assert!(!editor.mixed_line_endings);
+            assert_eq!(editor.encode(), bytes);
+        }
+
+        let mixed = TextEditor::decode(b"a\r\nb\r\nc\n").unwrap();
+        assert!(mixed.mixed_line_endings);
+        assert_eq!(mixed.line_ending, LineEnding::CrLf);
+        assert_eq!(mixed.text(), "a\nb\nc\n");
+
+        assert!(TextEditor::decode(&[0xFF, 0xFE, 0x61]).is_none());
+        assert!(TextEditor::decode(b"a\0b").is_none());
+    }
+
+    #[test]
+    fn matches_are_found_again_when_the_text_or_find_string_changes() {
+        let mut editor = TextEditor::decode(b"a A a").unwrap();
+        editor.find = "a".to_string();
+        assert_eq!(editor.matches(), [0..1, 2..3, 4..5]);
+
+        editor.match_case = true;
+        assert_eq!(editor.matches(), [0..1, 4..5]);
+
+        editor.set_text("a b");
+        assert_eq!(editor.matches().to_vec(), vec![(0..1)]);
+
+        editor.find = "b".to_string();
+        assert_eq!(editor.matches().to_vec(), vec![(2..3)]);
+        assert_eq!(editor.find_next(3), Some(2..3));
+    }
+}
@@ -0,0 +1,468 @@
+use imgui::Ui;
+use specs::Entity;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::Extension;
+use super::Patch;
+use super::Subscription;
+
+/// The stable state of every attribute at some point in time
+///
+/// Attributes that didn't change since the previous snapshot are shared with it, so keeping many snapshots is cheap
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// frame this snapshot was captured on
+    pub frame: u64,
+    /// why this snapshot was captured
+    pub label: String,
+    attributes: Vec<(Entity, Arc<Attribute>)>,
+}
+
+impl Snapshot {
+    /// entities and their attributes, ordered by entity
+    pub fn attributes(&self) -> impl Iterator<Item = (Entity, &Attribute)> {
+        self.attributes.iter().map(|(e, a)| (*e, a.as_ref()))
+    }
+
+    /// returns a copy of the attributes, for use with `Patch::diff`
+    pub fn to_vec(&self) -> Vec<Attribute> {
+        self.attributes
+            .iter()
+            .map(|(_, a)| a.as_ref().clone())
+            .collect()
+    }
+
+    /// Restores the attributes in `world` to this snapshot
+    ///
+    /// Attributes on entities that aren't part of this snapshot are removed, pending changes are discarded.
+    /// Entities that were deleted after this snapshot was captured can't be restored
+    pub fn restore(&self, world: &World) {
+        let entities = world.entities();
+        let mut attributes = world.write_component::<Attribute>();
+
+        let removed = (&entities, &attributes)
+            .join()
+            .map(|(e, _)| e)
+            .filter(|e| self.find(*e).is_none())
+            .collect::<Vec<_>>();
+        for entity in removed {
+            attributes.remove(entity);
+        }
+
+        for (entity, attribute) in self.attributes.iter() {
+            if let Err(err) = attributes.insert(*entity, attribute.as_ref().clone()) {
+                eprintln!(
+                    "Could not restore attribute {} on entity {}, {}",
+                    attribute.name(),
+                    entity.id(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn find(&self, entity: Entity) -> Option<&Arc<Attribute>> {
+        self.attributes
+            .binary_search_by_key(&entity, |(e, _)| *e)
+            .ok()
+            .map(|i| &self.attributes[i].1)
+    }
+
+    /// returns true if the stable state of `attribute` on the entity with `id` is the same as in this snapshot
+    fn contains(&self, id: u32, attribute: Option<&Attribute>) -> bool {
+        let captured = self
+            .attributes
+            .binary_search_by_key(&id, |(e, _)| e.id())
+            .ok()
+            .map(|i| self.attributes[i].1.as_ref());
+
+        match (captured, attribute) {
+            (Some(captured), Some(attribute)) => {
+                captured.id() == attribute.id()
+                    && captured.name() == attribute.name()
+                    && captured.value() == attribute.value()
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Resource with a ring buffer of the most recent snapshots, oldest first
+#[derive(Debug)]
+pub struct Snapshots {
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self::with_capacity(256)
+    }
+}
+
+impl Snapshots {
+    /// returns an empty ring buffer that keeps at most `capacity` snapshots
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// captures the stable state of every attribute in `world`, dropping the oldest snapshot if the buffer is full
+    pub fn capture(&mut self, world: &World, frame: u64, label: impl AsRef<str>) -> &Snapshot {
+        let previous = self.snapshots.back();
+        let attributes = (&world.entities(), &world.read_component::<Attribute>())
+            .join()
+            .map(|(entity, attribute)| {
+                let shared = previous.and_then(|p| p.find(entity)).filter(|p| {
+                    p.id() == attribute.id()
+                        && p.name() == attribute.name()
+                        && p.value() == attribute.value()
+                });
+
+                let attribute = match shared {
+                    Some(shared) => shared.clone(),
+                    None => Arc::new(Attribute::new(
+                        attribute.id(),
+                        attribute.name(),
+                        attribute.value().clone(),
+                    )),
+                };
+                (entity, attribute)
+            })
+            .collect();
+
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            frame,
+            label: label.as_ref().to_string(),
+            attributes,
+        });
+        self.snapshots.back().expect("snapshot was just pushed")
+    }
+
+    /// returns the snapshot at `index`, 0 is the oldest
+    pub fn get(&self, index: usize) -> Option<&Snapshot> {
+        self.snapshots.get(index)
+    }
+
+    /// snapshots, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Snapshot> {
+        self.snapshots.iter()
+    }
+
+    /// number of snapshots
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// returns true if there are no snapshots
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// largest number of snapshots that are kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// removes every snapshot
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+/// Extension that captures snapshots of the app world's attributes, and shows a timeline panel
+/// to scrub through them and restore the world to an earlier snapshot
+pub struct Timeline {
+    /// when true, the timeline window is shown
+    pub opened: bool,
+    /// when true, snapshots are captured
+    pub recording: bool,
+    /// capture a snapshot every N frames
+    pub every: Option<u64>,
+    /// capture a snapshot when the committed state of any attribute changes
+    pub on_commit: bool,
+    frame: u64,
+    subscription: Option<Subscription>,
+    selected: usize,
+    follow: bool,
+    /// diff between the selected snapshot and the one before it, with the index and frame of the selected snapshot
+    diff: Option<(usize, u64, Patch)>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            opened: false,
+            recording: true,
+            every: None,
+            on_commit: true,
+            frame: 0,
+            subscription: None,
+            selected: 0,
+            follow: true,
+            diff: None,
+        }
+    }
+}
+
+impl Timeline {
+    /// reads the attribute events since the last frame, returns true if the committed state of any attribute
+    /// is different from the latest snapshot
+    fn committed(&mut self, app_world: &World) -> bool {
+        let entities = app_world.entities();
+        let attributes = app_world.read_storage::<Attribute>();
+        let mut events = app_world.write_resource::<AttributeEvents>();
+        events.update(&entities, &attributes);
+
+        let events = match self.subscription {
+            Some(subscription) => events.drain(subscription),
+            None => {
+                self.subscription = Some(events.subscribe(AttributeFilter::Any));
+                return false;
+            }
+        };
+
+        match app_world.read_resource::<Snapshots>().snapshots.back() {
+            Some(latest) => events.iter().any(|event| {
+                !latest.contains(event.entity, attributes.get(entities.entity(event.entity)))
+            }),
+            None => !events.is_empty(),
+        }
+    }
+
+    fn capture(&mut self, app_world: &World, committed: bool) {
+        let periodic = self
+            .every
+            .is_some_and(|every| every > 0 && self.frame.is_multiple_of(every));
+
+        let label = if app_world.read_resource::<Snapshots>().is_empty() {
+            "start"
+        } else if committed && self.on_commit {
+            "commit"
+        } else if periodic {
+            "frame"
+        } else {
+            return;
+        };
+
+        app_world
+            .write_resource::<Snapshots>()
+            .capture(app_world, self.frame, label);
+    }
+
+    fn timeline_ui(&mut self, app_world: &World, ui: &Ui) {
+        let snapshots = app_world.read_resource::<Snapshots>();
+
+        ui.checkbox("recording", &mut self.recording);
+        ui.same_line();
+        ui.text(format!(
+            "{}/{} snapshots",
+            snapshots.len(),
+            snapshots.capacity()
+        ));
+
+        if snapshots.is_empty() {
+            ui.text_disabled("no snapshots");
+            return;
+        }
+
+        let last = snapshots.len() - 1;
+        if self.follow || self.selected > last {
+            self.selected = last;
+        }
+
+        let mut selected = self.selected as u32;
+        if imgui::Slider::new("snapshot", 0, last as u32).build(ui, &mut selected) {
+            self.selected = selected as usize;
+        }
+        self.follow = self.selected == last;
+
+        let snapshot = match snapshots.get(self.selected) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        ui.text(format!("frame {} ({})", snapshot.frame, snapshot.label));
+        ui.same_line();
+        let restore = ui.button("restore");
+        ui.separator();
+
+        if ui.collapsing_header("changes", imgui::TreeNodeFlags::DEFAULT_OPEN) {
+            match self.selected.checked_sub(1).and_then(|i| snapshots.get(i)) {
+                Some(previous) => {
+                    let cached = self.diff.as_ref().is_some_and(|(selected, frame, _)| {
+                        *selected == self.selected && *frame == snapshot.frame
+                    });
+                    if !cached {
+                        let diff = Patch::diff(&previous.to_vec(), &snapshot.to_vec());
+                        self.diff = Some((self.selected, snapshot.frame, diff));
+                    }
+                    if let Some((_, _, diff)) = self.diff.as_ref() {
+                        diff.side_by_side_ui(ui);
+                    }
+                }
+                None => ui.text_disabled("oldest snapshot"),
+            }
+        }
+
+        if ui.collapsing_header("attributes", imgui::TreeNodeFlags::empty()) {
+            for (entity, attribute) in snapshot.attributes() {
+                ui.text(format!(
+                    "{} {} {:#4x} = {:?}",
+                    entity.id(),
+                    attribute.name(),
+                    attribute.id(),
+                    attribute.value()
+                ));
+            }
+        }
+
+        if restore {
+            let snapshot = snapshot.clone();
+            drop(snapshots);
+            snapshot.restore(app_world);
+        }
+    }
+}
+
+impl Extension for Timeline {
+    fn configure_app_world(world: &mut World) {
+        ChangeDetection::configure_app_world(world);
+        world.insert(Snapshots::default());
+    }
+
+    fn on_ui(&'_ mut self, app_world: &World, ui: &'_ imgui::Ui<'_>) {
+        // events are read even when not recording, so that they don't pile up
+        let committed = self.committed(app_world);
+        if self.recording {
+            self.capture(app_world, committed);
+        }
+        self.frame += 1;
+
+        if !self.opened {
+            return;
+        }
+
+        let mut opened = self.opened;
+        imgui::Window::new("Timeline")
+            .size([600.0, 600.0], imgui::Condition::FirstUseEver)
+            .opened(&mut opened)
+            .build(ui, || self.timeline_ui(app_world, ui));
+        self.opened = opened;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+
+    use super::Snapshots;
+    use super::Timeline;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::Value;
+
+    #[test]
+    fn full_buffers_drop_the_oldest_snapshot() {
+        let mut world = World::new();
+        world.register::<Attribute>();
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(0)))
+            .build();
+
+        let mut snapshots = Snapshots::with_capacity(3);
+        for frame in 0..5 {
+            world
+                .write_storage::<Attribute>()
+                .get_mut(entity)
+                .unwrap()
+                .set(Value::Int(frame as i32));
+            snapshots.capture(&world, frame, "frame");
+        }
+
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(
+            snapshots.iter().map(|s| s.frame).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        let (_, oldest) = snapshots.get(0).unwrap().attributes().next().unwrap();
+        assert_eq!(oldest.value(), &Value::Int(2));
+    }
+
+    #[test]
+    fn snapshots_are_captured_when_attributes_are_committed() {
+        let mut world = World::new();
+        Timeline::configure_app_world(&mut world);
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(0, "a", Value::Int(0)))
+            .build();
+
+        let mut timeline = Timeline::default();
+        let mut step = |world: &mut World| {
+            world.maintain();
+            let committed = timeline.committed(world);
+            timeline.capture(world, committed);
+            timeline.frame += 1;
+        };
+        let labels = |world: &World| {
+            world
+                .read_resource::<Snapshots>()
+                .iter()
+                .map(|s| s.label.clone())
+                .collect::<Vec<_>>()
+        };
+
+        step(&mut world);
+        step(&mut world);
+        assert_eq!(labels(&world), ["start"]);
+
+        // pending changes aren't captured
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .edit_as(Value::Int(1));
+        step(&mut world);
+        assert_eq!(labels(&world), ["start"]);
+
+        world
+            .write_storage::<Attribute>()
+            .get_mut(entity)
+            .unwrap()
+            .commit_unchecked();
+        step(&mut world);
+        step(&mut world);
+        assert_eq!(labels(&world), ["start", "commit"]);
+
+        world.write_storage::<Attribute>().remove(entity);
+        step(&mut world);
+        assert_eq!(labels(&world), ["start", "commit", "commit"]);
+        assert_eq!(
+            world
+                .read_resource::<Snapshots>()
+                .get(2)
+                .unwrap()
+                .attributes()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,595 @@
+use imgui::Ui;
+use notify::EventKind;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
+use specs::storage::NullStorage;
+use specs::Component;
+use specs::Join;
+use specs::World;
+use specs::WorldExt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::register_scheme;
+use super::scheme::file_edit_ui;
+use super::scheme::to_bytes;
+use super::Attribute;
+use super::AttributeEvents;
+use super::AttributeFilter;
+use super::ChangeDetection;
+use super::ConflictDetection;
+use super::Extension;
+use super::FileScheme;
+use super::MergeBases;
+use super::SchemeError;
+use super::SchemeHandler;
+use super::Subscription;
+use super::Value;
+use super::ValueKind;
+
+/// Marks a `file::` attribute whose file was changed on disk while the attribute had pending changes,
+/// or that didn't match its file when the file was first watched
+///
+/// The new content of the file is the stable value of the attribute, so the pending changes conflict with it,
+/// see `MergeBases::merge_ui`. The marker is removed once the conflict is resolved and the stable value
+/// matches the file, e.g. after loading or overwriting the file
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct ChangedOnDisk;
+
+/// Hashes of the content of a watched file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileState {
+    /// content the attributes were last loaded from, or saved with
+    pub loaded: Option<u64>,
+    /// content on disk, None if the file doesn't exist
+    pub disk: Option<u64>,
+}
+
+impl FileState {
+    /// returns true if the file was changed on disk after it was last loaded or saved
+    pub fn changed_on_disk(&self) -> bool {
+        self.loaded != self.disk
+    }
+}
+
+/// Resource with the state of each file watched by `FileWatcher`, by the path in the attribute name
+///
+/// This is shared with the `file::` handler that `FileWatcher` registers
+#[derive(Debug, Clone, Default)]
+pub struct WatchedFiles(Arc<Mutex<BTreeMap<String, FileState>>>);
+
+impl WatchedFiles {
+    /// returns the state of the file at `path`, if it is watched
+    pub fn get(&self, path: &str) -> Option<FileState> {
+        self.lock().get(path).copied()
+    }
+
+    /// paths that are watched, in order
+    pub fn paths(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn update(&self, path: &str, f: impl FnOnce(&mut FileState)) {
+        f(self.lock().entry(path.to_string()).or_default());
+    }
+
+    fn remove(&self, path: &str) {
+        self.lock().remove(path);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, FileState>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn read_hash(path: &str) -> Result<Option<(Vec<u8>, u64)>, std::io::Error> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            let hash = hash_bytes(&bytes);
+            Ok(Some((bytes, hash)))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// `file::` handler that refuses to write a file that was changed on disk since it was loaded
+struct WatchedFileScheme {
+    files: WatchedFiles,
+}
+
+impl WatchedFileScheme {
+    fn overwrite(&self, path: &str, value: &Value) -> Result<(), SchemeError> {
+        let bytes = to_bytes(self.scheme(), value)?;
+        fs::write(path, &bytes)?;
+
+        let hash = hash_bytes(&bytes);
+        self.files.update(path, |state| {
+            state.loaded = Some(hash);
+            state.disk = Some(hash);
+        });
+        Ok(())
+    }
+}
+
+impl SchemeHandler for WatchedFileScheme {
+    fn scheme(&self) -> &str {
+        FileScheme.scheme()
+    }
+
+    fn load(&self, path: &str) -> Result<Value, SchemeError> {
+        let bytes = fs::read(path)?;
+        let hash = hash_bytes(&bytes);
+        self.files.update(path, |state| {
+            state.loaded = Some(hash);
+            state.disk = Some(hash);
+        });
+        Ok(Value::BinaryVector(bytes))
+    }
+
+    fn save(&self, path: &str, value: &Value) -> Result<(), SchemeError> {
+        if let Some(state) = self.files.get(path) {
+            let disk = read_hash(path)?.map(|(_, hash)| hash);
+            if state.loaded != disk {
+                return Err(SchemeError::ChangedOnDisk(path.to_string()));
+            }
+        }
+
+        self.overwrite(path, value)
+    }
+
+    fn edit_ui(&self, path: &str, label: &str, value: &mut Value, ui: &Ui) {
+        if self.files.get(path).is_some_and(|s| s.changed_on_disk()) {
+            ui.text_colored(
+                [1.0, 0.7, 0.2, 1.0],
+                format!("{} was changed on disk since it was loaded", path),
+            );
+            if ui.button(format!("overwrite {}", label)) {
+                if let Err(err) = self.overwrite(path, value) {
+                    eprintln!("Could not write file '{}', {}", path, err);
+                }
+            }
+        }
+
+        file_edit_ui(self, path, label, value, ui);
+    }
+}
+
+/// Extension that watches the files of every `file::` attribute in the app world
+///
+/// When a file changes on disk its content becomes the stable value of the attributes bound to it.
+/// Attributes with pending changes are marked with `ChangedOnDisk`, and can be merged with `MergeBases::merge_ui`.
+/// Attributes that don't match their file when it's first watched are also marked, and keep their value.
+///
+/// This registers a `file::` handler, see `register_scheme`, that refuses to write a file that was changed on disk
+/// since it was loaded, and shows a button to overwrite it instead
+#[derive(Default)]
+pub struct FileWatcher {
+    watcher: Option<RecommendedWatcher>,
+    events: Option<Receiver<notify::Result<notify::Event>>>,
+    failed: bool,
+    subscription: Option<Subscription>,
+    /// paths in attribute names, by absolute path
+    paths: BTreeMap<PathBuf, BTreeSet<String>>,
+    /// watched directories, and the number of watched files in each
+    dirs: BTreeMap<PathBuf, usize>,
+}
+
+impl FileWatcher {
+    fn start(&mut self) {
+        let (sender, receiver) = channel();
+        match notify::recommended_watcher(sender) {
+            Ok(watcher) => {
+                self.watcher = Some(watcher);
+                self.events = Some(receiver);
+            }
+            Err(err) => {
+                eprintln!("Could not start watching files, {}", err);
+                self.failed = true;
+            }
+        }
+    }
+
+    /// watches the files of the `file::` attributes in `world`, and stops watching files that are no longer used
+    fn track(&mut self, world: &World, files: &WatchedFiles) -> BTreeSet<String> {
+        let scheme = FileScheme.scheme();
+        let wanted = world
+            .read_component::<Attribute>()
+            .join()
+            .filter_map(|a| a.name().strip_prefix(scheme))
+            .filter(|path| !path.is_empty())
+            .map(|path| path.to_string())
+            .collect::<BTreeSet<_>>();
+
+        let current = files.paths().into_iter().collect::<BTreeSet<_>>();
+        for path in current.difference(&wanted) {
+            files.remove(path);
+            if let Ok(absolute) = std::path::absolute(path) {
+                self.unwatch(&absolute, path);
+            }
+        }
+
+        let added = wanted
+            .difference(&current)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for path in added.iter() {
+            files.update(path, |_| {});
+            match std::path::absolute(path) {
+                Ok(absolute) => self.watch(absolute, path),
+                Err(err) => eprintln!("Could not watch file '{}', {}", path, err),
+            }
+        }
+        added
+    }
+
+    fn watch(&mut self, absolute: PathBuf, path: &str) {
+        // the parent directory is watched, so that files replaced by a rename are still seen
+        if let Some(dir) = absolute.parent().map(Path::to_path_buf) {
+            let count = self.dirs.entry(dir.clone()).or_default();
+            if *count == 0 {
+                if let Some(watcher) = self.watcher.as_mut() {
+                    if let Err(err) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                        eprintln!("Could not watch directory '{}', {}", dir.display(), err);
+                    }
+                }
+            }
+            *count += 1;
+        }
+
+        self.paths
+            .entry(absolute)
+            .or_default()
+            .insert(path.to_string());
+    }
+
+    fn unwatch(&mut self, absolute: &Path, path: &str) {
+        if let Some(paths) = self.paths.get_mut(absolute) {
+            paths.remove(path);
+            if paths.is_empty() {
+                self.paths.remove(absolute);
+            }
+        }
+
+        if let Some(dir) = absolute.parent() {
+            if let Some(count) = self.dirs.get_mut(dir) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    self.dirs.remove(dir);
+                    if let Some(watcher) = self.watcher.as_mut() {
+                        // the directory may have been removed already
+                        watcher.unwatch(dir).ok();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the file at `path`, and updates the attributes bound to it if the content changed
+    ///
+    /// When the file is first watched, attributes that don't match the file are marked with `ChangedOnDisk`
+    /// instead of being updated, so that their value isn't lost
+    fn reload(&self, world: &World, files: &WatchedFiles, path: &str, first: bool) {
+        let disk = match read_hash(path) {
+            Ok(disk) => disk,
+            Err(err) => {
+                eprintln!("Could not read watched file '{}', {}", path, err);
+                return;
+            }
+        };
+        files.update(path, |state| state.disk = disk.as_ref().map(|(_, h)| *h));
+
+        let (bytes, hash) = match disk {
+            Some(disk) => disk,
+            None => return,
+        };
+
+        let name = format!("{}{}", FileScheme.scheme(), path);
+        let entities = world.entities();
+        let mut attributes = world.write_component::<Attribute>();
+        let mut changed = world.write_component::<ChangedOnDisk>();
+        let bases = world.read_resource::<MergeBases>();
+
+        let bound = (&entities, &attributes)
+            .join()
+            .filter(|(_, a)| a.name() == name)
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+        for entity in bound {
+            let attribute = match attributes.get_mut(entity) {
+                Some(attribute) => attribute,
+                None => continue,
+            };
+
+            let current = to_bytes(FileScheme.scheme(), attribute.value()).ok();
+            if current.as_deref() != Some(&bytes[..]) {
+                if first {
+                    if let Err(err) = changed.insert(entity, ChangedOnDisk) {
+                        eprintln!("Could not mark attribute {}, {}", attribute.name(), err);
+                    }
+                    continue;
+                }
+
+                let loaded = Value::BinaryVector(bytes.clone());
+                attribute.value = match attribute.value().kind() {
+                    ValueKind::TextBuffer => loaded.coerce(ValueKind::TextBuffer).unwrap_or(loaded),
+                    _ => loaded,
+                };
+            }
+
+            if !bases.has_conflict(entity, attribute) {
+                files.update(path, |state| state.loaded = Some(hash));
+            } else if let Err(err) = changed.insert(entity, ChangedOnDisk) {
+                eprintln!("Could not mark attribute {}, {}", attribute.name(), err);
+            }
+        }
+    }
+
+    /// removes `ChangedOnDisk` from attributes that no longer have a conflict, and match the file on disk
+    fn resolve(&self, world: &World, files: &WatchedFiles) {
+        let entities = world.entities();
+        let attributes = world.read_component::<Attribute>();
+        let mut changed = world.write_component::<ChangedOnDisk>();
+        let bases = world.read_resource::<MergeBases>();
+
+        let resolved = (&entities, &attributes, &changed)
+            .join()
+            .filter(|(e, a, _)| !bases.has_conflict(*e, a))
+            .filter(|(_, a, _)| {
+                let path = a
+                    .name()
+                    .strip_prefix(FileScheme.scheme())
+                    .unwrap_or_default();
+                let stable = to_bytes(FileScheme.scheme(), a.value()).ok();
+                stable.map(|bytes| hash_bytes(&bytes)) == files.get(path).and_then(|s| s.disk)
+            })
+            .map(|(e, a, _)| (e, a.name().to_string()))
+            .collect::<Vec<_>>();
+
+        for (entity, name) in resolved {
+            changed.remove(entity);
+            if let Some(path) = name.strip_prefix(FileScheme.scheme()) {
+                files.update(path, |state| state.loaded = state.disk);
+            }
+        }
+    }
+}
+
+impl Extension for FileWatcher {
+    fn configure_app_world(world: &mut World) {
+        ChangeDetection::configure_app_world(world);
+        ConflictDetection::configure_app_world(world);
+        world.register::<ChangedOnDisk>();
+        if !world.has_value::<WatchedFiles>() {
+            let files = WatchedFiles::default();
+            register_scheme(WatchedFileScheme {
+                files: files.clone(),
+            });
+            world.insert(files);
+        }
+    }
+
+    fn on_maintain(&'_ mut self, app_world: &mut World) {
+        if self.failed {
+            return;
+        }
+        if self.watcher.is_none() {
+            self.start();
+        }
+
+        // bases are updated before reloading, so that edits started before a reload are based on the old content
+        ConflictDetection.on_maintain(app_world);
+        let files = app_world.read_resource::<WatchedFiles>().clone();
+
+        let attributes_changed = {
+            let mut events = app_world.write_resource::<AttributeEvents>();
+            events.update(
+                &app_world.entities(),
+                &app_world.read_storage::<Attribute>(),
+            );
+
+            match self.subscription {
+                Some(subscription) => !events.drain(subscription).is_empty(),
+                None => {
+                    let filter =
+                        AttributeFilter::name(&format!("^{}", regex::escape(FileScheme.scheme())))
+                            .expect("escaped scheme is a valid regex");
+                    self.subscription = Some(events.subscribe(filter));
+                    true
+                }
+            }
+        };
+
+        let added = if attributes_changed {
+            self.track(app_world, &files)
+        } else {
+            BTreeSet::new()
+        };
+        for path in added.iter() {
+            self.reload(app_world, &files, path, true);
+        }
+
+        let mut changed = BTreeSet::new();
+
+        if let Some(events) = self.events.as_ref() {
+            for event in events.try_iter() {
+                match event {
+                    Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                        for path in event.paths.iter() {
+                            if let Some(paths) = self.paths.get(path) {
+                                changed.extend(paths.iter().cloned());
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => eprintln!("Error while watching files, {}", err),
+                }
+            }
+        }
+
+        if attributes_changed || !changed.is_empty() {
+            for path in changed.iter() {
+                self.reload(app_world, &files, path, false);
+            }
+            self.resolve(app_world, &files);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangedOnDisk;
+    use super::FileWatcher;
+    use super::WatchedFileScheme;
+    use super::WatchedFiles;
+    use crate::system::Attribute;
+    use crate::system::Extension;
+    use crate::system::SchemeError;
+    use crate::system::SchemeHandler;
+    use crate::system::Value;
+    use specs::Builder;
+    use specs::World;
+    use specs::WorldExt;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("atlier-watch-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn attributes_that_differ_from_their_file_are_marked() {
+        let dir = temp_dir("marked");
+        let same = dir.join("same.txt");
+        let different = dir.join("different.txt");
+        std::fs::write(&same, "text").unwrap();
+        std::fs::write(&different, "on disk").unwrap();
+
+        let mut world = World::new();
+        FileWatcher::configure_app_world(&mut world);
+        let attribute = |path: &std::path::Path| {
+            Attribute::new(
+                0,
+                format!("file::{}", path.display()),
+                Value::TextBuffer("text".to_string()),
+            )
+        };
+        let same = world.create_entity().with(attribute(&same)).build();
+        let different = world.create_entity().with(attribute(&different)).build();
+
+        let mut watcher = FileWatcher::default();
+        watcher.on_maintain(&mut world);
+        world.maintain();
+
+        let attributes = world.read_component::<Attribute>();
+        let changed = world.read_component::<ChangedOnDisk>();
+        assert!(!changed.contains(same));
+        assert!(changed.contains(different));
+        assert_eq!(
+            attributes.get(different).unwrap().value(),
+            &Value::TextBuffer("text".to_string()),
+            "the value is not overwritten by the file"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn files_changed_on_disk_are_not_overwritten() {
+        let dir = temp_dir("save");
+        let file = dir.join("file.txt");
+        let path = file.to_str().unwrap();
+        std::fs::write(&file, "loaded").unwrap();
+
+        let scheme = WatchedFileScheme {
+            files: WatchedFiles::default(),
+        };
+        scheme.load(path).unwrap();
+        std::fs::write(&file, "external").unwrap();
+
+        let edited = Value::TextBuffer("edited".to_string());
+        assert!(matches!(
+            scheme.save(path, &edited),
+            Err(SchemeError::ChangedOnDisk(p)) if p == path
+        ));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "external");
+
+        // once the new content is loaded, the file can be saved again
+        scheme.load(path).unwrap();
+        scheme.save(path, &edited).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "edited");
+        assert!(!scheme.files.get(path).unwrap().changed_on_disk());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn external_changes_reload_attributes_without_pending_changes() {
+        let dir = temp_dir("reload");
+        let file = dir.join("file.txt");
+        std::fs::write(&file, "before").unwrap();
+
+        let mut world = World::new();
+        FileWatcher::configure_app_world(&mut world);
+        let entity = world
+            .create_entity()
+            .with(Attribute::new(
+                0,
+                format!("file::{}", file.display()),
+                Value::TextBuffer("before".to_string()),
+            ))
+            .build();
+
+        let mut watcher = FileWatcher::default();
+        watcher.on_maintain(&mut world);
+        world.maintain();
+
+        std::fs::write(&file, "after").unwrap();
+        let start = Instant::now();
+        let value = loop {
+            watcher.on_maintain(&mut world);
+            world.maintain();
+
+            let value = world
+                .read_component::<Attribute>()
+                .get(entity)
+                .map(|a| a.value().clone());
+            if value != Some(Value::TextBuffer("before".to_string()))
+                || start.elapsed() > Duration::from_secs(5)
+            {
+                break value;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(value, Some(Value::TextBuffer("after".to_string())));
+        assert!(!world.read_component::<ChangedOnDisk>().contains(entity));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
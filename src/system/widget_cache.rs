@@ -0,0 +1,109 @@
+use imgui::Ui;
+use std::any::Any;
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::OnceLock;
+
+/// Entries that have not been used for this many frames are dropped
+pub(super) const EXPIRE_FRAMES: i32 = 600;
+
+/// State of widgets that are shown without access to the app world, e.g. from `App::edit_ui`, kept between frames
+///
+/// There is one cache for each type of state, entries are identified by a key that must be unique to the widget,
+/// and are dropped when they are not used for `EXPIRE_FRAMES` frames
+pub(super) struct WidgetCache<T> {
+    entries: BTreeMap<String, (T, i32)>,
+    frame: i32,
+}
+
+impl<T: Send + 'static> WidgetCache<T> {
+    /// Locks the cache for state of type `T`, and drops entries that expired
+    ///
+    /// Caches of different types can be locked at the same time, e.g. by a widget that shows another widget
+    pub(super) fn lock(ui: &Ui) -> MutexGuard<'static, Self> {
+        static CACHES: OnceLock<Mutex<HashMap<TypeId, &'static (dyn Any + Send + Sync)>>> =
+            OnceLock::new();
+
+        let cache = *CACHES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                // one cache per type of state, for the lifetime of the process
+                Box::leak(Box::new(Mutex::new(Self {
+                    entries: BTreeMap::new(),
+                    frame: 0,
+                })))
+            });
+
+        let mut cache = cache
+            .downcast_ref::<Mutex<Self>>()
+            .expect("caches are stored by the type of their state")
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        cache.expire(ui.frame_count());
+        cache
+    }
+
+    /// returns the state for `key`, `init` is called if there is none
+    pub(super) fn get_or_insert_with(&mut self, key: &str, init: impl FnOnce() -> T) -> &mut T {
+        let frame = self.frame;
+        let (state, used) = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| (init(), frame));
+        *used = frame;
+        state
+    }
+
+    /// returns the state for `key`, if there is any
+    pub(super) fn get_mut(&mut self, key: &str) -> Option<&mut T> {
+        let frame = self.frame;
+        self.entries.get_mut(key).map(|(state, used)| {
+            *used = frame;
+            state
+        })
+    }
+
+    /// drops the state for `key`
+    pub(super) fn remove(&mut self, key: &str) -> Option<T> {
+        self.entries.remove(key).map(|(state, _)| state)
+    }
+
+    fn expire(&mut self, frame: i32) {
+        if self.frame != frame {
+            self.frame = frame;
+            self.entries
+                .retain(|_, (_, used)| frame - *used < EXPIRE_FRAMES);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WidgetCache;
+    use super::EXPIRE_FRAMES;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn unused_entries_expire() {
+        let mut cache = WidgetCache {
+            entries: BTreeMap::new(),
+            frame: 0,
+        };
+        *cache.get_or_insert_with("used", || 0) += 1;
+        cache.get_or_insert_with("unused", || 0);
+
+        cache.expire(EXPIRE_FRAMES - 1);
+        assert_eq!(cache.get_mut("used"), Some(&mut 1));
+
+        cache.expire(EXPIRE_FRAMES);
+        assert_eq!(cache.get_mut("used"), Some(&mut 1));
+        assert_eq!(cache.get_mut("unused"), None);
+        assert_eq!(cache.remove("used"), Some(1));
+    }
+}
@@ -13,6 +13,7 @@ pub struct WindowContext {
     pub font_size: Option<f32>,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for WindowContext {
     fn default() -> Self {
         WindowContext{
@@ -28,6 +29,7 @@ impl Default for WindowContext {
 }
 
 impl WindowContext {
+    #[allow(clippy::needless_borrow)]
     pub fn new(title: &str, width: f64, height: f64) -> Self {
         let event_loop = EventLoop::new();
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
@@ -72,6 +74,7 @@ pub struct Hardware {
 }
 
 impl From<WindowContext> for Hardware {
+    #[allow(clippy::needless_return)]
     fn from(context: WindowContext) -> Self {
         let hardware = move || {
             if let (Some(instance), Some(surface), Some(physical_size)) = (context.instance, context.surface, context.physical_size) {
@@ -117,6 +120,7 @@ impl From<WindowContext> for Hardware {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Hardware {
     fn default() -> Self {
         Self {
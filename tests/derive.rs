@@ -0,0 +1,168 @@
+use atlier::imgui;
+use atlier::system::find_field;
+use atlier::system::App;
+use atlier::system::Attribute;
+use atlier::system::Attributes;
+use atlier::system::ConversionError;
+use atlier::system::Value;
+
+#[derive(Attributes, Clone, Debug, PartialEq)]
+#[attributes(app)]
+struct Settings {
+    #[attributes(label = "Enabled")]
+    enabled: bool,
+    #[attributes(read_only)]
+    locked: bool,
+    #[attributes(min = 0, max = 10)]
+    count: i32,
+    #[attributes(hidden)]
+    secret: String,
+    #[attributes(min = -1.0, max = 1.0)]
+    scale: f32,
+}
+
+fn settings() -> Settings {
+    Settings {
+        enabled: false,
+        locked: false,
+        count: 5,
+        secret: "hunter2".to_string(),
+        scale: 0.5,
+    }
+}
+
+#[test]
+fn fields_map_to_attributes() {
+    let attributes = settings().to_attributes(7);
+
+    assert_eq!(
+        attributes,
+        vec![
+            Attribute::new(7, "enabled", Value::Bool(false)),
+            Attribute::new(7, "locked", Value::Bool(false)),
+            Attribute::new(7, "count", Value::Int(5)),
+            Attribute::new(7, "secret", Value::TextBuffer("hunter2".to_string())),
+            Attribute::new(7, "scale", Value::Float(0.5)),
+        ]
+    );
+    assert_eq!(find_field(&attributes, "count"), Ok(&Value::Int(5)));
+    assert_eq!(Settings::from_attributes(&attributes), Ok(settings()));
+}
+
+#[test]
+fn missing_and_mismatched_attributes_are_errors() {
+    let mut attributes = settings().to_attributes(7);
+    attributes.retain(|a| a.name() != "scale");
+
+    let missing = ConversionError::Missing {
+        name: "scale".to_string(),
+    };
+    assert_eq!(find_field(&attributes, "scale"), Err(missing.clone()));
+    assert_eq!(Settings::from_attributes(&attributes), Err(missing));
+
+    attributes.push(Attribute::new(7, "scale", Value::Int(1)));
+    assert_eq!(
+        Settings::from_attributes(&attributes),
+        Err(ConversionError::Mismatch {
+            expected: atlier::system::ValueKind::Float,
+            found: atlier::system::ValueKind::Int,
+        })
+    );
+}
+
+#[test]
+fn app_is_named_after_the_struct() {
+    assert_eq!(<Settings as App>::name(), "Settings");
+}
+
+/// Runs frames of `edit_fields` in a window without a renderer,
+/// clicking the left end of the visible field at `line` with the mouse
+struct Headless {
+    imgui: imgui::Context,
+    /// top left of the fields, and the height of each line
+    layout: Option<([f32; 2], f32)>,
+}
+
+impl Headless {
+    fn new() -> Self {
+        let mut imgui = imgui::Context::create();
+        imgui.set_ini_filename(None);
+        imgui.io_mut().display_size = [800.0, 600.0];
+        imgui.io_mut().delta_time = 1.0 / 60.0;
+        imgui.fonts().build_rgba32_texture();
+
+        Self {
+            imgui,
+            layout: None,
+        }
+    }
+
+    /// returns the height of the fields that were shown
+    fn frame(&mut self, settings: &mut Settings, mouse: Option<(usize, bool)>) -> f32 {
+        let position = match (self.layout, mouse) {
+            (Some(([x, y], line_height)), Some((line, _))) => {
+                [x + 4.0, y + line as f32 * line_height + line_height / 2.0]
+            }
+            _ => [-1.0, -1.0],
+        };
+        self.imgui.io_mut().mouse_pos = position;
+        self.imgui.io_mut().mouse_down[0] = matches!(mouse, Some((_, true)));
+
+        let ui = self.imgui.frame();
+        let mut height = 0.0;
+        let mut layout = None;
+        imgui::Window::new("settings")
+            .position([0.0, 0.0], imgui::Condition::Always)
+            .size([800.0, 600.0], imgui::Condition::Always)
+            .build(&ui, || {
+                let start = ui.cursor_screen_pos();
+                settings.edit_fields(&ui);
+                height = ui.cursor_screen_pos()[1] - start[1];
+                layout = Some((start, ui.frame_height_with_spacing()));
+            });
+        ui.render();
+
+        self.layout = layout;
+        height
+    }
+
+    fn click(&mut self, settings: &mut Settings, line: usize) {
+        self.frame(settings, Some((line, false)));
+        self.frame(settings, Some((line, true)));
+        self.frame(settings, Some((line, false)));
+        self.frame(settings, None);
+    }
+}
+
+#[test]
+fn edit_fields_follow_field_options() {
+    let mut headless = Headless::new();
+    let mut edited = settings();
+
+    headless.frame(&mut edited, None);
+    let height = headless.frame(&mut edited, None);
+    let line_height = headless.layout.expect("fields were shown").1;
+    assert_eq!(height, 4.0 * line_height, "the hidden field is not shown");
+    assert_eq!(
+        edited,
+        settings(),
+        "showing the fields does not change them"
+    );
+
+    headless.click(&mut edited, 0);
+    assert!(edited.enabled, "clicking a checkbox toggles its field");
+
+    headless.click(&mut edited, 1);
+    assert!(!edited.locked, "read-only fields can't be changed");
+
+    headless.click(&mut edited, 2);
+    assert_eq!(edited.count, 0, "the slider starts at the min of the field");
+
+    headless.click(&mut edited, 3);
+    assert_eq!(
+        edited.scale, -1.0,
+        "the slider starts at the min of the field"
+    );
+
+    assert_eq!(edited.secret, "hunter2");
+}